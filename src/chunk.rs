@@ -1,20 +1,61 @@
-use crate::nbt::{NBTError, NBTReader, NBTValue};
+use crate::nbt::{get_path, NBTError, NBTReader, NBTValue};
 use byteorder::{BigEndian, ReadBytesExt};
 
-use crate::constants::tags::{LEVEL_TAGS, TAG_LEVEL, TAG_X_POS, TAG_Z_POS};
-use crate::region_file::BLOCK_SIZE;
-use flate2::read::{GzDecoder, ZlibDecoder};
+use crate::constants::tags::{
+    MCREGION_TAGS, TAG_BIOMES, TAG_BLOCK_ENTITIES, TAG_BLOCK_PALETTE, TAG_BLOCK_STATES,
+    TAG_BLOCK_STATES_DATA, TAG_BLOCK_STATES_PALETTE, TAG_BLOCK_X, TAG_BLOCK_Y, TAG_BLOCK_Z,
+    TAG_DATA, TAG_DATA_VERSION, TAG_ENTITIES, TAG_HEIGHTMAPS, TAG_ID, TAG_INHABITED_TIME,
+    TAG_LAST_UPDATE, TAG_LEVEL, TAG_NAME, TAG_PALETTE, TAG_POS, TAG_POSITION, TAG_REFERENCES,
+    TAG_ROOT_BIOMES, TAG_ROOT_SECTIONS, TAG_ROOT_STARTS, TAG_ROOT_STRUCTURES, TAG_SECTIONS,
+    TAG_STARTS, TAG_STATUS, TAG_STRUCTURES, TAG_TILE_ENTITIES, TAG_X_POS, TAG_Y, TAG_Z_POS,
+};
+use crate::constants::versions::{
+    ChunkFormat, BIOME_LENGTHS, CHUNK_FORMATS, FLAT_CHUNK_FORMAT, HEIGHTMAP_LENGTHS,
+    SECTION_Y_RANGES, UNSPANNED_BLOCK_STATES,
+};
+use crate::lz4::{compress_lz4_blocks, LZ4BlockReader, LZ4_BLOCK_MAGIC};
+use crate::region_file::{RegionFolder, RegionFormat, BLOCK_SIZE};
+use crate::utils::{ByteArrayCache, LimitedReader, SizeLimitExceeded};
+use flate2::read::{GzDecoder, GzEncoder, ZlibDecoder};
+use flate2::Compression;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
-use std::io::{self, BufReader, Error};
+use std::io::{self, BufReader, Error, Read, SeekFrom};
 
 type IOResult<T> = io::Result<T>;
 
+/// The maximum distance in chunks of a chunk referencing a structure to the chunk
+/// the structure starts in. Even the largest structures span less than this
+const MAX_STRUCTURE_REFERENCE_DISTANCE: i64 = 32;
+
+/// Flag in the compression type marking that the chunk data is stored in an external .mcc file
+pub const EXTERNAL_CHUNK_FLAG: u8 = 0x80;
+
 #[derive(Debug)]
 pub struct Chunk {
     pub length: u32,
     pub compression_type: u8,
     pub x_pos: Option<i32>,
     pub z_pos: Option<i32>,
+    pub inhabited_time: Option<i64>,
+    pub last_update: Option<i64>,
+    pub data_version: Option<i32>,
+    /// The data version of the world used for chunks that don't store their own
+    pub world_data_version: Option<i32>,
+    pub status: Option<String>,
+    pub entities: Option<u64>,
+    pub block_entities: Option<u64>,
+    pub entity_ids: Vec<String>,
+    /// The distinct namespaces of the blocks, block entities and entities of the chunk
+    pub namespaces: Vec<String>,
+    pub misplaced_entities: u64,
+    pub misplaced_tile_entities: u64,
+    pub duplicate_tile_entities: u64,
+    pub invalid_heightmaps: u64,
+    pub invalid_structures: bool,
+    pub invalid_biomes: bool,
+    pub invalid_sections: bool,
+    pub invalid_block_states: bool,
 }
 
 impl Chunk {
@@ -30,52 +71,773 @@ impl Chunk {
             length,
             x_pos: None,
             z_pos: None,
+            inhabited_time: None,
+            last_update: None,
+            data_version: None,
+            world_data_version: None,
+            status: None,
+            entities: None,
+            block_entities: None,
+            entity_ids: Vec::new(),
+            namespaces: Vec::new(),
+            misplaced_entities: 0,
+            misplaced_tile_entities: 0,
+            duplicate_tile_entities: 0,
+            invalid_heightmaps: 0,
+            invalid_structures: false,
+            invalid_biomes: false,
+            invalid_sections: false,
+            invalid_block_states: false,
         })
     }
 
+    /// Returns if the chunk data is stored in an external .mcc file
+    pub fn is_external(&self) -> bool {
+        self.compression_type & EXTERNAL_CHUNK_FLAG != 0
+    }
+
+    /// Returns the compression type without the external file flag
+    pub fn compression_method(&self) -> u8 {
+        self.compression_type & !EXTERNAL_CHUNK_FLAG
+    }
+
+    /// Decompresses and parses the nbt data of the chunk.
+    /// If the stored compression type is implausible the codec is guessed from the data.
+    /// Fails if the decompressed data is larger than `size_limit` bytes
+    /// or the nbt data is nested deeper than `max_depth`
+    pub fn read_nbt_data<R: io::Read + io::Seek>(
+        &self,
+        reader: &mut R,
+        size_limit: u64,
+        max_depth: u64,
+    ) -> Result<HashMap<String, NBTValue>, ChunkScanError> {
+        let compression_type = if is_valid_compression_method(self.compression_method()) {
+            self.compression_method()
+        } else {
+            sniff_compression_type(reader)?.unwrap_or(self.compression_method())
+        };
+
+        decode_nbt_data(reader, compression_type, size_limit, max_depth)
+    }
+
+    /// Validates the nbt data of the chunk depending on the folder the region file is stored in
+    /// and the format of the region file. Custom required tags replace the ones of the format
     pub fn validate_nbt_data<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
+        folder: RegionFolder,
+        format: RegionFormat,
+        size_limit: u64,
+        max_depth: u64,
+        custom_tags: Option<&[String]>,
     ) -> Result<(), ChunkScanError> {
-        let data = if self.compression_type == 1 {
-            let mut nbt_reader = NBTReader::new(BufReader::new(GzDecoder::new(reader)));
-            nbt_reader.parse()?
-        } else if self.compression_type == 2 {
-            let mut nbt_reader = NBTReader::new(BufReader::new(ZlibDecoder::new(reader)));
-            nbt_reader.parse()?
-        } else {
-            let mut nbt_reader = NBTReader::new(reader);
-            nbt_reader.parse()?
-        };
+        // guessing the codec of legacy chunks could silently parse garbage
+        if format == RegionFormat::McRegion && !matches!(self.compression_method(), 1 | 2) {
+            return Err(ChunkScanError::String(format!(
+                "Compression type {} isn't supported by McRegion files",
+                self.compression_type
+            )));
+        }
+        let data = self.read_nbt_data(reader, size_limit, max_depth)?;
 
-        if !data.contains_key(TAG_LEVEL) {
-            Err(ChunkScanError::MissingTag(TAG_LEVEL))
-        } else {
-            let lvl_data = &data[TAG_LEVEL];
+        match folder {
+            RegionFolder::Region if format == RegionFormat::McRegion => {
+                self.validate_level_data(&data, MCREGION_TAGS, custom_tags)
+            }
+            RegionFolder::Region => {
+                // chunks without a data version can be older than 1.9 if they're nested
+                // in a level tag. Only the other ones take the data version of the world
+                self.data_version = get_path(&data, TAG_DATA_VERSION)
+                    .and_then(|v| v.as_int())
+                    .cloned()
+                    .or_else(|| {
+                        self.world_data_version
+                            .filter(|_| !data.contains_key(TAG_LEVEL))
+                    });
+                let format = chunk_format(self.data_version);
+
+                if format.nested_in_level {
+                    self.validate_level_data(&data, format.required_tags, custom_tags)
+                } else {
+                    self.validate_chunk_tags(&data, format.required_tags, custom_tags)
+                }
+            }
+            RegionFolder::Entities => self.validate_entities_data(&data),
+            // poi chunks only store sections without position information
+            RegionFolder::Poi => Ok(()),
+        }
+    }
+
+    /// Validates that the level tag of a region chunk contains all required tags
+    fn validate_level_data(
+        &mut self,
+        data: &HashMap<String, NBTValue>,
+        required_tags: &[&'static str],
+        custom_tags: Option<&[String]>,
+    ) -> Result<(), ChunkScanError> {
+        let lvl_data = get_path(data, TAG_LEVEL)
+            .ok_or(ChunkScanError::MissingTag(TAG_LEVEL))?
+            .as_compound()
+            .ok_or(ChunkScanError::InvalidFormat(TAG_LEVEL))?;
+
+        self.validate_chunk_tags(lvl_data, required_tags, custom_tags)
+    }
 
-            if let NBTValue::Compound(lvl_data) = lvl_data {
-                for tag in LEVEL_TAGS {
-                    if !lvl_data.contains_key(*tag) {
+    /// Validates that the chunk data contains all required tags and reads its position
+    fn validate_chunk_tags(
+        &mut self,
+        data: &HashMap<String, NBTValue>,
+        required_tags: &[&'static str],
+        custom_tags: Option<&[String]>,
+    ) -> Result<(), ChunkScanError> {
+        match custom_tags {
+            Some(custom_tags) => {
+                if let Some(tag) = custom_tags.iter().find(|tag| !data.contains_key(*tag)) {
+                    return Err(ChunkScanError::String(format!(
+                        "Missing Tag in NBT Data: {}",
+                        tag
+                    )));
+                }
+            }
+            None => {
+                for tag in required_tags {
+                    if !data.contains_key(*tag) {
                         return Err(ChunkScanError::MissingTag(tag));
                     }
                 }
-                self.x_pos = lvl_data[TAG_X_POS].as_int().cloned();
-                self.z_pos = lvl_data[TAG_Z_POS].as_int().cloned();
+            }
+        }
+        self.x_pos = get_path(data, TAG_X_POS).and_then(|v| v.as_int()).cloned();
+        self.z_pos = get_path(data, TAG_Z_POS).and_then(|v| v.as_int()).cloned();
+        self.inhabited_time = get_path(data, TAG_INHABITED_TIME)
+            .and_then(|v| v.as_long())
+            .cloned();
+        self.last_update = get_path(data, TAG_LAST_UPDATE)
+            .and_then(|v| v.as_long())
+            .cloned();
+        self.status = get_path(data, TAG_STATUS)
+            .and_then(|v| v.as_string())
+            .cloned();
+        // chunks in the 1.18+ format store their entities in the entities folder
+        self.entities = list_length(data, TAG_ENTITIES);
+        self.entity_ids = entity_ids(data);
+        self.namespaces = namespaces(data);
+        self.misplaced_entities = count_misplaced_entities(data, self.x_pos, self.z_pos);
+        self.block_entities =
+            list_length(data, TAG_TILE_ENTITIES).or_else(|| list_length(data, TAG_BLOCK_ENTITIES));
+        let (misplaced, duplicates) = count_invalid_tile_entities(data, self.x_pos, self.z_pos);
+        self.misplaced_tile_entities = misplaced;
+        self.duplicate_tile_entities = duplicates;
+        self.invalid_heightmaps = count_invalid_heightmaps(data, self.data_version);
+        self.invalid_structures = has_invalid_structures(data, self.x_pos, self.z_pos);
+        self.invalid_biomes = has_invalid_biomes(data, self.data_version);
+        self.invalid_sections = has_invalid_sections(data, self.data_version);
+        self.invalid_block_states = has_invalid_block_states(data, self.data_version);
+
+        Ok(())
+    }
+
+    /// Validates the position of an entities chunk which is stored as an int array of x and z
+    fn validate_entities_data(
+        &mut self,
+        data: &HashMap<String, NBTValue>,
+    ) -> Result<(), ChunkScanError> {
+        let position = get_path(data, TAG_POSITION)
+            .ok_or(ChunkScanError::MissingTag(TAG_POSITION))?
+            .as_int_array()
+            .filter(|p| p.len() == 2)
+            .ok_or(ChunkScanError::InvalidFormat(TAG_POSITION))?;
+        self.x_pos = Some(position[0]);
+        self.z_pos = Some(position[1]);
+        self.entities = list_length(data, TAG_ENTITIES);
+        self.entity_ids = entity_ids(data);
+        self.namespaces = namespaces(data);
+        self.misplaced_entities = count_misplaced_entities(data, self.x_pos, self.z_pos);
+
+        Ok(())
+    }
+}
+
+/// Returns the number of items of the list stored in the tag
+fn list_length(data: &HashMap<String, NBTValue>, tag: &str) -> Option<u64> {
+    get_path(data, tag)
+        .and_then(|v| v.as_list())
+        .map(|list| list.len() as u64)
+}
+
+/// Returns the ids of the entities stored in the entities tag
+fn entity_ids(data: &HashMap<String, NBTValue>) -> Vec<String> {
+    get_path(data, TAG_ENTITIES)
+        .and_then(|v| v.as_list())
+        .map(|entities| {
+            entities
+                .iter()
+                .filter_map(|entity| get_path(entity.as_compound()?, TAG_ID)?.as_string())
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the distinct namespaces of the block states in the palettes of the sections
+/// and of the ids of the block entities and entities
+fn namespaces(data: &HashMap<String, NBTValue>) -> Vec<String> {
+    let mut namespaces = HashSet::new();
+    let sections = get_path(data, TAG_SECTIONS)
+        .or_else(|| get_path(data, TAG_ROOT_SECTIONS))
+        .and_then(|v| v.as_list());
+    let palettes = sections
+        .into_iter()
+        .flatten()
+        .filter_map(|section| section.as_compound())
+        .filter_map(|section| {
+            get_path(section, TAG_BLOCK_STATES_PALETTE)
+                .or_else(|| get_path(section, TAG_BLOCK_PALETTE))
+                .and_then(|v| v.as_list())
+        });
+    for palette in palettes {
+        let names = palette
+            .iter()
+            .filter_map(|state| get_path(state.as_compound()?, TAG_NAME)?.as_string());
+        namespaces.extend(names.map(|name| namespace(name)));
+    }
+    for tag in &[TAG_ENTITIES, TAG_TILE_ENTITIES, TAG_BLOCK_ENTITIES] {
+        let ids = get_path(data, tag)
+            .and_then(|v| v.as_list())
+            .into_iter()
+            .flatten()
+            .filter_map(|entity| get_path(entity.as_compound()?, TAG_ID)?.as_string());
+        namespaces.extend(ids.map(|id| namespace(id)));
+    }
+
+    namespaces.into_iter().map(String::from).collect()
+}
+
+/// Returns the namespace of a resource location. Locations without one belong to minecraft
+fn namespace(location: &str) -> &str {
+    location
+        .split_once(':')
+        .map(|(namespace, _)| namespace)
+        .unwrap_or("minecraft")
+}
+
+/// Returns the number of heightmaps that aren't long arrays of the length
+/// expected for the data version. Chunks don't need to contain all heightmaps
+fn count_invalid_heightmaps(data: &HashMap<String, NBTValue>, data_version: Option<i32>) -> u64 {
+    let heightmaps = match get_path(data, TAG_HEIGHTMAPS).and_then(|v| v.as_compound()) {
+        Some(heightmaps) => heightmaps,
+        None => return 0,
+    };
+    let data_version = data_version.unwrap_or(i32::MIN);
+    let expected_length = HEIGHTMAP_LENGTHS
+        .iter()
+        .rev()
+        .find(|(min_data_version, _)| *min_data_version <= data_version)
+        .map(|(_, length)| *length)
+        .unwrap_or(HEIGHTMAP_LENGTHS[0].1);
+
+    heightmaps
+        .values()
+        .filter(|heightmap| {
+            heightmap
+                .as_long_array()
+                .map(|longs| longs.len() != expected_length)
+                .unwrap_or(true)
+        })
+        .count() as u64
+}
+
+/// Returns if the biomes don't have the shape expected for the data version. Older chunks
+/// store an array with an entry for each column or cube while chunks in the 1.18+ format
+/// store a palette in each section. Chunks that don't contain biomes yet are valid
+fn has_invalid_biomes(data: &HashMap<String, NBTValue>, data_version: Option<i32>) -> bool {
+    let data_version = data_version.unwrap_or(i32::MIN);
+    if data_version >= FLAT_CHUNK_FORMAT {
+        return get_path(data, TAG_ROOT_SECTIONS)
+            .and_then(|v| v.as_list())
+            .map(|sections| {
+                sections
+                    .iter()
+                    .filter_map(|section| section.as_compound())
+                    .filter_map(|section| get_path(section, TAG_ROOT_BIOMES))
+                    .any(|biomes| !is_valid_paletted_container(biomes))
+            })
+            .unwrap_or(false);
+    }
+    let expected_length = BIOME_LENGTHS
+        .iter()
+        .rev()
+        .find(|(min_data_version, _)| *min_data_version <= data_version)
+        .map(|(_, length)| *length)
+        .unwrap_or(BIOME_LENGTHS[0].1);
+
+    match get_path(data, TAG_BIOMES) {
+        Some(NBTValue::IntArray(biomes)) => biomes.len() != expected_length,
+        // chunks of versions before 1.13 store the biome ids as bytes
+        Some(NBTValue::ByteArray(biomes)) => biomes.len() != BIOME_LENGTHS[0].1,
+        Some(_) => true,
+        None => false,
+    }
+}
+
+/// Returns if any section doesn't have a y index, has the index of another section or one
+/// outside of the range of the data version. Sections may be missing since older versions
+/// only store sections containing blocks
+fn has_invalid_sections(data: &HashMap<String, NBTValue>, data_version: Option<i32>) -> bool {
+    let sections = match get_path(data, TAG_SECTIONS)
+        .or_else(|| get_path(data, TAG_ROOT_SECTIONS))
+        .and_then(|v| v.as_list())
+    {
+        Some(sections) => sections,
+        None => return false,
+    };
+    let data_version = data_version.unwrap_or(i32::MIN);
+    let (min_y, max_y) = SECTION_Y_RANGES
+        .iter()
+        .rev()
+        .find(|(min_data_version, _)| *min_data_version <= data_version)
+        .map(|(_, range)| *range)
+        .unwrap_or(SECTION_Y_RANGES[0].1);
+    let mut indices = HashSet::new();
+
+    sections.iter().any(|section| {
+        match section.as_compound().and_then(|s| get_path(s, TAG_Y)) {
+            // the index is a signed byte
+            Some(NBTValue::Byte(y)) => {
+                let y = *y as i8;
+                y < min_y || y > max_y || !indices.insert(y)
+            }
+            _ => true,
+        }
+    })
+}
+
+/// Returns if the packed block states of any section don't have the length
+/// required by the size of its palette
+fn has_invalid_block_states(data: &HashMap<String, NBTValue>, data_version: Option<i32>) -> bool {
+    let sections = match get_path(data, TAG_SECTIONS)
+        .or_else(|| get_path(data, TAG_ROOT_SECTIONS))
+        .and_then(|v| v.as_list())
+    {
+        Some(sections) => sections,
+        None => return false,
+    };
+    let spanned = data_version.unwrap_or(i32::MIN) < UNSPANNED_BLOCK_STATES;
+
+    sections
+        .iter()
+        .filter_map(|section| section.as_compound())
+        .any(|section| {
+            let (palette, block_states) = match get_path(section, TAG_BLOCK_STATES_PALETTE) {
+                Some(palette) => (palette, get_path(section, TAG_BLOCK_STATES_DATA)),
+                None => match get_path(section, TAG_BLOCK_PALETTE) {
+                    Some(palette) => (palette, get_path(section, TAG_BLOCK_STATES)),
+                    // sections of versions before 1.13 store block ids instead
+                    None => return false,
+                },
+            };
+            let palette_length = match palette.as_list() {
+                Some(palette) if !palette.is_empty() => palette.len(),
+                _ => return true,
+            };
 
-                Ok(())
-            } else {
-                Err(ChunkScanError::InvalidFormat(TAG_LEVEL))
+            match block_states {
+                Some(block_states) => block_states
+                    .as_long_array()
+                    .map(|block_states| {
+                        block_states.len() != packed_block_states_length(palette_length, spanned)
+                    })
+                    .unwrap_or(true),
+                // a section made of a single block state doesn't need to store indices
+                None => palette_length > 1,
             }
+        })
+}
+
+/// Returns the number of longs storing the 4096 block states of a section with
+/// at least 4 bits per entry. Spanned entries can be split across two longs
+fn packed_block_states_length(palette_length: usize, spanned: bool) -> usize {
+    let bits = (usize::BITS - (palette_length - 1).leading_zeros()).max(4) as usize;
+
+    if spanned {
+        (4096 * bits).div_ceil(64)
+    } else {
+        4096usize.div_ceil(64 / bits)
+    }
+}
+
+/// Returns if the value is a compound with a non-empty palette and optional packed data
+fn is_valid_paletted_container(value: &NBTValue) -> bool {
+    let container = match value.as_compound() {
+        Some(container) => container,
+        None => return false,
+    };
+    let has_palette = get_path(container, TAG_PALETTE)
+        .and_then(|v| v.as_list())
+        .map(|palette| !palette.is_empty())
+        .unwrap_or(false);
+    let has_valid_data = get_path(container, TAG_DATA)
+        .map(|v| v.as_long_array().is_some())
+        .unwrap_or(true);
+
+    has_palette && has_valid_data
+}
+
+/// Returns if the structures tag isn't made of a compound of structure starts and a compound
+/// of long arrays referencing chunks close to the chunk at the given coordinates.
+/// Chunks without structures are valid since older versions don't always store them
+fn has_invalid_structures(
+    data: &HashMap<String, NBTValue>,
+    x: Option<i32>,
+    z: Option<i32>,
+) -> bool {
+    let structures =
+        match get_path(data, TAG_STRUCTURES).or_else(|| get_path(data, TAG_ROOT_STRUCTURES)) {
+            Some(structures) => structures,
+            None => return false,
+        };
+    let structures = match structures.as_compound() {
+        Some(structures) => structures,
+        None => return true,
+    };
+    let starts = get_path(structures, TAG_STARTS).or_else(|| get_path(structures, TAG_ROOT_STARTS));
+    let valid_starts = starts
+        .and_then(|starts| starts.as_compound())
+        .map(|starts| starts.values().all(|start| start.as_compound().is_some()))
+        .unwrap_or(false);
+    let valid_references = get_path(structures, TAG_REFERENCES)
+        .and_then(|references| references.as_compound())
+        .map(|references| {
+            references
+                .values()
+                .all(|chunks| match chunks.as_long_array() {
+                    Some(chunks) => chunks.iter().all(|chunk| is_nearby_reference(*chunk, x, z)),
+                    None => false,
+                })
+        })
+        .unwrap_or(false);
+
+    !valid_starts || !valid_references
+}
+
+/// Returns if the chunk position packed into the long with x in the lower and z in the
+/// upper 32 bits lies close enough to the chunk at the given coordinates to be referenced
+fn is_nearby_reference(reference: i64, x: Option<i32>, z: Option<i32>) -> bool {
+    let (x, z) = match (x, z) {
+        (Some(x), Some(z)) => (x, z),
+        _ => return true,
+    };
+    let reference_x = reference as i32;
+    let reference_z = (reference >> 32) as i32;
+
+    (reference_x as i64 - x as i64).abs() <= MAX_STRUCTURE_REFERENCE_DISTANCE
+        && (reference_z as i64 - z as i64).abs() <= MAX_STRUCTURE_REFERENCE_DISTANCE
+}
+
+/// Returns the number of entities positioned outside of the chunk at the given coordinates
+fn count_misplaced_entities(
+    data: &HashMap<String, NBTValue>,
+    x: Option<i32>,
+    z: Option<i32>,
+) -> u64 {
+    let (x, z) = match (x, z) {
+        (Some(x), Some(z)) => (x, z),
+        _ => return 0,
+    };
+
+    get_path(data, TAG_ENTITIES)
+        .and_then(|v| v.as_list())
+        .map(|entities| {
+            entities
+                .iter()
+                .filter(|entity| is_misplaced_entity(entity, x, z))
+                .count() as u64
+        })
+        .unwrap_or(0)
+}
+
+/// Returns if the `Pos` of the entity lies outside of the chunk at the given coordinates.
+/// Entities without a valid position are never considered misplaced
+fn is_misplaced_entity(entity: &NBTValue, x: i32, z: i32) -> bool {
+    let position = entity
+        .as_compound()
+        .and_then(|entity| get_path(entity, TAG_POS))
+        .and_then(|v| v.as_list());
+
+    match position.map(|p| p.as_slice()) {
+        Some([NBTValue::Double(pos_x), _, NBTValue::Double(pos_z)]) => {
+            (pos_x / 16.0).floor() as i32 != x || (pos_z / 16.0).floor() as i32 != z
         }
+        _ => false,
+    }
+}
+
+/// Removes the entities positioned outside of the chunk they're stored in from
+/// the nbt data of a region or entities chunk. Returns the number of removed entities
+pub(crate) fn remove_misplaced_entities(data: &mut HashMap<String, NBTValue>) -> u64 {
+    match data.get_mut(TAG_LEVEL).and_then(|v| v.as_compound_mut()) {
+        Some(level) => retain_placed_entities(level),
+        None => retain_placed_entities(data),
     }
 }
 
+/// Removes the misplaced entities from the entities list stored next to the chunk position
+fn retain_placed_entities(data: &mut HashMap<String, NBTValue>) -> u64 {
+    let (x, z) = match chunk_position(data) {
+        Some(position) => position,
+        None => return 0,
+    };
+
+    match data.get_mut(TAG_ENTITIES).and_then(|v| v.as_list_mut()) {
+        Some(entities) => {
+            let length = entities.len();
+            entities.retain(|entity| !is_misplaced_entity(entity, x, z));
+
+            (length - entities.len()) as u64
+        }
+        None => 0,
+    }
+}
+
+/// Returns the number of tile entities positioned outside of the chunk at the given
+/// coordinates and the number of tile entities at the position of a previous one
+fn count_invalid_tile_entities(
+    data: &HashMap<String, NBTValue>,
+    x: Option<i32>,
+    z: Option<i32>,
+) -> (u64, u64) {
+    let (x, z) = match (x, z) {
+        (Some(x), Some(z)) => (x, z),
+        _ => return (0, 0),
+    };
+    let tile_entities = match get_path(data, TAG_TILE_ENTITIES)
+        .or_else(|| get_path(data, TAG_BLOCK_ENTITIES))
+        .and_then(|v| v.as_list())
+    {
+        Some(tile_entities) => tile_entities,
+        None => return (0, 0),
+    };
+    let mut positions = HashSet::new();
+    let mut misplaced = 0;
+    let mut duplicates = 0;
+
+    for position in tile_entities.iter().filter_map(tile_entity_position) {
+        if position.0.div_euclid(16) != x || position.2.div_euclid(16) != z {
+            misplaced += 1;
+        }
+        if !positions.insert(position) {
+            duplicates += 1;
+        }
+    }
+
+    (misplaced, duplicates)
+}
+
+/// Returns the block position of a tile entity or `None` if it doesn't have a valid one
+fn tile_entity_position(tile_entity: &NBTValue) -> Option<(i32, i32, i32)> {
+    let tile_entity = tile_entity.as_compound()?;
+    let coordinate = |tag| tile_entity.get(tag).and_then(|v| v.as_int()).cloned();
+
+    Some((
+        coordinate(TAG_BLOCK_X)?,
+        coordinate(TAG_BLOCK_Y)?,
+        coordinate(TAG_BLOCK_Z)?,
+    ))
+}
+
+/// Removes all but the first tile entity at each position from the nbt data of a chunk.
+/// Returns the number of removed tile entities
+pub(crate) fn remove_duplicate_tile_entities(data: &mut HashMap<String, NBTValue>) -> u64 {
+    let data = match data.get_mut(TAG_LEVEL).and_then(|v| v.as_compound_mut()) {
+        Some(level) => level,
+        None => data,
+    };
+    let tag = if data.contains_key(TAG_TILE_ENTITIES) {
+        TAG_TILE_ENTITIES
+    } else {
+        TAG_BLOCK_ENTITIES
+    };
+
+    match data.get_mut(tag).and_then(|v| v.as_list_mut()) {
+        Some(tile_entities) => {
+            let length = tile_entities.len();
+            let mut positions = HashSet::new();
+            tile_entities.retain(|tile_entity| match tile_entity_position(tile_entity) {
+                Some(position) => positions.insert(position),
+                None => true,
+            });
+
+            (length - tile_entities.len()) as u64
+        }
+        None => 0,
+    }
+}
+
+/// Returns the chunk coordinates stored in the xPos and zPos tags
+/// or the position array of entities chunks
+fn chunk_position(data: &HashMap<String, NBTValue>) -> Option<(i32, i32)> {
+    let x = get_path(data, TAG_X_POS).and_then(|v| v.as_int());
+    let z = get_path(data, TAG_Z_POS).and_then(|v| v.as_int());
+
+    match (x, z) {
+        (Some(x), Some(z)) => Some((*x, *z)),
+        _ => get_path(data, TAG_POSITION)
+            .and_then(|v| v.as_int_array())
+            .filter(|p| p.len() == 2)
+            .map(|p| (p[0], p[1])),
+    }
+}
+
+/// Returns the format of chunks with the given data version.
+/// Chunks without a data version are treated like the oldest format
+fn chunk_format(data_version: Option<i32>) -> &'static ChunkFormat {
+    let data_version = data_version.unwrap_or(i32::MIN);
+
+    CHUNK_FORMATS
+        .iter()
+        .rev()
+        .find(|format| format.min_data_version <= data_version)
+        .unwrap_or(&CHUNK_FORMATS[0])
+}
+
+/// Compresses serialized nbt data with the given compression type.
+/// The level is ignored by uncompressed and LZ4 data
+pub(crate) fn compress_nbt_data(
+    data: &[u8],
+    compression_type: u8,
+    level: Compression,
+) -> IOResult<Vec<u8>> {
+    let mut compressed = Vec::new();
+
+    match compression_type {
+        1 => {
+            GzEncoder::new(data, level).read_to_end(&mut compressed)?;
+        }
+        2 => {
+            let mut cache = ByteArrayCache::with_compression(level);
+            cache.write(data)?;
+            cache.read_to_end(&mut compressed)?;
+        }
+        3 => compressed.extend_from_slice(data),
+        4 => compressed = compress_lz4_blocks(data),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown compression type {}", compression_type),
+            ))
+        }
+    }
+
+    Ok(compressed)
+}
+
+/// Decompresses the data with the compression type and parses the nbt data in it
+fn decode_nbt_data<R: io::Read>(
+    reader: R,
+    compression_type: u8,
+    size_limit: u64,
+    max_depth: u64,
+) -> Result<HashMap<String, NBTValue>, ChunkScanError> {
+    let decoder: Box<dyn io::Read + '_> = match compression_type {
+        1 => Box::new(GzDecoder::new(reader)),
+        2 => Box::new(ZlibDecoder::new(reader)),
+        4 => Box::new(LZ4BlockReader::new(reader)),
+        // uncompressed data and data without a known header is parsed directly
+        _ => Box::new(reader),
+    };
+    let mut nbt_reader = NBTReader::with_size_limit(
+        BufReader::new(LimitedReader::new(decoder, size_limit)),
+        size_limit,
+    )
+    .recursion_limit(max_depth);
+
+    nbt_reader.parse().map_err(|e| match e {
+        // exceeding the limit is caused by the compressed data and not the nbt structure
+        NBTError::IO(e) if SizeLimitExceeded::is_cause_of(&e) => ChunkScanError::IO(e),
+        e => e.into(),
+    })
+}
+
+/// Decompresses the chunk data with the compression method without parsing it.
+/// Fails if the decompressed data is larger than `size_limit` bytes
+pub(crate) fn decompress_chunk_data(
+    data: &[u8],
+    compression_type: u8,
+    size_limit: u64,
+) -> IOResult<Vec<u8>> {
+    let decoder: Box<dyn io::Read + '_> = match compression_type {
+        1 => Box::new(GzDecoder::new(data)),
+        2 => Box::new(ZlibDecoder::new(data)),
+        3 => Box::new(data),
+        4 => Box::new(LZ4BlockReader::new(data)),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown compression type {}", compression_type),
+            ))
+        }
+    };
+    let mut decompressed = Vec::new();
+    LimitedReader::new(decoder, size_limit).read_to_end(&mut decompressed)?;
+
+    Ok(decompressed)
+}
+
+/// Returns the first of the compression methods the chunk data can be decompressed
+/// and parsed as nbt data with. Returns `None` if none of them works
+pub(crate) fn detect_compression_method(
+    data: &[u8],
+    methods: &[u8],
+    size_limit: u64,
+    max_depth: u64,
+) -> Option<u8> {
+    methods
+        .iter()
+        .copied()
+        .find(|method| decode_nbt_data(data, *method, size_limit, max_depth).is_ok())
+}
+
+/// Returns if chunks may be stored with the compression method.
+/// The methods are 1 for GZIP, 2 for ZLIB, 3 for uncompressed data and 4 for LZ4.
+/// The game never writes 0 so it's as invalid as the unknown methods above 4
+pub fn is_valid_compression_method(method: u8) -> bool {
+    matches!(method, 1..=4)
+}
+
+/// Guesses the compression type of chunk data by looking at its first bytes
+/// without consuming them. Returns `None` if no known header was found
+pub(crate) fn sniff_compression_type<R: io::Read + io::Seek>(
+    reader: &mut R,
+) -> IOResult<Option<u8>> {
+    let mut magic = Vec::with_capacity(LZ4_BLOCK_MAGIC.len());
+    reader
+        .by_ref()
+        .take(LZ4_BLOCK_MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    reader.seek(SeekFrom::Current(-(magic.len() as i64)))?;
+
+    let compression_type = match magic.as_slice() {
+        // GZIP magic number
+        [0x1f, 0x8b, ..] => Some(1),
+        // ZLIB header with deflate and a valid header checksum
+        [0x78, flags, ..] if u16::from_be_bytes([0x78, *flags]).is_multiple_of(31) => Some(2),
+        // magic of the LZ4 block stream of lz4-java the game writes
+        magic if magic == LZ4_BLOCK_MAGIC => Some(4),
+        // uncompressed data starting with the root compound tag
+        [10, ..] => Some(3),
+        _ => None,
+    };
+
+    Ok(compression_type)
+}
+
 #[derive(Debug)]
 pub enum ChunkScanError {
     String(String),
     IO(io::Error),
     NBTError(NBTError),
     MissingTag(&'static str),
+    MissingExternalFile,
     InvalidFormat(&'static str),
     InvalidLength(u32),
 }
@@ -87,6 +849,7 @@ impl Display for ChunkScanError {
             Self::IO(io) => write!(f, "IO Error: {}", io),
             Self::NBTError(nbt) => write!(f, "NBT Error: {}", nbt),
             Self::MissingTag(tag) => write!(f, "Missing Tag in NBT Data: {}", tag),
+            Self::MissingExternalFile => write!(f, "Missing external chunk file"),
             Self::InvalidFormat(tag) => write!(f, "Unexpected data format for NBT Tag {}", tag),
             Self::InvalidLength(length) => write!(f, "Invalid chunk data length: {}", length),
         }
@@ -110,3 +873,290 @@ impl From<String> for ChunkScanError {
         Self::String(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_known_compression_methods_are_valid() {
+        let valid: Vec<u8> = (0..=u8::MAX)
+            .filter(|method| is_valid_compression_method(*method))
+            .collect();
+
+        assert_eq!(valid, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn gzip_data_behind_a_wrong_compression_byte_is_still_read() {
+        let data = compress_nbt_data(&data_version_nbt(), 1, Compression::default()).unwrap();
+        assert_eq!(&data[..2], [0x1f, 0x8b]);
+
+        let mut reader = io::Cursor::new(chunk_record(7, &data));
+        let chunk = Chunk::from_buf_reader(&mut reader).unwrap();
+        assert!(!is_valid_compression_method(chunk.compression_method()));
+
+        let data = chunk.read_nbt_data(&mut reader, 1024, 16).unwrap();
+        assert_eq!(data.get(TAG_DATA_VERSION), Some(&NBTValue::Int(3465)));
+    }
+
+    /// Returns the record of a chunk with the compression type followed by the data
+    fn chunk_record(compression: u8, data: &[u8]) -> Vec<u8> {
+        let mut record = (data.len() as u32 + 1).to_be_bytes().to_vec();
+        record.push(compression);
+        record.extend_from_slice(data);
+
+        record
+    }
+
+    /// Returns the serialized nbt data of a root compound only storing the data version
+    fn data_version_nbt() -> Vec<u8> {
+        let mut nbt = vec![10, 0, 0, 3, 0, 11];
+        nbt.extend_from_slice(b"DataVersion");
+        nbt.extend_from_slice(&3465i32.to_be_bytes());
+        nbt.push(0);
+
+        nbt
+    }
+
+    /// Returns a chunk compressed like lz4-java's `LZ4BlockOutputStream` writes it laid out
+    /// byte by byte. The nbt data is stored in a single LZ4 block holding one literal run
+    fn lz4_block_fixture() -> (Vec<u8>, Vec<u8>) {
+        let nbt = data_version_nbt();
+        // the literal length of 22 doesn't fit the token and continues in the next byte
+        let mut block = vec![0xf0, nbt.len() as u8 - 15];
+        block.extend_from_slice(&nbt);
+        let checksum = crate::lz4::xxhash32(&nbt, 0x9747_b28c) & 0x0fff_ffff;
+
+        let mut stream = b"LZ4Block".to_vec();
+        stream.push(0x26);
+        stream.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        stream.extend_from_slice(&(nbt.len() as u32).to_le_bytes());
+        stream.extend_from_slice(&checksum.to_le_bytes());
+        stream.extend_from_slice(&block);
+        stream.extend_from_slice(b"LZ4Block");
+        stream.push(0x16);
+        stream.extend_from_slice(&[0; 12]);
+
+        (stream, nbt)
+    }
+
+    #[test]
+    fn lz4_chunks_are_read_as_lz4_block_streams() {
+        let (stream, nbt) = lz4_block_fixture();
+        assert_eq!(decompress_chunk_data(&stream, 4, 1024).unwrap(), nbt);
+
+        for compression in [4, 0] {
+            let mut reader = io::Cursor::new(chunk_record(compression, &stream));
+            let chunk = Chunk::from_buf_reader(&mut reader).unwrap();
+            assert_eq!(sniff_compression_type(&mut reader).unwrap(), Some(4));
+
+            let data = chunk.read_nbt_data(&mut reader, 1024, 16).unwrap();
+            assert_eq!(data.get(TAG_DATA_VERSION), Some(&NBTValue::Int(3465)));
+        }
+    }
+
+    #[test]
+    fn lz4_compressed_chunks_start_with_the_lz4_block_magic() {
+        let (_, nbt) = lz4_block_fixture();
+        let compressed = compress_nbt_data(&nbt, 4, Compression::default()).unwrap();
+
+        assert_eq!(&compressed[..8], LZ4_BLOCK_MAGIC);
+        assert_eq!(decompress_chunk_data(&compressed, 4, 1024).unwrap(), nbt);
+    }
+
+    /// Returns a compound with the given tags
+    fn compound(tags: Vec<(&str, NBTValue)>) -> NBTValue {
+        NBTValue::Compound(
+            tags.into_iter()
+                .map(|(tag, value)| (tag.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn tile_entity(id: &str, x: i32, y: i32, z: i32) -> NBTValue {
+        compound(vec![
+            (TAG_ID, NBTValue::String(id.to_string())),
+            (TAG_BLOCK_X, NBTValue::Int(x)),
+            (TAG_BLOCK_Y, NBTValue::Int(y)),
+            (TAG_BLOCK_Z, NBTValue::Int(z)),
+        ])
+    }
+
+    #[test]
+    fn stacked_tile_entities_are_reduced_to_the_first_one() {
+        let tile_entities = vec![
+            tile_entity("minecraft:chest", 16, 64, 32),
+            tile_entity("minecraft:furnace", 16, 64, 32),
+            tile_entity("minecraft:hopper", 17, 64, 33),
+            // lies in the chunk next to the one it's stored in
+            tile_entity("minecraft:sign", 0, 64, 32),
+            tile_entity("minecraft:chest", 17, 64, 33),
+            tile_entity("minecraft:chest", 16, 64, 32),
+        ];
+        let level = compound(vec![
+            (TAG_X_POS, NBTValue::Int(1)),
+            (TAG_Z_POS, NBTValue::Int(2)),
+            (TAG_TILE_ENTITIES, NBTValue::List(tile_entities)),
+        ]);
+        let mut data = HashMap::new();
+        data.insert(TAG_LEVEL.to_string(), level);
+
+        let level = data[TAG_LEVEL].as_compound().unwrap();
+        assert_eq!(count_invalid_tile_entities(level, Some(1), Some(2)), (1, 3));
+        assert_eq!(remove_duplicate_tile_entities(&mut data), 3);
+
+        let level = data[TAG_LEVEL].as_compound().unwrap();
+        assert_eq!(count_invalid_tile_entities(level, Some(1), Some(2)), (1, 0));
+        let ids: Vec<_> = level[TAG_TILE_ENTITIES]
+            .as_list()
+            .unwrap()
+            .iter()
+            .map(|tile_entity| tile_entity.as_compound().unwrap()[TAG_ID].clone())
+            .collect();
+        assert_eq!(
+            ids,
+            ["minecraft:chest", "minecraft:hopper", "minecraft:sign"]
+                .map(|id| NBTValue::String(id.to_string()))
+        );
+    }
+
+    #[test]
+    fn biomes_are_validated_in_the_array_and_the_paletted_form() {
+        let with_biomes = |biomes| HashMap::from([(TAG_BIOMES.to_string(), biomes)]);
+        assert!(!has_invalid_biomes(
+            &with_biomes(NBTValue::IntArray(vec![1; 1024])),
+            Some(2586)
+        ));
+        // the array stores an entry for each column instead of each cube
+        assert!(has_invalid_biomes(
+            &with_biomes(NBTValue::IntArray(vec![1; 256])),
+            Some(2586)
+        ));
+
+        let with_section_biomes = |biomes| {
+            let section = compound(vec![(TAG_Y, NBTValue::Byte(0)), (TAG_ROOT_BIOMES, biomes)]);
+            HashMap::from([(TAG_ROOT_SECTIONS.to_string(), NBTValue::List(vec![section]))])
+        };
+        let plains = NBTValue::String("minecraft:plains".to_string());
+        let forest = NBTValue::String("minecraft:forest".to_string());
+        let paletted = compound(vec![
+            (TAG_PALETTE, NBTValue::List(vec![plains, forest])),
+            (TAG_DATA, NBTValue::LongArray(vec![0])),
+        ]);
+        assert!(!has_invalid_biomes(
+            &with_section_biomes(paletted),
+            Some(3465)
+        ));
+        let without_palette = compound(vec![
+            (TAG_PALETTE, NBTValue::List(Vec::new())),
+            (TAG_DATA, NBTValue::LongArray(vec![0])),
+        ]);
+        assert!(has_invalid_biomes(
+            &with_section_biomes(without_palette),
+            Some(3465)
+        ));
+    }
+
+    #[test]
+    fn block_states_are_packed_spanned_before_1_16() {
+        let lengths = |spanned| {
+            [16, 17, 256, 257].map(|palette| packed_block_states_length(palette, spanned))
+        };
+        assert_eq!(lengths(true), [256, 320, 512, 576]);
+        assert_eq!(lengths(false), [256, 342, 512, 586]);
+
+        let with_block_states = |palette_length: usize, longs: usize| {
+            let palette = (0..palette_length)
+                .map(|i| compound(vec![(TAG_NAME, NBTValue::String(format!("block_{}", i)))]))
+                .collect();
+            let section = compound(vec![
+                (TAG_Y, NBTValue::Byte(0)),
+                (TAG_BLOCK_PALETTE, NBTValue::List(palette)),
+                (TAG_BLOCK_STATES, NBTValue::LongArray(vec![0; longs])),
+            ]);
+            HashMap::from([(TAG_SECTIONS.to_string(), NBTValue::List(vec![section]))])
+        };
+        // 1.15.2 and 1.16.5
+        for (data_version, spanned) in [(2230, true), (2586, false)] {
+            for palette_length in [16, 17, 256, 257] {
+                let length = packed_block_states_length(palette_length, spanned);
+                let other_length = packed_block_states_length(palette_length, !spanned);
+                let data = with_block_states(palette_length, length);
+                assert!(!has_invalid_block_states(&data, Some(data_version)));
+                if other_length != length {
+                    let data = with_block_states(palette_length, other_length);
+                    assert!(has_invalid_block_states(&data, Some(data_version)));
+                }
+            }
+        }
+    }
+
+    /// Returns the uncompressed record of a chunk with the data version and the tags
+    /// stored at the root or nested in a level tag
+    fn chunk_with_tags(data_version: i32, tags: &[&str], nested: bool) -> Vec<u8> {
+        let tags: HashMap<String, NBTValue> = tags
+            .iter()
+            .map(|tag| {
+                let value = match *tag {
+                    TAG_X_POS | TAG_Z_POS => NBTValue::Int(0),
+                    TAG_LAST_UPDATE | TAG_INHABITED_TIME => NBTValue::Long(0),
+                    TAG_STATUS => NBTValue::String("full".to_string()),
+                    _ => NBTValue::List(Vec::new()),
+                };
+                (tag.to_string(), value)
+            })
+            .collect();
+        let mut root = if nested {
+            HashMap::from([(TAG_LEVEL.to_string(), NBTValue::Compound(tags))])
+        } else {
+            tags
+        };
+        root.insert(TAG_DATA_VERSION.to_string(), NBTValue::Int(data_version));
+        let mut writer = crate::nbt::NBTWriter::new(Vec::new());
+        writer.write(&root).unwrap();
+
+        chunk_record(3, &writer.into_inner())
+    }
+
+    /// Validates the chunk record like it's stored in the region folder of an anvil file
+    fn validate_record(record: Vec<u8>) -> Result<Chunk, ChunkScanError> {
+        let mut reader = io::Cursor::new(record);
+        let mut chunk = Chunk::from_buf_reader(&mut reader)?;
+        chunk.validate_nbt_data(
+            &mut reader,
+            RegionFolder::Region,
+            RegionFormat::Anvil,
+            1024 * 1024,
+            64,
+            None,
+        )?;
+
+        Ok(chunk)
+    }
+
+    #[test]
+    fn required_tags_depend_on_the_chunk_format() {
+        use crate::constants::tags::{LEVEL_TAGS, ROOT_TAGS};
+
+        // 1.16.5 stores the chunk in a level tag and 1.18.2 at the root
+        let chunk = validate_record(chunk_with_tags(2586, LEVEL_TAGS, true)).unwrap();
+        assert_eq!(chunk.status.as_deref(), Some("full"));
+        let chunk = validate_record(chunk_with_tags(2975, ROOT_TAGS, false)).unwrap();
+        assert_eq!(chunk.status.as_deref(), Some("full"));
+
+        let without = |tags: &[&'static str], missing| -> Vec<&'static str> {
+            tags.iter().copied().filter(|tag| *tag != missing).collect()
+        };
+        let tags = without(LEVEL_TAGS, TAG_ENTITIES);
+        assert!(matches!(
+            validate_record(chunk_with_tags(2586, &tags, true)),
+            Err(ChunkScanError::MissingTag(TAG_ENTITIES))
+        ));
+        let tags = without(ROOT_TAGS, TAG_BLOCK_ENTITIES);
+        assert!(matches!(
+            validate_record(chunk_with_tags(2975, &tags, false)),
+            Err(ChunkScanError::MissingTag(TAG_BLOCK_ENTITIES))
+        ));
+    }
+}
@@ -0,0 +1,100 @@
+use crate::scan::ScanOptions;
+use serde::Deserialize;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+/// The name of the config file that is read from the world folder
+pub const CONFIG_FILE_NAME: &str = "regions-tool.toml";
+
+/// The dimensions that can be selected in the config file
+const DIMENSIONS: &[&str] = &["overworld", "nether", "end", "all"];
+
+/// Scan options read from a toml config file. Options that aren't set
+/// keep the values given on the command line or their defaults.
+/// `max_inhabited_ticks` is only used by the prune subcommand so that
+/// a scan never deletes chunks because of a file it wasn't given explicitly
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScanConfig {
+    pub fix: Option<bool>,
+    pub delete: Option<bool>,
+    pub dimension: Option<String>,
+    pub threads: Option<usize>,
+    pub compression_level: Option<u32>,
+    pub max_inhabited_ticks: Option<i64>,
+    pub required_tags: Option<Vec<String>>,
+}
+
+impl ScanConfig {
+    /// Reads and validates the config file
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let invalid = |message: String| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid config file {:?}: {}", path, message),
+            )
+        };
+        let config: Self = toml::from_str(&content).map_err(|e| invalid(e.to_string()))?;
+
+        if let Some(dimension) = &config.dimension {
+            if !DIMENSIONS.contains(&dimension.as_str()) {
+                return Err(invalid(format!("unknown dimension {}", dimension)));
+            }
+        }
+        if let Some(level) = config.compression_level {
+            if level > 9 {
+                return Err(invalid(format!("compression level {} isn't 0 to 9", level)));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Reads the config file in the world folder if there is one
+    pub fn discover(world_path: &Path) -> io::Result<Option<Self>> {
+        let path = world_path.join(CONFIG_FILE_NAME);
+        if !world_path.is_dir() || !path.is_file() {
+            return Ok(None);
+        }
+        log::debug!("Reading scan options from {:?}", path);
+
+        Self::from_file(&path).map(Some)
+    }
+
+    /// Sets the options that are set in the config
+    pub fn apply(&self, mut options: ScanOptions) -> ScanOptions {
+        if let Some(fix) = self.fix {
+            options = options.fix(fix);
+        }
+        if let Some(delete) = self.delete {
+            options = options.fix_delete(delete);
+        }
+        if let Some(threads) = self.threads {
+            options = options.threads(threads);
+        }
+        if let Some(level) = self.compression_level {
+            options = options.compression_level(level);
+        }
+        if let Some(tags) = &self.required_tags {
+            options = options.required_tags(Some(tags.clone()));
+        }
+
+        options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applying_the_config_never_prunes_chunks() {
+        let config: ScanConfig = toml::from_str("fix = true\nmax_inhabited_ticks = 200").unwrap();
+        let options = config.apply(ScanOptions::new());
+
+        assert!(options.fix);
+        assert_eq!(options.prune, None);
+    }
+}
@@ -18,9 +18,48 @@ pub mod tags {
     pub const TAG_POST_PROCESSING: &str = "PostProcessing";
     pub const TAG_STATUS: &str = "Status";
     pub const TAG_STRUCTURES: &str = "Structures";
+    pub const TAG_ROOT_STRUCTURES: &str = "structures";
+    pub const TAG_REFERENCES: &str = "References";
+    pub const TAG_STARTS: &str = "Starts";
+    pub const TAG_ROOT_STARTS: &str = "starts";
+    pub const TAG_POSITION: &str = "Position";
+    pub const TAG_DATA_VERSION: &str = "DataVersion";
+    pub const TAG_ROOT_SECTIONS: &str = "sections";
+    pub const TAG_BLOCK_ENTITIES: &str = "block_entities";
+    pub const TAG_ID: &str = "id";
+    pub const TAG_POS: &str = "Pos";
+    pub const TAG_BLOCKS: &str = "Blocks";
+    pub const TAG_BIOMES: &str = "Biomes";
+    pub const TAG_ROOT_BIOMES: &str = "biomes";
+    pub const TAG_PALETTE: &str = "palette";
+    pub const TAG_DATA: &str = "data";
+    pub const TAG_Y: &str = "Y";
+    pub const TAG_NAME: &str = "Name";
+    /// The block palette of sections before and since 1.18
+    pub const TAG_BLOCK_PALETTE: &str = "Palette";
+    pub const TAG_BLOCK_STATES_PALETTE: &str = "block_states.palette";
+    /// The packed indices into the block palette before and since 1.18
+    pub const TAG_BLOCK_STATES: &str = "BlockStates";
+    pub const TAG_BLOCK_STATES_DATA: &str = "block_states.data";
+    /// The block position of block entities
+    pub const TAG_BLOCK_X: &str = "x";
+    pub const TAG_BLOCK_Y: &str = "y";
+    pub const TAG_BLOCK_Z: &str = "z";
+    /// The path of the number of ticks the world has run in the level.dat
+    pub const TAG_WORLD_TIME: &str = "Data.Time";
+    /// Paths of the information about the world in the level.dat
+    pub const TAG_LEVEL_NAME: &str = "Data.LevelName";
+    pub const TAG_WORLD_DATA_VERSION: &str = "Data.DataVersion";
+    pub const TAG_VERSION_NAME: &str = "Data.Version.Name";
+    pub const TAG_SPAWN_X: &str = "Data.SpawnX";
+    pub const TAG_SPAWN_Y: &str = "Data.SpawnY";
+    pub const TAG_SPAWN_Z: &str = "Data.SpawnZ";
+    pub const TAG_ENABLED_DATAPACKS: &str = "Data.DataPacks.Enabled";
+    pub const TAG_DISABLED_DATAPACKS: &str = "Data.DataPacks.Disabled";
+    pub const TAG_WORLD_DIMENSIONS: &str = "Data.WorldGenSettings.dimensions";
 
     /// A list of required tags stored in the level tag
-    pub const LEVEL_TAGS: &[&'static str] = &[
+    pub const LEVEL_TAGS: &[&str] = &[
         TAG_X_POS,
         TAG_Z_POS,
         TAG_SECTIONS,
@@ -30,4 +69,99 @@ pub mod tags {
         TAG_TILE_ENTITIES,
         TAG_STATUS,
     ];
+
+    /// A list of required tags stored in the level tag of chunks in the legacy McRegion format
+    pub const MCREGION_TAGS: &[&str] = &[
+        TAG_X_POS,
+        TAG_Z_POS,
+        TAG_BLOCKS,
+        TAG_LAST_UPDATE,
+        TAG_ENTITIES,
+        TAG_TILE_ENTITIES,
+    ];
+
+    /// A list of required tags stored in the level tag
+    /// after entities were moved into their own region files
+    pub const LEVEL_TAGS_WITHOUT_ENTITIES: &[&str] = &[
+        TAG_X_POS,
+        TAG_Z_POS,
+        TAG_SECTIONS,
+        TAG_LAST_UPDATE,
+        TAG_INHABITED_TIME,
+        TAG_TILE_ENTITIES,
+        TAG_STATUS,
+    ];
+
+    /// A list of required tags stored at the root of chunks in the 1.18+ format
+    pub const ROOT_TAGS: &[&str] = &[
+        TAG_X_POS,
+        TAG_Z_POS,
+        TAG_ROOT_SECTIONS,
+        TAG_LAST_UPDATE,
+        TAG_INHABITED_TIME,
+        TAG_BLOCK_ENTITIES,
+        TAG_STATUS,
+    ];
+}
+
+/// Data version constants
+pub mod versions {
+    use super::tags::{LEVEL_TAGS, LEVEL_TAGS_WITHOUT_ENTITIES, ROOT_TAGS};
+
+    /// The first data version storing entities in their own region files
+    pub const SEPARATE_ENTITIES: i32 = 2681;
+    /// The first data version storing the chunk data at the root instead of the level tag
+    pub const FLAT_CHUNK_FORMAT: i32 = 2844;
+
+    /// The first data version that doesn't split the packed heightmap entries across longs
+    pub const UNSPANNED_HEIGHTMAPS: i32 = 2529;
+
+    /// The number of longs of each heightmap ordered by the data version they were introduced in.
+    /// Heightmaps store 256 entries with 9 bits each
+    pub const HEIGHTMAP_LENGTHS: &[(i32, usize)] = &[(i32::MIN, 36), (UNSPANNED_HEIGHTMAPS, 37)];
+
+    /// The first data version that doesn't split the packed block states across longs.
+    /// It's the same snapshot that changed the packing of heightmaps
+    pub const UNSPANNED_BLOCK_STATES: i32 = UNSPANNED_HEIGHTMAPS;
+
+    /// The first data version storing a biome for every 4x4x4 cube instead of every column
+    pub const VOLUMETRIC_BIOMES: i32 = 2203;
+
+    /// The number of entries of the biomes array ordered by the data version they were
+    /// introduced in. Chunks in the 1.18+ format store paletted biomes in each section
+    pub const BIOME_LENGTHS: &[(i32, usize)] = &[(i32::MIN, 256), (VOLUMETRIC_BIOMES, 1024)];
+
+    /// The first data version of the 1.18 snapshots extending the world height
+    pub const EXTENDED_WORLD_HEIGHT: i32 = 2825;
+
+    /// The lowest and highest y index of chunk sections ordered by the data version they were
+    /// introduced in. The range includes the sections above and below the world storing light
+    pub const SECTION_Y_RANGES: &[(i32, (i8, i8))] =
+        &[(i32::MIN, (-1, 16)), (EXTENDED_WORLD_HEIGHT, (-5, 20))];
+
+    /// The layout of the chunk data starting at a data version
+    pub struct ChunkFormat {
+        pub min_data_version: i32,
+        pub nested_in_level: bool,
+        pub required_tags: &'static [&'static str],
+    }
+
+    /// The chunk formats ordered by the data version they were introduced in
+    pub const CHUNK_FORMATS: &[ChunkFormat] = &[
+        ChunkFormat {
+            min_data_version: i32::MIN,
+            nested_in_level: true,
+            required_tags: LEVEL_TAGS,
+        },
+        ChunkFormat {
+            min_data_version: SEPARATE_ENTITIES,
+            nested_in_level: true,
+            required_tags: LEVEL_TAGS_WITHOUT_ENTITIES,
+        },
+        ChunkFormat {
+            min_data_version: FLAT_CHUNK_FORMAT,
+            nested_in_level: false,
+            required_tags: ROOT_TAGS,
+        },
+    ];
 }
@@ -1,7 +1,34 @@
+//! Scans, fixes and inspects the region files of minecraft worlds.
+//!
+//! Worlds are scanned with a [`WorldFolder`] and single region files with a [`RegionFile`].
+//! Both are configured with [`ScanOptions`] and report their findings as [`ScanStatistics`].
+//! The types needed for most uses can be imported at once with
+//! `use minecraft_regions_tool::prelude::*;`
 pub mod chunk;
+pub mod config;
 pub mod constants;
+pub mod lz4;
+pub mod map;
 pub mod nbt;
 pub mod region_file;
 pub mod scan;
+pub mod snbt;
 pub mod utils;
 pub mod world_folder;
+pub mod world_info;
+
+pub use chunk::Chunk;
+pub use nbt::{NBTReader, NBTValue};
+pub use region_file::RegionFile;
+pub use scan::{ScanOptions, ScanStatistics};
+pub use world_folder::WorldFolder;
+
+/// Re-exports the types needed to scan and inspect worlds
+pub mod prelude {
+    pub use crate::chunk::{Chunk, ChunkScanError};
+    pub use crate::nbt::{NBTError, NBTReader, NBTValue, NBTWriter};
+    pub use crate::region_file::{RegionFile, RegionFolder, RegionFormat};
+    pub use crate::scan::{ScanCheck, ScanEvent, ScanOptions, ScanStatistics};
+    pub use crate::world_folder::{Dimension, WorldFolder};
+    pub use crate::world_info::WorldInfo;
+}
@@ -0,0 +1,291 @@
+//! Reads and writes the LZ4 block streams the game stores chunks with compression method 4.
+//!
+//! The game compresses these chunks with the `LZ4BlockOutputStream` of lz4-java which doesn't
+//! use the standard LZ4 frame format. The stream is a sequence of blocks each starting with
+//! a 21 byte header:
+//!
+//! | bytes | content                                                               |
+//! |-------|-----------------------------------------------------------------------|
+//! | 8     | the magic `LZ4Block`                                                  |
+//! | 1     | the method (`0x10` raw, `0x20` LZ4) or'ed with log2(block size) - 10  |
+//! | 4     | the compressed length (little endian)                                 |
+//! | 4     | the original length (little endian)                                   |
+//! | 4     | the XXHash32 checksum of the original data (little endian)            |
+//!
+//! The stream ends with a raw block with both lengths and the checksum set to 0.
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{self, ErrorKind, Read};
+
+/// The magic bytes every block starts with
+pub const LZ4_BLOCK_MAGIC: &[u8; 8] = b"LZ4Block";
+
+const HEADER_LENGTH: usize = 21;
+const METHOD_RAW: u8 = 0x10;
+const METHOD_LZ4: u8 = 0x20;
+const BLOCK_SIZE_BASE: u32 = 10;
+/// The block size lz4-java uses by default and the one the game writes with
+const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+/// The seed lz4-java hashes the blocks with
+const CHECKSUM_SEED: u32 = 0x9747_b28c;
+/// lz4-java reads the checksum as a `java.util.zip.Checksum` which only keeps 28 bits
+const CHECKSUM_MASK: u32 = 0x0fff_ffff;
+
+/// Decompresses an LZ4 block stream while it's being read
+pub struct LZ4BlockReader<R: Read> {
+    inner: R,
+    buffer: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read> LZ4BlockReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buffer: Vec::new(),
+            position: 0,
+            finished: false,
+        }
+    }
+
+    /// Reads the next block into the buffer.
+    /// Sets finished when the end block or the end of the data was reached
+    fn read_block(&mut self) -> io::Result<()> {
+        let mut header = [0u8; HEADER_LENGTH];
+        let read = read_fully(&mut self.inner, &mut header)?;
+
+        if read == 0 {
+            self.finished = true;
+            return Ok(());
+        }
+        if read < HEADER_LENGTH || &header[..8] != LZ4_BLOCK_MAGIC {
+            return Err(invalid_data("Invalid LZ4 block header"));
+        }
+        let method = header[8] & 0xf0;
+        let max_length = 1u64 << (BLOCK_SIZE_BASE + (header[8] & 0x0f) as u32);
+        let mut fields = &header[9..];
+        let compressed_length = fields.read_i32::<LittleEndian>()?;
+        let original_length = fields.read_i32::<LittleEndian>()?;
+        let checksum = fields.read_i32::<LittleEndian>()? as u32;
+
+        if (method != METHOD_RAW && method != METHOD_LZ4)
+            || compressed_length < 0
+            || original_length < 0
+            || original_length as u64 > max_length
+            || (original_length == 0) != (compressed_length == 0)
+            || (method == METHOD_RAW && original_length != compressed_length)
+        {
+            return Err(invalid_data("Invalid LZ4 block header"));
+        }
+        if original_length == 0 {
+            if checksum != 0 {
+                return Err(invalid_data("Invalid LZ4 end block checksum"));
+            }
+            self.finished = true;
+            return Ok(());
+        }
+        let mut compressed = vec![0u8; compressed_length as usize];
+        self.inner.read_exact(&mut compressed)?;
+
+        self.buffer = if method == METHOD_RAW {
+            compressed
+        } else {
+            lz4_flex::block::decompress(&compressed, original_length as usize)
+                .map_err(|e| invalid_data(&e.to_string()))?
+        };
+        self.position = 0;
+
+        if self.buffer.len() != original_length as usize || block_checksum(&self.buffer) != checksum
+        {
+            return Err(invalid_data("LZ4 block checksum mismatch"));
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for LZ4BlockReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.buffer.len() && !self.finished {
+            self.read_block()?;
+        }
+        let count = buf.len().min(self.buffer.len() - self.position);
+        buf[..count].copy_from_slice(&self.buffer[self.position..self.position + count]);
+        self.position += count;
+
+        Ok(count)
+    }
+}
+
+/// Compresses the data to an LZ4 block stream like the game writes it
+pub fn compress_lz4_blocks(data: &[u8]) -> Vec<u8> {
+    let block_size_bits = DEFAULT_BLOCK_SIZE.trailing_zeros() - BLOCK_SIZE_BASE;
+    let mut stream = Vec::with_capacity(data.len() + HEADER_LENGTH * 2);
+
+    for block in data.chunks(DEFAULT_BLOCK_SIZE) {
+        let compressed = lz4_flex::block::compress(block);
+        let (method, payload) = if compressed.len() < block.len() {
+            (METHOD_LZ4, compressed.as_slice())
+        } else {
+            (METHOD_RAW, block)
+        };
+        write_block_header(
+            &mut stream,
+            method | block_size_bits as u8,
+            payload.len() as u32,
+            block.len() as u32,
+            block_checksum(block),
+        );
+        stream.extend_from_slice(payload);
+    }
+    write_block_header(&mut stream, METHOD_RAW | block_size_bits as u8, 0, 0, 0);
+
+    stream
+}
+
+fn write_block_header(
+    stream: &mut Vec<u8>,
+    token: u8,
+    compressed_length: u32,
+    original_length: u32,
+    checksum: u32,
+) {
+    stream.extend_from_slice(LZ4_BLOCK_MAGIC);
+    stream.push(token);
+    stream.extend_from_slice(&compressed_length.to_le_bytes());
+    stream.extend_from_slice(&original_length.to_le_bytes());
+    stream.extend_from_slice(&checksum.to_le_bytes());
+}
+
+/// Returns the checksum lz4-java stores for the original data of a block
+fn block_checksum(data: &[u8]) -> u32 {
+    xxhash32(data, CHECKSUM_SEED) & CHECKSUM_MASK
+}
+
+/// Reads until the buffer is full or the reader ends and returns the number of bytes read
+fn read_fully<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(count) => read += count,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(read)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+const PRIME_1: u32 = 2_654_435_761;
+const PRIME_2: u32 = 2_246_822_519;
+const PRIME_3: u32 = 3_266_489_917;
+const PRIME_4: u32 = 668_265_263;
+const PRIME_5: u32 = 374_761_393;
+
+/// Computes the 32 bit XXHash of the data
+pub(crate) fn xxhash32(data: &[u8], seed: u32) -> u32 {
+    let round = |acc: u32, lane: &[u8]| {
+        acc.wrapping_add(read_u32_le(lane).wrapping_mul(PRIME_2))
+            .rotate_left(13)
+            .wrapping_mul(PRIME_1)
+    };
+    let stripes = data.chunks_exact(16);
+    let mut rest = stripes.remainder();
+
+    let mut hash = if data.len() >= 16 {
+        let mut acc = [
+            seed.wrapping_add(PRIME_1).wrapping_add(PRIME_2),
+            seed.wrapping_add(PRIME_2),
+            seed,
+            seed.wrapping_sub(PRIME_1),
+        ];
+        for stripe in stripes {
+            for (i, lane) in stripe.chunks_exact(4).enumerate() {
+                acc[i] = round(acc[i], lane);
+            }
+        }
+        acc[0]
+            .rotate_left(1)
+            .wrapping_add(acc[1].rotate_left(7))
+            .wrapping_add(acc[2].rotate_left(12))
+            .wrapping_add(acc[3].rotate_left(18))
+    } else {
+        seed.wrapping_add(PRIME_5)
+    };
+    hash = hash.wrapping_add(data.len() as u32);
+
+    while rest.len() >= 4 {
+        hash = hash
+            .wrapping_add(read_u32_le(rest).wrapping_mul(PRIME_3))
+            .rotate_left(17)
+            .wrapping_mul(PRIME_4);
+        rest = &rest[4..];
+    }
+    for byte in rest {
+        hash = hash
+            .wrapping_add((*byte as u32).wrapping_mul(PRIME_5))
+            .rotate_left(11)
+            .wrapping_mul(PRIME_1);
+    }
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(PRIME_2);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(PRIME_3);
+
+    hash ^ (hash >> 16)
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xxhash32_matches_the_reference_values() {
+        assert_eq!(xxhash32(b"", 0), 0x02cc_5d05);
+        assert_eq!(xxhash32(b"a", 0), 0x550d_7456);
+        assert_eq!(xxhash32(b"abc", 0), 0x32d1_53ff);
+        assert_eq!(
+            xxhash32(b"Nobody inspects the spammish repetition", 0),
+            0xe229_3b2f
+        );
+    }
+
+    #[test]
+    fn streams_are_read_back_across_blocks() {
+        let data: Vec<u8> = (0..DEFAULT_BLOCK_SIZE * 2 + 100)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let stream = compress_lz4_blocks(&data);
+        assert_eq!(&stream[..8], LZ4_BLOCK_MAGIC);
+        assert_eq!(stream[8], METHOD_LZ4 | 6);
+        assert_eq!(&stream[stream.len() - 12..], &[0u8; 12][..]);
+
+        let mut decompressed = Vec::new();
+        LZ4BlockReader::new(stream.as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+    }
+
+    #[test]
+    fn corrupted_blocks_fail_the_checksum() {
+        let mut stream = compress_lz4_blocks(b"incompressible");
+        assert_eq!(stream[8], METHOD_RAW | 6);
+        stream[HEADER_LENGTH] ^= 0xff;
+
+        let mut decompressed = Vec::new();
+        assert!(LZ4BlockReader::new(stream.as_slice())
+            .read_to_end(&mut decompressed)
+            .is_err());
+    }
+}
@@ -1,20 +1,54 @@
 use colored::*;
 use env_logger::Env;
-use log::Level;
-use minecraft_regions_tool::scan::ScanOptions;
-use minecraft_regions_tool::world_folder::WorldFolder;
-use std::path::PathBuf;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use minecraft_regions_tool::config::ScanConfig;
+use minecraft_regions_tool::map::{ChunkMap, MapMetric};
+use minecraft_regions_tool::nbt::{NBTValue, TaggedNBTValue};
+use minecraft_regions_tool::region_file::{
+    parse_region_coords, RegionFile, RegionFolder, BLOCK_SIZE,
+};
+use minecraft_regions_tool::scan::{ScanCheck, ScanOptions, ScanStatistics, TrimArea};
+use minecraft_regions_tool::utils::{format_bytes, format_hex};
+use minecraft_regions_tool::world_folder::{
+    ChunkDifference, Dimension, RegionBounds, RegionPattern, WorldFolder,
+};
+use minecraft_regions_tool::world_info::WorldInfo;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, IsTerminal, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
 struct Opt {
-    /// Path to the world folder
+    /// Path to the world folder. The scan subcommand also accepts a single region file
     #[structopt(parse(from_os_str))]
     input: PathBuf,
 
-    /// Forces verbose output
-    #[structopt(short, long)]
-    verbose: bool,
+    /// Increases the verbosity of the output. -v prints debug and -vv trace messages
+    #[structopt(short, long, parse(from_occurrences))]
+    verbose: u8,
+
+    /// Only prints warnings and errors and disables the progress bar
+    #[structopt(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Appends the log including debug messages to this file regardless of the verbosity
+    #[structopt(long, parse(from_os_str))]
+    log_file: Option<PathBuf>,
+
+    /// Disables the progress bar
+    #[structopt(long)]
+    no_progress: bool,
+
+    /// Modifies the world even if it's loaded by a running game or server
+    #[structopt(long)]
+    force: bool,
 
     #[structopt(subcommand)]
     sub_command: SubCommand,
@@ -24,10 +58,74 @@ struct Opt {
 #[structopt()]
 enum SubCommand {
     /// Return the total number of chunks in the world
-    Count,
+    Count(CountArgs),
 
     /// Scan for errors in the region files and optionally fix them
-    Scan(ScanArgs),
+    Scan(Box<ScanArgs>),
+
+    /// Check the region files for errors without ever modifying them
+    Verify(VerifyArgs),
+
+    /// Verify region files again whenever the game changes them without ever modifying them
+    Watch(WatchArgs),
+
+    /// Print the nbt data of a chunk in the region file given as input as json
+    Dump(DumpArgs),
+
+    /// Close the gaps between chunks without validating their data
+    Defrag(DefragArgs),
+
+    /// Compress all chunks again with zlib to shrink the world
+    Recompress(RecompressArgs),
+
+    /// Delete chunks that players have spent little to no time in
+    Prune(PruneArgs),
+
+    /// Delete chunks outside of a radius around a center chunk
+    Trim(TrimArgs),
+
+    /// Print the coordinates of all chunks present in the world
+    List(ListArgs),
+
+    /// Compare the chunks of the world with the ones of another world like a backup
+    Diff(DiffArgs),
+
+    /// Export the size, compression and timestamp of every chunk for external analysis
+    ExportSizes(ExportSizesArgs),
+
+    /// Print the number of chunks in each generation status
+    StatusReport(StatusReportArgs),
+
+    /// Print the size of the region files without reading them
+    Size(SizeArgs),
+
+    /// Render a png map of the chunks of a dimension with one pixel per chunk
+    Map(MapArgs),
+
+    /// Print a checksum of the chunks of each region file and of the whole world
+    Checksum(ChecksumArgs),
+
+    /// Print information about the header of the region file given as input
+    Info(InfoArgs),
+
+    /// Rebuild the locations table of the region file given as input from the chunks it contains
+    RepairHeader(RepairHeaderArgs),
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct CountArgs {
+    /// The dimension to count the chunks of
+    #[structopt(long, default_value = "overworld", possible_values = &["overworld", "nether", "end", "all"])]
+    dimension: String,
+
+    /// Prints the number of chunks of each region file
+    #[structopt(long)]
+    per_file: bool,
+
+    /// The size in bytes above which region files are skipped
+    #[structopt(long, default_value = "536870912")]
+    max_file_size: u64,
 }
 
 #[derive(StructOpt, Debug)]
@@ -40,51 +138,1416 @@ struct ScanArgs {
     /// Deletes corrupted data
     #[structopt(short, long)]
     delete: bool,
+
+    /// Doesn't fix errors even if the config file enables fixing
+    #[structopt(long, conflicts_with = "fix")]
+    no_fix: bool,
+
+    /// Doesn't delete corrupted data even if the config file enables deleting
+    #[structopt(long, conflicts_with = "delete")]
+    no_delete: bool,
+
+    /// Deletes without asking for confirmation. Required for deleting if stdin isn't a terminal
+    #[structopt(short, long)]
+    yes: bool,
+
+    /// Explicitly reports when no errors were found
+    #[structopt(long)]
+    report_clean: bool,
+
+    /// The folder containing the region files to scan. Defaults to all of the region,
+    /// entities and poi folders that exist
+    #[structopt(long, default_value = "all", possible_values = &["region", "entities", "poi", "all"])]
+    folder: String,
+
+    /// The dimension to scan. Defaults to the overworld
+    #[structopt(long, possible_values = &["overworld", "nether", "end", "all"])]
+    dimension: Option<String>,
+
+    /// Only scans the region files matching the pattern like r.0.* or r.5.-3.
+    /// Can be given multiple times to scan the files matching any of them
+    #[structopt(long = "region", number_of_values = 1)]
+    regions: Vec<RegionPattern>,
+
+    /// Only scans the region files inside the box of region coordinates like -2,-2,2,2
+    #[structopt(long, allow_hyphen_values = true)]
+    region_bounds: Option<RegionBounds>,
+
+    /// Only scans the region files containing chunks inside the box of chunk coordinates
+    /// like -64,-64,63,63
+    #[structopt(long, allow_hyphen_values = true, conflicts_with = "region-bounds")]
+    chunk_bounds: Option<RegionBounds>,
+
+    /// The format of the scan results. jsonl prints the results of each file as soon as
+    /// it's scanned followed by the total, with one json object per line
+    #[structopt(long, default_value = "text", possible_values = &["text", "json", "jsonl"])]
+    format: String,
+
+    /// Adds the results of each region file to the json output, ordered by their number
+    /// of errors so that the worst files come first
+    #[structopt(long)]
+    per_file: bool,
+
+    /// The number of region files scanned in parallel. 0 uses all cores and is the default.
+    /// Fewer threads also limit the memory usage since fewer chunks are decompressed at once
+    #[structopt(long)]
+    threads: Option<usize>,
+
+    /// The maximum depth of nested nbt compounds and lists before a chunk is considered corrupted
+    #[structopt(long, default_value = "100")]
+    max_nbt_depth: u64,
+
+    /// The size in bytes above which region files are skipped without opening them
+    #[structopt(long, default_value = "536870912")]
+    max_file_size: u64,
+
+    /// How often opening a region file is retried with a growing delay after errors that
+    /// might be transient like the ones of network file systems
+    #[structopt(long, default_value = "0")]
+    io_retries: u32,
+
+    /// Only runs the given checks, separated by commas. Defaults to all checks
+    #[structopt(long, use_delimiter = true, possible_values = &["pointers", "length", "compression", "nbt", "gaps", "timestamps", "alignment"])]
+    only: Option<Vec<ScanCheck>>,
+
+    /// Reads each region file into memory before scanning it if it isn't modified.
+    /// Faster on slow disks but needs up to the size of a region file per thread
+    #[structopt(long)]
+    in_memory: bool,
+
+    /// The compression level from 0 to 9 used when chunks are rewritten. Defaults to 6.
+    /// Higher levels shrink the world further but take longer
+    #[structopt(long, possible_values = &["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"])]
+    compression_level: Option<u32>,
+
+    /// Counts the entities and block entities and reports the chunks containing the most
+    #[structopt(long)]
+    count_entities: bool,
+
+    /// Reports the given number of chunks with the largest compressed data
+    #[structopt(long, default_value = "0")]
+    top: usize,
+
+    /// Counts the entities by their type. Entities of newer worlds are stored in the entities folder
+    #[structopt(long)]
+    entity_histogram: bool,
+
+    /// Counts the chunks containing blocks, block entities or entities of each namespace
+    /// to show which mods the world depends on
+    #[structopt(long)]
+    inventory: bool,
+
+    /// Removes entities whose position lies outside of the chunk they're stored in
+    #[structopt(long)]
+    fix_entities: bool,
+
+    /// Removes all but the first tile entity at the same block position
+    #[structopt(long)]
+    fix_tile_entities: bool,
+
+    /// Sets the compression method of chunks that can't be read with their stored one
+    /// to the one their data is actually compressed with. Invalid methods are detected
+    /// with --fix as well. Chunks that can't be read with any method are deleted with --delete
+    #[structopt(long = "fix-compression-properly")]
+    fix_compression: bool,
+
+    /// Recompresses readable chunks with ZLIB at the highest level and writes them back
+    /// if they occupy fewer sectors afterwards. The freed space is reclaimed
+    #[structopt(long)]
+    recompress: bool,
+
+    /// Prints every problem found with the coordinates of the affected chunk
+    #[structopt(long)]
+    events: bool,
+
+    /// Exits with a non-zero status code if errors were found
+    #[structopt(long)]
+    strict: bool,
+
+    /// Only validates the data of this percentage of randomly selected chunks and
+    /// estimates the errors of the whole world from them. The headers are still checked completely
+    #[structopt(long)]
+    sample: Option<f64>,
+
+    /// The seed used to select the sampled chunks
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+
+    /// Aborts on the first region file that can't be scanned instead of skipping it
+    #[structopt(long)]
+    fail_fast: bool,
+
+    /// Overwrites the space freed by fixing with zeros
+    #[structopt(long)]
+    wipe_free_space: bool,
+
+    /// Deletes chunks that aren't fully generated
+    #[structopt(long)]
+    delete_protochunks: bool,
+
+    /// Copies region files into this directory before they are modified
+    #[structopt(long, alias = "backup-dir", parse(from_os_str))]
+    backup: Option<PathBuf>,
+
+    /// Copies region files to a .bak file next to them before they are modified
+    #[structopt(long, conflicts_with = "backup")]
+    backup_in_place: bool,
+
+    /// Writes the fixed region files into this directory keeping the layout of the world
+    /// instead of modifying the world itself
+    #[structopt(long, parse(from_os_str))]
+    output_dir: Option<PathBuf>,
+
+    /// Runs all fixes on copies of the region files in memory and reports what would
+    /// be changed without modifying anything on disk
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Restores the modification time of fixed region files so that incremental
+    /// backups don't copy them again
+    #[structopt(long)]
+    preserve_mtime: bool,
+
+    /// Writes every modification made to the region files into this file, one per line
+    #[structopt(long, parse(from_os_str))]
+    repair_log: Option<PathBuf>,
+
+    /// Fixes region files in a temporary copy that replaces the original once it succeeded
+    #[structopt(long)]
+    atomic: bool,
+
+    /// The tags chunks need to contain like xPos,zPos,Status instead of the ones
+    /// required by the format of the chunk. Useful for worlds of modded or unusual versions
+    #[structopt(long, use_delimiter = true)]
+    required_tags: Option<Vec<String>>,
+
+    /// Reads scan options from this toml file instead of the regions-tool.toml in the world folder.
+    /// Options given on the command line override the ones of the file
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct VerifyArgs {
+    /// The folder containing the region files to verify
+    #[structopt(long, default_value = "region", possible_values = &["region", "entities", "poi", "all"])]
+    folder: String,
+
+    /// The dimension to verify
+    #[structopt(long, default_value = "overworld", possible_values = &["overworld", "nether", "end", "all"])]
+    dimension: String,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct WatchArgs {
+    /// The folder containing the region files to watch
+    #[structopt(long, default_value = "region", possible_values = &["region", "entities", "poi", "all"])]
+    folder: String,
+
+    /// The dimension to watch
+    #[structopt(long, default_value = "overworld", possible_values = &["overworld", "nether", "end", "all"])]
+    dimension: String,
+
+    /// The number of seconds a file needs to stay unchanged before it's verified.
+    /// The game writes to the files repeatedly while saving
+    #[structopt(long, default_value = "2")]
+    delay: u64,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct DefragArgs {
+    /// The dimension to defragment
+    #[structopt(long, default_value = "overworld", possible_values = &["overworld", "nether", "end", "all"])]
+    dimension: String,
+
+    /// Copies region files into this directory before they are modified
+    #[structopt(long, parse(from_os_str))]
+    backup: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct RecompressArgs {
+    /// The compression level from 0 to 9. Higher levels shrink the world further but take longer
+    #[structopt(long, default_value = "9", possible_values = &["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"])]
+    compression_level: u32,
+
+    /// The dimension to recompress
+    #[structopt(long, default_value = "overworld", possible_values = &["overworld", "nether", "end", "all"])]
+    dimension: String,
+
+    /// Copies region files into this directory before they are modified
+    #[structopt(long, parse(from_os_str))]
+    backup: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct PruneArgs {
+    /// Chunks with an inhabited time of at most this many ticks are deleted.
+    /// Defaults to the max_inhabited_ticks of the config file or 0
+    #[structopt(long)]
+    max_inhabited_ticks: Option<i64>,
+
+    /// The dimension to prune
+    #[structopt(long, default_value = "overworld", possible_values = &["overworld", "nether", "end", "all"])]
+    dimension: String,
+
+    /// Copies region files into this directory before they are modified
+    #[structopt(long, parse(from_os_str))]
+    backup: Option<PathBuf>,
+
+    /// Reads the max_inhabited_ticks from this toml file instead of the regions-tool.toml in the world folder
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(setting = AppSettings::AllowNegativeNumbers)]
+struct TrimArgs {
+    /// The x and z chunk coordinates of the center of the area that is kept
+    #[structopt(long, number_of_values = 2, value_names = &["x", "z"])]
+    center: Vec<i32>,
+
+    /// The radius in chunks of the area that is kept
+    #[structopt(long)]
+    radius: u32,
+
+    /// The dimension to trim
+    #[structopt(long, default_value = "overworld", possible_values = &["overworld", "nether", "end", "all"])]
+    dimension: String,
+
+    /// Copies region files into this directory before they are modified
+    #[structopt(long, parse(from_os_str))]
+    backup: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct ListArgs {
+    /// The dimension to list the chunks of
+    #[structopt(long, default_value = "overworld", possible_values = &["overworld", "nether", "end", "all"])]
+    dimension: String,
+
+    /// The format of the list. csv also contains the size and timestamp of each chunk
+    #[structopt(long, default_value = "text", possible_values = &["text", "csv"])]
+    format: String,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct DiffArgs {
+    /// Path to the world folder that is compared with the input
+    #[structopt(parse(from_os_str))]
+    other: PathBuf,
+
+    /// The dimension to compare
+    #[structopt(long, default_value = "overworld", possible_values = &["overworld", "nether", "end", "all"])]
+    dimension: String,
+
+    /// The folder containing the region files to compare
+    #[structopt(long, default_value = "region", possible_values = &["region", "entities", "poi", "all"])]
+    folder: String,
+
+    /// Prints the coordinates of each differing chunk
+    #[structopt(long)]
+    list: bool,
+
+    /// Compares the decompressed nbt data of the chunks instead of their timestamp and length
+    #[structopt(long)]
+    deep: bool,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct ExportSizesArgs {
+    /// The dimension to export the chunk sizes of
+    #[structopt(long, default_value = "overworld", possible_values = &["overworld", "nether", "end", "all"])]
+    dimension: String,
+
+    /// The format of the export. json groups the chunks by their dimension
+    #[structopt(long, default_value = "csv", possible_values = &["csv", "json"])]
+    format: String,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct StatusReportArgs {
+    /// The dimension to report the chunk statuses of
+    #[structopt(long, default_value = "overworld", possible_values = &["overworld", "nether", "end", "all"])]
+    dimension: String,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct SizeArgs {
+    /// The dimension to report the size of
+    #[structopt(long, default_value = "overworld", possible_values = &["overworld", "nether", "end", "all"])]
+    dimension: String,
+
+    /// The folder containing the region files
+    #[structopt(long, default_value = "region", possible_values = &["region", "entities", "poi", "all"])]
+    folder: String,
+
+    /// The number of the largest files that are printed
+    #[structopt(long, default_value = "10")]
+    largest: usize,
+
+    /// The number of the largest chunks that are printed. Only reads the header of each chunk
+    #[structopt(long, default_value = "0")]
+    top: usize,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct MapArgs {
+    /// The dimension to render
+    #[structopt(long, default_value = "overworld", possible_values = &["overworld", "nether", "end"])]
+    dimension: String,
+
+    /// The value of the chunks that decides their color
+    #[structopt(long, default_value = "presence", possible_values = &["presence", "inhabited", "size"])]
+    metric: String,
+
+    /// The png file the map is written to
+    #[structopt(parse(from_os_str))]
+    output: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct ChecksumArgs {
+    /// The dimension to compute the checksums of
+    #[structopt(long, default_value = "overworld", possible_values = &["overworld", "nether", "end", "all"])]
+    dimension: String,
+
+    /// The folder containing the region files
+    #[structopt(long, default_value = "region", possible_values = &["region", "entities", "poi", "all"])]
+    folder: String,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct InfoArgs {
+    /// The number of location entries that are printed
+    #[structopt(long, default_value = "10")]
+    entries: usize,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct RepairHeaderArgs {
+    /// Copies the region file into this directory before its header is rewritten
+    #[structopt(long, parse(from_os_str))]
+    backup: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(setting = AppSettings::AllowNegativeNumbers)]
+struct DumpArgs {
+    /// The x coordinate of the chunk
+    x: isize,
+
+    /// The z coordinate of the chunk
+    z: isize,
+
+    /// The format of the nbt data. tagged keeps the type of each value
+    /// so that the data can be converted back into nbt. snbt uses the syntax of commands.
+    /// tree prints one tag per line with its type and summarizes arrays by their length
+    #[structopt(
+        long,
+        default_value = "json",
+        possible_values = &["json", "tagged", "snbt", "tree"]
+    )]
+    format: String,
 }
 
 fn main() {
     let opt: Opt = Opt::from_args();
-    build_logger(opt.verbose);
-    let world = WorldFolder::new(opt.input);
+    build_logger(opt.verbose, opt.quiet, opt.log_file.as_deref());
+    let progress = !opt.no_progress && !opt.quiet;
     match opt.sub_command {
-        SubCommand::Count => log::info!("Chunk Count: {}", world.count_chunks().unwrap()),
-        SubCommand::Scan(opt) => {
-            if opt.fix {
+        SubCommand::Count(args) => {
+            let world = WorldFolder::new(opt.input.clone())
+                .dimensions(parse_dimensions(&args.dimension, &opt.input))
+                .max_file_size(args.max_file_size);
+            let region_folders = world.region_folders();
+            if args.per_file {
+                let mut counts = world
+                    .count_chunks_per_file()
+                    .unwrap_or_else(|e| exit_with_error(e));
+                counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+                for (path, count) in counts {
+                    log::info!(
+                        "{}: {} chunks",
+                        region_file_name(&path, &opt.input).display(),
+                        count
+                    );
+                }
+            }
+            if region_folders.len() > 1 {
+                for (dimension, _, _) in region_folders {
+                    let count = WorldFolder::new(opt.input.clone())
+                        .dimensions(vec![dimension.clone()])
+                        .max_file_size(args.max_file_size)
+                        .count_chunks()
+                        .unwrap_or_else(|e| exit_with_error(e));
+                    log::info!("Chunk Count for {}: {}", dimension.name(), count);
+                }
+            }
+            log::info!(
+                "Chunk Count: {}",
+                world.count_chunks().unwrap_or_else(|e| exit_with_error(e))
+            )
+        }
+        SubCommand::Scan(args) => {
+            let config = read_config(args.config.as_deref(), &opt.input);
+            let defaults = config.apply(ScanOptions::new());
+            let fix = args.fix || (defaults.fix && !args.no_fix);
+            let delete = args.delete || (defaults.fix_delete && !args.no_delete);
+            let threads = args.threads.unwrap_or(defaults.threads);
+            let compression_level = args.compression_level.unwrap_or(defaults.compression_level);
+            let required_tags = args
+                .required_tags
+                .clone()
+                .or_else(|| defaults.required_tags.clone());
+            let dimension = args
+                .dimension
+                .clone()
+                .or_else(|| config.dimension.clone())
+                .unwrap_or_else(|| String::from("overworld"));
+            let world_info = match WorldInfo::read(&opt.input) {
+                Ok(info) => {
+                    log::info!("World:{}", info);
+                    Some(info)
+                }
+                Err(e) => {
+                    log::warn!("Failed to read the level.dat of the world: {}", e);
+                    None
+                }
+            };
+            if fix {
                 log::info!("Fixing fixable errors.");
             }
+            if args.dry_run {
+                log::info!("Dry run: no files will be modified.");
+            }
             log::info!("Scanning Region files for errors...");
-            log::info!(
-                "Scan Results:\n{}",
+            let options = defaults
+                .world_time(world_info.as_ref().and_then(|info| info.time))
+                .data_version(world_info.as_ref().and_then(|info| info.data_version))
+                .fix(fix)
+                .fix_delete(delete)
+                .backup(args.backup.clone())
+                .backup_in_place(args.backup_in_place)
+                .output_dir(args.output_dir.clone())
+                .atomic(args.atomic)
+                .wipe_free_space(args.wipe_free_space)
+                .delete_protochunks(args.delete_protochunks)
+                .threads(threads)
+                .progress(progress)
+                .in_memory(args.in_memory)
+                .count_entities(args.count_entities)
+                .top_chunks(args.top)
+                .entity_histogram(args.entity_histogram)
+                .inventory(args.inventory)
+                .fix_entities(args.fix_entities)
+                .fix_tile_entities(args.fix_tile_entities)
+                .fix_compression(args.fix_compression)
+                .recompress(args.recompress)
+                .collect_events(args.events)
+                .compression_level(compression_level)
+                .max_nbt_depth(args.max_nbt_depth)
+                .max_file_size(args.max_file_size)
+                .io_retries(args.io_retries)
+                .preserve_mtime(args.preserve_mtime)
+                .dry_run(args.dry_run)
+                .sample(args.sample.map(|percent| percent / 100.0))
+                .seed(args.seed)
+                .fail_fast(args.fail_fast)
+                .checks(args.only.clone().unwrap_or_else(ScanCheck::all))
+                .required_tags(required_tags);
+            if args.output_dir.is_some() && !options.modifies_files() {
+                exit_with_error("An output directory can only be used while fixing the world");
+            }
+            if let Some(percent) = args.sample {
+                if !(percent > 0.0 && percent <= 100.0) {
+                    exit_with_error("The sample needs to be a percentage above 0 and up to 100");
+                }
+                // chunks outside of the sample would silently stay broken
+                if options.modifies_files() {
+                    exit_with_error("A sample of chunks can't be scanned while fixing the world");
+                }
+            }
+            if options.writes_files() {
+                ensure_world_unused(&opt.input, opt.force);
+            }
+            let json = args.format == "json";
+            let json_lines = args.format == "jsonl";
+            if args.per_file && !json {
+                exit_with_error("The results of each file can only be printed as json");
+            }
+            let file_results = Arc::new(Mutex::new(Vec::new()));
+            let options = if json && args.per_file {
+                let file_results = Arc::clone(&file_results);
+                options.on_file_scanned(move |path, statistic| {
+                    file_results
+                        .lock()
+                        .unwrap()
+                        .push((path.to_path_buf(), statistic.clone()));
+                })
+            } else if json_lines {
+                options.on_file_scanned(|path, statistic| {
+                    let line = serde_json::json!({ "path": path, "statistic": statistic });
+                    // the lock keeps the lines of files scanned in parallel apart
+                    let stdout = io::stdout();
+                    let mut stdout = stdout.lock();
+                    let _ = writeln!(stdout, "{}", line);
+                })
+            } else {
+                options
+            };
+            let bounds = args
+                .region_bounds
+                .or_else(|| args.chunk_bounds.map(RegionBounds::from_chunk_bounds));
+            let world = WorldFolder::new(opt.input.clone())
+                .dimensions(parse_dimensions(&dimension, &opt.input))
+                .folders(parse_folders(&args.folder))
+                .regions(args.regions.clone())
+                .bounds(bounds);
+            let region_folders = world.region_folders();
+            let is_single_file = opt.input.is_file() && parse_region_coords(&opt.input).is_some();
+            if delete && !args.yes && !args.dry_run {
+                let file_count = if is_single_file {
+                    1
+                } else {
+                    world
+                        .count_region_files()
+                        .unwrap_or_else(|e| exit_with_error(e))
+                };
+                confirm_delete(file_count);
+            }
+            // scripts need to be able to tell io errors apart from corrupted chunks
+            let io_error_code = if args.strict { 2 } else { 1 };
+
+            let mut statistic = if is_single_file {
+                // allows inspecting a single file without the layout of a world folder
+                WorldFolder::scan_single_file(&opt.input, options)
+                    .unwrap_or_else(|e| exit_with_error_code(e, io_error_code))
+            } else if region_folders.len() > 1 {
+                // scan each folder separately to report the results for each one
+                let mut statistic = ScanStatistics::new();
+                for (dimension, folder, _) in region_folders {
+                    let folder_statistic = WorldFolder::new(opt.input.clone())
+                        .dimensions(vec![dimension.clone()])
+                        .folders(vec![folder])
+                        .regions(args.regions.clone())
+                        .bounds(bounds)
+                        .scan_files(options.clone())
+                        .unwrap_or_else(|e| exit_with_error_code(e, io_error_code));
+                    if !json && !json_lines {
+                        log::info!(
+                            "Scan Results for {}/{}:\n{}",
+                            dimension.name(),
+                            folder.dir_name(),
+                            folder_statistic
+                        );
+                    }
+                    statistic = statistic + folder_statistic;
+                }
+                statistic
+            } else {
                 world
-                    .scan_files(ScanOptions::new().fix(opt.fix).fix_delete(opt.delete))
-                    .unwrap()
-            )
+                    .scan_files(options)
+                    .unwrap_or_else(|e| exit_with_error_code(e, io_error_code))
+            };
+            if json && args.per_file {
+                let mut files = file_results.lock().unwrap().clone();
+                files.sort_by(|(path_a, a), (path_b, b)| {
+                    b.total_errors()
+                        .cmp(&a.total_errors())
+                        .then_with(|| path_a.cmp(path_b))
+                });
+                let files: Vec<_> = files
+                    .iter()
+                    .map(|(path, statistic)| serde_json::json!({ "path": path, "statistic": statistic }))
+                    .collect();
+                let output = serde_json::json!({ "total": statistic, "files": files });
+                println!("{}", serde_json::to_string_pretty(&output).unwrap());
+            } else if json {
+                println!("{}", serde_json::to_string_pretty(&statistic).unwrap());
+            } else if json_lines {
+                // the events were already printed with the results of their files
+                statistic.events.clear();
+                println!("{}", serde_json::json!({ "total": statistic }));
+            } else {
+                for event in &statistic.events {
+                    log::info!("{}", event);
+                }
+                log::info!("Scan Results:\n{}", statistic);
+                if args.count_entities {
+                    log::info!(
+                        "Entities: {}, Block Entities: {}",
+                        statistic.entities,
+                        statistic.block_entities
+                    );
+                    for chunk in &statistic.densest_chunks {
+                        log::info!(
+                            "Chunk ({}, {}): {} entities, {} block entities",
+                            chunk.x,
+                            chunk.z,
+                            chunk.entities,
+                            chunk.block_entities
+                        );
+                    }
+                }
+                for chunk in &statistic.largest_chunks {
+                    log::info!(
+                        "Chunk ({}, {}): {}",
+                        chunk.x,
+                        chunk.z,
+                        format_bytes(chunk.length as u64)
+                    );
+                }
+                if args.entity_histogram {
+                    let mut entity_types: Vec<_> = statistic.entity_types.iter().collect();
+                    entity_types.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+                    for (id, count) in entity_types {
+                        log::info!("{}: {}", id, count);
+                    }
+                }
+                if args.inventory {
+                    let mut namespaces: Vec<_> = statistic.namespaces.iter().collect();
+                    namespaces.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+                    for (namespace, count) in namespaces {
+                        log::info!("{}: {} chunks", namespace, count);
+                    }
+                }
+            }
+
+            if let Some(path) = &args.repair_log {
+                write_repair_log(path, &statistic, &opt.input).unwrap_or_else(|e| {
+                    exit_with_error(format!("Failed to write the repair log {:?}: {}", path, e))
+                });
+            }
+
+            if args.report_clean {
+                if let Some((level, message)) = statistic.clean_report() {
+                    log::log!(level, "{}", message);
+                }
+            }
+
+            if args.strict {
+                if statistic.skipped_files > 0 {
+                    process::exit(2);
+                } else if statistic.total_errors() > 0 {
+                    process::exit(1);
+                }
+            }
+        }
+        SubCommand::Verify(args) => {
+            log::info!("Verifying region files...");
+            // the default options never open the files with write access
+            let options = ScanOptions::new().progress(progress);
+            let statistic = WorldFolder::new(opt.input.clone())
+                .dimensions(parse_dimensions(&args.dimension, &opt.input))
+                .folders(parse_folders(&args.folder))
+                .scan_files(options)
+                .unwrap_or_else(|e| exit_with_error_code(e, 2));
+            log::info!("Verification Results:\n{}", statistic);
+
+            if statistic.skipped_files > 0 {
+                process::exit(2);
+            } else if statistic.total_errors() > 0 {
+                process::exit(1);
+            }
+        }
+        SubCommand::Watch(args) => {
+            // the default options never open the files with write access
+            let options = ScanOptions::new().progress(false).collect_events(true);
+            let mut scans = 0u64;
+            let mut total_errors = 0u64;
+            let input = opt.input.clone();
+            WorldFolder::new(opt.input.clone())
+                .dimensions(parse_dimensions(&args.dimension, &opt.input))
+                .folders(parse_folders(&args.folder))
+                .watch(options, Duration::from_secs(args.delay), |path, result| {
+                    let name = region_file_name(path, &input).display();
+                    let statistic = match result {
+                        Ok(statistic) => statistic,
+                        Err(e) => {
+                            log::warn!("Failed to verify {}: {}", name, e);
+                            return;
+                        }
+                    };
+                    scans += 1;
+                    total_errors += statistic.total_errors();
+                    for event in &statistic.events {
+                        log::warn!("{}: {}", name, event);
+                    }
+                    log::info!(
+                        "{}: {} errors in {} chunks ({} errors in {} scans so far)",
+                        name,
+                        statistic.total_errors(),
+                        statistic.total_chunks,
+                        total_errors,
+                        scans
+                    );
+                })
+                .unwrap_or_else(|e| exit_with_error(e));
+        }
+        SubCommand::Dump(args) => {
+            let input = &opt.input;
+            let mut region_file = RegionFile::open_read_only(input)
+                .unwrap_or_else(|e| exit_with_file_error(input, e));
+            let data = region_file
+                .read_chunk_nbt(args.x, args.z)
+                .unwrap_or_else(|e| exit_with_file_error(input, e));
+            match data {
+                Some(data) if args.format == "snbt" => {
+                    println!("{}", NBTValue::Compound(data).to_snbt())
+                }
+                Some(data) if args.format == "tree" => {
+                    println!("{}", NBTValue::Compound(data).to_tree())
+                }
+                Some(data) if args.format == "tagged" => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&TaggedNBTValue::from(NBTValue::Compound(data)))
+                        .unwrap()
+                ),
+                Some(data) => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&NBTValue::Compound(data)).unwrap()
+                ),
+                None => {
+                    log::error!(
+                        "Chunk ({}, {}) is not present in {:?}",
+                        args.x,
+                        args.z,
+                        input
+                    );
+                    process::exit(1);
+                }
+            }
+        }
+        SubCommand::Defrag(args) => {
+            ensure_world_unused(&opt.input, opt.force);
+            log::info!("Closing gaps between chunks...");
+            let options = ScanOptions::new()
+                .defrag(true)
+                .skip_nbt(true)
+                .backup(args.backup)
+                .progress(progress);
+            let statistic = WorldFolder::new(opt.input.clone())
+                .dimensions(parse_dimensions(&args.dimension, &opt.input))
+                .folders(RegionFolder::all())
+                .scan_files(options)
+                .unwrap_or_else(|e| exit_with_error(e));
+            log::info!(
+                "Closed {} of gaps and reclaimed {}",
+                format_bytes(statistic.unused_space),
+                format_bytes(statistic.shrunk_size)
+            );
+        }
+        SubCommand::Recompress(args) => {
+            ensure_world_unused(&opt.input, opt.force);
+            log::info!(
+                "Recompressing chunks with level {}...",
+                args.compression_level
+            );
+            let options = ScanOptions::new()
+                .compression_level(args.compression_level)
+                .backup(args.backup);
+            let (saved, statistic) = WorldFolder::new(opt.input.clone())
+                .dimensions(parse_dimensions(&args.dimension, &opt.input))
+                .folders(RegionFolder::all())
+                .recompress_files(options)
+                .unwrap_or_else(|e| exit_with_error(e));
+            log::info!(
+                "Recompressed {} chunks, the chunk data shrunk by {} bytes and {} were reclaimed",
+                statistic.total_chunks,
+                saved,
+                format_bytes(statistic.shrunk_size)
+            );
+        }
+        SubCommand::Prune(args) => {
+            ensure_world_unused(&opt.input, opt.force);
+            let max_inhabited_ticks = args
+                .max_inhabited_ticks
+                .or(read_config(args.config.as_deref(), &opt.input).max_inhabited_ticks)
+                .unwrap_or(0);
+            log::info!(
+                "Pruning chunks with an inhabited time of at most {} ticks...",
+                max_inhabited_ticks
+            );
+            let options = ScanOptions::new()
+                .prune(Some(max_inhabited_ticks))
+                .backup(args.backup)
+                .progress(progress);
+            let statistic = WorldFolder::new(opt.input.clone())
+                .dimensions(parse_dimensions(&args.dimension, &opt.input))
+                .scan_files(options)
+                .unwrap_or_else(|e| exit_with_error(e));
+            log::info!(
+                "Pruned {} of {} chunks ({})",
+                statistic.pruned_chunks,
+                statistic.total_chunks,
+                format_bytes(statistic.pruned_space)
+            );
+        }
+        SubCommand::Trim(args) => {
+            ensure_world_unused(&opt.input, opt.force);
+            let (center_x, center_z) = match args.center.as_slice() {
+                [x, z] => (*x, *z),
+                _ => (0, 0),
+            };
+            log::info!(
+                "Trimming chunks further than {} chunks away from ({}, {})...",
+                args.radius,
+                center_x,
+                center_z
+            );
+            let options = ScanOptions::new()
+                .trim(Some(TrimArea::new(center_x, center_z, args.radius)))
+                .backup(args.backup)
+                .progress(progress);
+            let statistic = WorldFolder::new(opt.input.clone())
+                .dimensions(parse_dimensions(&args.dimension, &opt.input))
+                .folders(RegionFolder::all())
+                .scan_files(options)
+                .unwrap_or_else(|e| exit_with_error(e));
+            log::info!(
+                "Trimmed {} of {} chunks ({})",
+                statistic.pruned_chunks,
+                statistic.total_chunks,
+                format_bytes(statistic.pruned_space)
+            );
+        }
+        SubCommand::List(args) => {
+            let csv = args.format == "csv";
+            if csv {
+                println!("dimension,x,z,sectors,size,timestamp");
+            }
+            for dimension in parse_dimensions(&args.dimension, &opt.input) {
+                let chunks = WorldFolder::new(opt.input.clone())
+                    .dimensions(vec![dimension.clone()])
+                    .list_chunks()
+                    .unwrap_or_else(|e| exit_with_error(e));
+
+                for (x, z, sectors, timestamp) in chunks {
+                    if csv {
+                        println!(
+                            "{},{},{},{},{},{}",
+                            dimension.name(),
+                            x,
+                            z,
+                            sectors,
+                            sectors as usize * BLOCK_SIZE,
+                            timestamp
+                        );
+                    } else {
+                        println!("{},{}", x, z);
+                    }
+                }
+            }
+        }
+        SubCommand::Diff(args) => {
+            let mut differences = Vec::new();
+
+            for dimension in parse_dimensions(&args.dimension, &opt.input) {
+                let folders = parse_folders(&args.folder);
+                let dimension_differences = WorldFolder::new(opt.input.clone())
+                    .dimensions(vec![dimension.clone()])
+                    .folders(folders.clone())
+                    .diff(
+                        &WorldFolder::new(args.other.clone())
+                            .dimensions(vec![dimension.clone()])
+                            .folders(folders),
+                        args.deep,
+                    )
+                    .unwrap_or_else(|e| exit_with_error(e));
+                differences.extend(
+                    dimension_differences
+                        .into_iter()
+                        .map(|(x, z, difference)| (dimension.clone(), x, z, difference)),
+                );
+            }
+            if args.list {
+                for (dimension, x, z, difference) in &differences {
+                    println!("{},{},{},{}", dimension.name(), x, z, difference);
+                }
+            }
+            let count = |kind: ChunkDifference| {
+                differences
+                    .iter()
+                    .filter(|(_, _, _, difference)| *difference == kind)
+                    .count()
+            };
+            log::info!(
+                "Chunks added: {}, removed: {}, changed: {}",
+                count(ChunkDifference::Added),
+                count(ChunkDifference::Removed),
+                count(ChunkDifference::Changed)
+            );
+        }
+        SubCommand::ExportSizes(args) => {
+            let json = args.format == "json";
+            let mut dimension_sizes = BTreeMap::new();
+            if !json {
+                println!("dimension,region_x,region_z,x,z,sectors,length,compression,timestamp");
+            }
+            for dimension in parse_dimensions(&args.dimension, &opt.input) {
+                let sizes = WorldFolder::new(opt.input.clone())
+                    .dimensions(vec![dimension.clone()])
+                    .chunk_sizes()
+                    .unwrap_or_else(|e| exit_with_error(e));
+
+                if json {
+                    dimension_sizes.insert(dimension.name().to_string(), sizes);
+                    continue;
+                }
+                for size in sizes {
+                    println!(
+                        "{},{},{},{},{},{},{},{},{}",
+                        dimension.name(),
+                        size.region_x,
+                        size.region_z,
+                        size.x,
+                        size.z,
+                        size.sectors,
+                        size.length,
+                        size.compression_type,
+                        size.timestamp
+                    );
+                }
+            }
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&dimension_sizes).unwrap()
+                );
+            }
+        }
+        SubCommand::StatusReport(args) => {
+            let statuses = WorldFolder::new(opt.input.clone())
+                .dimensions(parse_dimensions(&args.dimension, &opt.input))
+                .count_statuses()
+                .unwrap_or_else(|e| exit_with_error(e));
+            let mut statuses: Vec<(String, u64)> = statuses.into_iter().collect();
+            statuses.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            let max_count = statuses.first().map(|(_, count)| *count).unwrap_or(0);
+            let name_width = statuses.iter().map(|(s, _)| s.len()).max().unwrap_or(0);
+
+            for (status, count) in statuses {
+                // the bar of the most common status has a length of 50
+                let bar = "#".repeat((count * 50 / max_count) as usize);
+                log::info!(
+                    "{:<width$} {:>8} {}",
+                    status,
+                    count,
+                    bar,
+                    width = name_width
+                );
+            }
+        }
+        SubCommand::Size(args) => {
+            let dimensions = parse_dimensions(&args.dimension, &opt.input);
+            let mut sizes = Vec::new();
+
+            for dimension in &dimensions {
+                let dimension_sizes = WorldFolder::new(opt.input.clone())
+                    .dimensions(vec![dimension.clone()])
+                    .folders(parse_folders(&args.folder))
+                    .file_sizes()
+                    .unwrap_or_else(|e| exit_with_error(e));
+                log_file_sizes(dimension.name(), &dimension_sizes);
+                sizes.extend(dimension_sizes);
+            }
+            if dimensions.len() > 1 {
+                sizes.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+                log_file_sizes("total", &sizes);
+            }
+            for (path, size) in sizes.iter().take(args.largest) {
+                log::info!(
+                    "{}: {}",
+                    region_file_name(path, &opt.input).display(),
+                    format_bytes(*size)
+                );
+            }
+            if args.top > 0 {
+                let mut chunk_sizes = WorldFolder::new(opt.input.clone())
+                    .dimensions(dimensions)
+                    .folders(parse_folders(&args.folder))
+                    .chunk_sizes()
+                    .unwrap_or_else(|e| exit_with_error(e));
+                chunk_sizes.sort_by_key(|chunk| std::cmp::Reverse(chunk.length));
+
+                for chunk in chunk_sizes.iter().take(args.top) {
+                    log::info!(
+                        "Chunk ({}, {}): {}",
+                        chunk.x,
+                        chunk.z,
+                        format_bytes(chunk.length as u64)
+                    );
+                }
+            }
+        }
+        SubCommand::Map(args) => {
+            let world = WorldFolder::new(opt.input.clone())
+                .dimensions(parse_dimensions(&args.dimension, &opt.input));
+            let metric: MapMetric = args.metric.parse().unwrap();
+            let map = ChunkMap::from_world(&world, metric)
+                .unwrap_or_else(|e| exit_with_error(e))
+                .unwrap_or_else(|| {
+                    exit_with_error("The dimension doesn't contain any region files")
+                });
+            map.save(&args.output)
+                .unwrap_or_else(|e| exit_with_error(e));
+            let (width, height) = map.dimensions();
+            let (x, z) = map.origin();
+            log::info!(
+                "Wrote a {}x{} map starting at chunk ({}, {}) to {:?}",
+                width,
+                height,
+                x,
+                z,
+                args.output
+            );
+        }
+        SubCommand::Checksum(args) => {
+            let checksums = WorldFolder::new(opt.input.clone())
+                .dimensions(parse_dimensions(&args.dimension, &opt.input))
+                .folders(parse_folders(&args.folder))
+                .checksums()
+                .unwrap_or_else(|e| exit_with_error(e));
+
+            for (name, checksum) in &checksums.files {
+                let path = opt.input.join(name);
+                let name = region_file_name(&path, &opt.input).display();
+                match checksum {
+                    Ok(checksum) => println!("{}: {}", name, format_hex(checksum)),
+                    Err(e) => log::error!("{}: {}", name, e),
+                }
+            }
+            match checksums.world {
+                Some(checksum) => println!("world: {}", format_hex(&checksum)),
+                None => {
+                    exit_with_error("The world checksum is missing since files couldn't be read")
+                }
+            }
+        }
+        SubCommand::Info(args) => {
+            let input = &opt.input;
+            let region_file = RegionFile::open_read_only(input)
+                .unwrap_or_else(|e| exit_with_file_error(input, e));
+            let length = region_file.length();
+            let entries = region_file.locations().valid_entries_enumerate();
+            let used_sectors: u64 = entries.iter().map(|(_, (_, s))| *s as u64).sum();
+            let file_sectors = length / BLOCK_SIZE as u64;
+            // the header occupies the first two sectors
+            let wasted_sectors = file_sectors.saturating_sub(used_sectors + 2);
+
+            log::info!("File size: {} bytes", length);
+            log::info!(
+                "Multiple of {} bytes: {}",
+                BLOCK_SIZE,
+                length.is_multiple_of(BLOCK_SIZE as u64)
+            );
+            log::info!("Location entries: {}", entries.len());
+            if let (Some(min), Some(max)) = (
+                entries.iter().map(|(_, (o, _))| o).min(),
+                entries.iter().map(|(_, (o, _))| o).max(),
+            ) {
+                log::info!("Offsets: {} - {}", min, max);
+            }
+            log::info!(
+                "Sectors used: {} of {} ({} wasted)",
+                used_sectors,
+                file_sectors,
+                format_bytes(wasted_sectors * BLOCK_SIZE as u64)
+            );
+            log::info!(
+                "{:>5} {:>4} {:>4} {:>8} {:>7} {:>10}",
+                "index",
+                "x",
+                "z",
+                "offset",
+                "sectors",
+                "timestamp"
+            );
+
+            for (index, (offset, sectors)) in entries.into_iter().take(args.entries) {
+                log::info!(
+                    "{:>5} {:>4} {:>4} {:>8} {:>7} {:>10}",
+                    index,
+                    index % 32,
+                    index / 32,
+                    offset,
+                    sectors,
+                    region_file.timestamps().get(index)
+                );
+            }
+        }
+        SubCommand::RepairHeader(args) => {
+            let input = &opt.input;
+            // region files are stored in a folder inside of the world folder
+            if let Some(world_path) = input.parent().and_then(Path::parent) {
+                ensure_world_unused(world_path, opt.force);
+            }
+            let mut region_file =
+                RegionFile::new(input).unwrap_or_else(|e| exit_with_file_error(input, e));
+            region_file.set_backup(
+                args.backup
+                    .zip(input.file_name())
+                    .map(|(backup, name)| backup.join(name)),
+            );
+            let recovered = region_file
+                .rebuild_locations()
+                .unwrap_or_else(|e| exit_with_error(e));
+            log::info!("Recovered {} chunks in {:?}", recovered, input);
         }
     }
 }
 
-fn build_logger(verbose: bool) {
-    env_logger::Builder::from_env(Env::default().default_filter_or(if verbose {
-        "debug"
+/// Exits if the world is loaded by a running game or server since modifying
+/// the region files while they're written corrupts the world
+fn ensure_world_unused(world_path: &Path, force: bool) {
+    if force {
+        return;
+    }
+    match WorldFolder::new(world_path.to_path_buf()).is_in_use() {
+        Ok(false) => {}
+        Ok(true) => exit_with_error(
+            "The world is in use by a running game or server. Stop it first or pass --force",
+        ),
+        Err(e) => log::warn!("Failed to check if the world is in use: {}", e),
+    }
+}
+
+/// Reads the given config file or the one in the world folder if there is one
+fn read_config(path: Option<&Path>, world_path: &Path) -> ScanConfig {
+    match path {
+        Some(path) => ScanConfig::from_file(path),
+        None => ScanConfig::discover(world_path).map(Option::unwrap_or_default),
+    }
+    .unwrap_or_else(|e| exit_with_error(e))
+}
+
+/// Asks the user to confirm deleting corrupted data and exits if they don't.
+/// Exits without asking if stdin isn't a terminal since nobody could answer
+fn confirm_delete(file_count: usize) {
+    if !io::stdin().is_terminal() {
+        exit_with_error("Deleting requires confirmation. Pass --yes to delete without a prompt");
+    }
+    eprint!(
+        "This will modify {} region files and delete corrupted chunks, continue? [y/N] ",
+        file_count
+    );
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .unwrap_or_else(|e| exit_with_error(e));
+
+    if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        log::info!("Aborted");
+        process::exit(1);
+    }
+}
+
+/// Logs the error and exits the process
+fn exit_with_error<E: std::fmt::Display>(error: E) -> ! {
+    exit_with_error_code(error, 1)
+}
+
+/// Logs the error with the region file it occurred in and exits the process
+fn exit_with_file_error<E: std::fmt::Display>(path: &Path, error: E) -> ! {
+    exit_with_error(format!("Failed to read region file {:?}: {}", path, error))
+}
+
+/// Logs the error and exits the process with the given status code
+fn exit_with_error_code<E: std::fmt::Display>(error: E, code: i32) -> ! {
+    log::error!("{}", error);
+    process::exit(code)
+}
+
+/// Logs the number, total, average and median size of the region files
+/// which are expected to be ordered by their size
+fn log_file_sizes(name: &str, sizes: &[(PathBuf, u64)]) {
+    if sizes.is_empty() {
+        log::info!("{}: no region files", name);
+        return;
+    }
+    let count = sizes.len() as u64;
+    let total: u64 = sizes.iter().map(|(_, size)| *size).sum();
+    let middle = sizes.len() / 2;
+    let median = if sizes.len().is_multiple_of(2) {
+        (sizes[middle - 1].1 + sizes[middle].1) / 2
+    } else {
+        sizes[middle].1
+    };
+
+    log::info!(
+        "{}: {} region files, {} total, {} average, {} median",
+        name,
+        count,
+        format_bytes(total),
+        format_bytes(total / count),
+        format_bytes(median)
+    );
+}
+
+/// Writes the modifications made to each region file into the file
+/// as lines of the file name followed by the modification
+fn write_repair_log(path: &Path, statistic: &ScanStatistics, world_path: &Path) -> io::Result<()> {
+    let mut file = LineWriter::new(
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?,
+    );
+    for (region_path, actions) in &statistic.repairs {
+        for action in actions {
+            writeln!(
+                file,
+                "{}: {}",
+                region_file_name(region_path, world_path).display(),
+                action
+            )?;
+        }
+    }
+
+    file.flush()
+}
+
+/// Returns the path of the region file relative to the region folder of the overworld.
+/// Files of other dimensions are shown relative to the world folder
+/// since their names are the same as the ones of the overworld
+fn region_file_name<'a>(path: &'a Path, world_path: &Path) -> &'a Path {
+    path.strip_prefix(world_path.join(RegionFolder::Region.dir_name()))
+        .or_else(|_| path.strip_prefix(world_path))
+        .unwrap_or(path)
+}
+
+/// Returns the dimensions selected by the dimension argument.
+/// `all` selects the vanilla and custom dimensions found in the world
+fn parse_dimensions(dimension: &str, world_path: &Path) -> Vec<Dimension> {
+    if dimension == "all" {
+        let dimensions: Vec<Dimension> = WorldFolder::new(world_path.to_path_buf())
+            .discover_region_dirs()
+            .unwrap_or_else(|e| exit_with_error(e))
+            .into_iter()
+            .map(|(dimension, _)| dimension)
+            .collect();
+        let names: Vec<&str> = dimensions.iter().map(|d| d.name()).collect();
+        log::info!("Dimensions: {}", names.join(", "));
+
+        dimensions
     } else {
-        "info"
+        vec![dimension.parse().unwrap()]
+    }
+}
+
+/// Returns the region folders selected by the folder argument
+fn parse_folders(folder: &str) -> Vec<RegionFolder> {
+    if folder == "all" {
+        RegionFolder::all()
+    } else {
+        vec![folder.parse().unwrap()]
+    }
+}
+
+fn build_logger(verbose: u8, quiet: bool, log_file: Option<&Path>) {
+    // colored already respects NO_COLOR and CLICOLOR_FORCE
+    // but doesn't check if the output is piped into a file
+    if env::var_os("CLICOLOR_FORCE").is_none() && !io::stderr().is_terminal() {
+        colored::control::set_override(false);
+    }
+    let level = match (quiet, verbose) {
+        (true, _) => "warn",
+        (false, 0) => "info",
+        (false, 1) => "debug",
+        _ => "trace",
+    };
+    let console = env_logger::Builder::from_env(Env::default().default_filter_or(level))
+        .format(|buf, record| {
+            let color = get_level_style(record.level());
+            writeln!(
+                buf,
+                "{}: {}",
+                record
+                    .level()
+                    .to_string()
+                    .to_lowercase()
+                    .as_str()
+                    .color(color),
+                record.args()
+            )
+        })
+        .build();
+    let log_file = match log_file {
+        Some(path) => OpenOptions::new().create(true).append(true).open(path),
+        None => {
+            log::set_max_level(console.filter());
+            log::set_boxed_logger(Box::new(console)).unwrap();
+            return;
+        }
+    };
+    let file = log_file.unwrap_or_else(|e| {
+        eprintln!("Failed to open the log file: {}", e);
+        process::exit(1)
+    });
+    log::set_max_level(console.filter().max(LevelFilter::Debug));
+    log::set_boxed_logger(Box::new(TeeLogger {
+        console,
+        // writing each line directly keeps the log complete when the process exits early
+        file: Mutex::new(LineWriter::new(file)),
     }))
-    .format(|buf, record| {
-        use std::io::Write;
-        let color = get_level_style(record.level());
-        writeln!(
-            buf,
-            "{}: {}",
-            record
-                .level()
-                .to_string()
-                .to_lowercase()
-                .as_str()
-                .color(color),
-            record.args()
-        )
-    })
-    .init();
+    .unwrap();
+}
+
+/// Logs to the console and writes all records up to the debug level
+/// without colors and with a timestamp into a file
+struct TeeLogger {
+    console: env_logger::Logger,
+    file: Mutex<LineWriter<std::fs::File>>,
+}
+
+impl Log for TeeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Debug || self.console.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.console.matches(record) {
+            self.console.log(record);
+        }
+        if record.level() <= Level::Debug {
+            if let Ok(mut file) = self.file.lock() {
+                let _ = writeln!(
+                    file,
+                    "{} {:<5} {}",
+                    humantime::format_rfc3339_seconds(SystemTime::now()),
+                    record.level(),
+                    record.args()
+                );
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
 }
 
 fn get_level_style(level: Level) -> colored::Color {
@@ -0,0 +1,156 @@
+use crate::world_folder::WorldFolder;
+use image::{ImageFormat, Rgb, RgbImage};
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The color of pixels without a chunk
+const COLOR_ABSENT: [u8; 3] = [0, 0, 0];
+/// The color of present chunks when only their presence is shown
+const COLOR_PRESENT: [u8; 3] = [80, 200, 80];
+/// The color of chunks with the lowest value of the metric
+const COLOR_LOW: [u8; 3] = [30, 40, 120];
+/// The color of chunks with the highest value of the metric
+const COLOR_HIGH: [u8; 3] = [255, 220, 60];
+
+/// The value of a chunk that decides its color on the map
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapMetric {
+    Presence,
+    /// The number of ticks players have spent in the chunk
+    Inhabited,
+    /// The compressed size of the chunk in bytes
+    Size,
+}
+
+impl FromStr for MapMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "presence" => Ok(Self::Presence),
+            "inhabited" => Ok(Self::Inhabited),
+            "size" => Ok(Self::Size),
+            _ => Err(format!("Unknown map metric {}", s)),
+        }
+    }
+}
+
+/// A map of the chunks of a world with one pixel per chunk
+pub struct ChunkMap {
+    min_x: i32,
+    min_z: i32,
+    width: u32,
+    height: u32,
+    metric: MapMetric,
+    values: Vec<Option<u64>>,
+}
+
+impl ChunkMap {
+    /// Reads the chunks of the world folder. The bounds of the map are taken from the
+    /// names of the region files. Returns `None` if the world doesn't have any region files
+    pub fn from_world(world: &WorldFolder, metric: MapMetric) -> io::Result<Option<Self>> {
+        let ((min_x, min_z), (max_x, max_z)) = match world.chunk_bounds()? {
+            Some(bounds) => bounds,
+            None => return Ok(None),
+        };
+        let chunks: Vec<(i32, i32, u64)> = match metric {
+            MapMetric::Presence => world
+                .list_chunks()?
+                .into_iter()
+                .map(|(x, z, _, _)| (x, z, 1))
+                .collect(),
+            MapMetric::Inhabited => world
+                .inhabited_times()?
+                .into_iter()
+                .map(|(x, z, ticks)| (x, z, ticks.max(0) as u64))
+                .collect(),
+            MapMetric::Size => world
+                .chunk_sizes()?
+                .into_iter()
+                .map(|size| (size.x, size.z, size.length as u64))
+                .collect(),
+        };
+        let width = (max_x - min_x + 1) as u32;
+        let height = (max_z - min_z + 1) as u32;
+        let mut map = Self {
+            min_x,
+            min_z,
+            width,
+            height,
+            metric,
+            values: vec![None; width as usize * height as usize],
+        };
+        for (x, z, value) in chunks {
+            map.set(x, z, value);
+        }
+
+        Ok(Some(map))
+    }
+
+    /// Returns the width and height of the map in chunks
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the chunk coordinates of the top left pixel
+    pub fn origin(&self) -> (i32, i32) {
+        (self.min_x, self.min_z)
+    }
+
+    /// Sets the value of the chunk at the given global coordinates.
+    /// Chunks outside of the map are ignored
+    fn set(&mut self, x: i32, z: i32, value: u64) {
+        let (column, row) = (x - self.min_x, z - self.min_z);
+
+        if column >= 0 && row >= 0 && (column as u32) < self.width && (row as u32) < self.height {
+            self.values[row as usize * self.width as usize + column as usize] = Some(value);
+        }
+    }
+
+    /// Renders the map with north at the top. The colors of the inhabited and size
+    /// metrics are scaled logarithmically from the lowest to the highest value
+    pub fn render(&self) -> RgbImage {
+        let present = || {
+            self.values
+                .iter()
+                .flatten()
+                .map(|value| (*value as f64).ln_1p())
+        };
+        let min_log = present().fold(f64::INFINITY, f64::min);
+        let max_log = present().fold(0.0, f64::max);
+
+        RgbImage::from_fn(self.width, self.height, |column, row| {
+            let value = self.values[row as usize * self.width as usize + column as usize];
+            let color = match (value, self.metric) {
+                (None, _) => COLOR_ABSENT,
+                (Some(_), MapMetric::Presence) => COLOR_PRESENT,
+                (Some(_), _) if max_log <= min_log => COLOR_HIGH,
+                (Some(value), _) => interpolate(
+                    COLOR_LOW,
+                    COLOR_HIGH,
+                    ((value as f64).ln_1p() - min_log) / (max_log - min_log),
+                ),
+            };
+
+            Rgb(color)
+        })
+    }
+
+    /// Renders the map and saves it as a png file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        self.render()
+            .save_with_format(path, ImageFormat::Png)
+            .map_err(|e| io::Error::other(format!("Failed to save map to {:?}: {}", path, e)))
+    }
+}
+
+/// Returns the color at the given fraction between the two colors
+fn interpolate(from: [u8; 3], to: [u8; 3], fraction: f64) -> [u8; 3] {
+    let mut color = from;
+    for (channel, target) in color.iter_mut().zip(to.iter()) {
+        *channel = (*channel as f64 + (*target as f64 - *channel as f64) * fraction).round() as u8;
+    }
+
+    color
+}
@@ -1,16 +1,22 @@
-use crate::utils::ByteArrayCache;
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use enum_as_inner::EnumAsInner;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::error::Error;
 use std::fmt::{self, Display, Formatter};
 use std::io::{self};
 
-const MAX_RECURSION: u64 = 100;
+/// The default maximum depth of nested compounds and lists
+pub const DEFAULT_MAX_DEPTH: u64 = 100;
+/// The default maximum number of elements of an array or list
+pub const DEFAULT_MAX_ELEMENTS: u32 = 16 * 1024 * 1024;
 
 pub struct NBTReader<R> {
     inner: R,
     recursion: u64,
+    max_recursion: u64,
+    remaining_size: u64,
+    max_elements: u32,
 }
 
 type NBTResult<T> = Result<T, NBTError>;
@@ -20,32 +26,120 @@ where
     R: io::Read,
 {
     pub fn new(inner: R) -> Self {
+        Self::with_size_limit(inner, u64::MAX)
+    }
+
+    /// Creates a reader that fails when the declared lengths of
+    /// arrays, lists and strings exceed the size limit in total
+    pub fn with_size_limit(inner: R, size_limit: u64) -> Self {
         Self {
             inner,
             recursion: 0,
+            max_recursion: DEFAULT_MAX_DEPTH,
+            remaining_size: size_limit,
+            max_elements: DEFAULT_MAX_ELEMENTS,
+        }
+    }
+
+    /// Creates a reader that fails when arrays or lists declare more elements than the limit
+    pub fn with_max_elements(inner: R, max_elements: u32) -> Self {
+        Self::new(inner).max_elements(max_elements)
+    }
+
+    /// Creates a reader that fails when compounds and lists are nested deeper than the limit
+    pub fn with_recursion_limit(inner: R, limit: u64) -> Self {
+        Self::new(inner).recursion_limit(limit)
+    }
+
+    /// Sets the maximum depth of nested compounds and lists
+    pub fn recursion_limit(mut self, limit: u64) -> Self {
+        self.max_recursion = limit;
+
+        self
+    }
+
+    /// Increases the recursion depth and fails if the limit is exceeded
+    fn enter(&mut self) -> NBTResult<()> {
+        if self.recursion >= self.max_recursion {
+            return Err(NBTError::RecursionError);
         }
+        self.recursion += 1;
+
+        Ok(())
+    }
+
+    /// Sets the maximum number of elements an array or list may declare
+    pub fn max_elements(mut self, max_elements: u32) -> Self {
+        self.max_elements = max_elements;
+
+        self
+    }
+
+    /// Reads the length of an array or list and checks it against the element limit
+    fn read_length(&mut self) -> NBTResult<u32> {
+        let length = self.inner.read_u32::<BigEndian>()?;
+        if length > self.max_elements {
+            return Err(NBTError::LengthLimit(length));
+        }
+
+        Ok(length)
+    }
+
+    /// Subtracts the size from the remaining size budget before allocating
+    fn reserve(&mut self, size: u64) -> NBTResult<()> {
+        if size > self.remaining_size {
+            return Err(NBTError::SizeLimitExceeded(size));
+        }
+        self.remaining_size -= size;
+
+        Ok(())
     }
 
     /// Parses the contents of the reader
     pub fn parse(&mut self) -> NBTResult<HashMap<String, NBTValue>> {
+        let mut builder = TreeBuilder::default();
+        self.visit(&mut builder)?;
+
+        builder.root.ok_or(NBTError::MissingRootTag)
+    }
+
+    /// Reads the contents of the reader and passes them to the visitor without
+    /// building the tree of values. Reading stops early when the visitor returns `Stop`
+    pub fn visit<V: NBTVisitor>(&mut self, visitor: &mut V) -> NBTResult<()> {
         let tag = self.inner.read_u8()?;
 
         if tag != 10 {
             return Err(NBTError::MissingRootTag);
         }
-        let mut buf = [0u8; 2];
-        self.inner.read(&mut buf)?;
+        // the name of the root tag is usually empty
+        let name = self.parse_string()?;
+        self.visit_compound(&name, visitor)?;
 
-        self.parse_compound()
+        Ok(())
     }
 
-    /// Parses a compound tag
-    fn parse_compound(&mut self) -> NBTResult<HashMap<String, NBTValue>> {
-        self.recursion += 1;
-        if self.recursion > MAX_RECURSION {
-            return Err(NBTError::RecursionError);
+    /// Reads a compound tag and passes its entries to the visitor
+    fn visit_compound<V: NBTVisitor>(
+        &mut self,
+        name: &str,
+        visitor: &mut V,
+    ) -> NBTResult<VisitControl> {
+        self.enter()?;
+        let result = self.visit_compound_entries(name, visitor);
+        // the depth is restored on errors as well so that the reader can be reused
+        self.recursion -= 1;
+
+        result
+    }
+
+    fn visit_compound_entries<V: NBTVisitor>(
+        &mut self,
+        name: &str,
+        visitor: &mut V,
+    ) -> NBTResult<VisitControl> {
+        if visitor.start_compound(name) == VisitControl::Stop {
+            return Ok(VisitControl::Stop);
         }
-        let mut root_value = HashMap::new();
         loop {
             let tag = self.inner.read_u8()?;
             if tag == 0 {
@@ -53,37 +147,89 @@ where
             }
             let name = self.parse_string()?;
 
-            let value = match tag {
-                1 => NBTValue::Byte(self.inner.read_u8()?),
-                2 => NBTValue::Short(self.inner.read_i16::<BigEndian>()?),
-                3 => NBTValue::Int(self.inner.read_i32::<BigEndian>()?),
-                4 => NBTValue::Long(self.inner.read_i64::<BigEndian>()?),
-                5 => NBTValue::Float(self.inner.read_f32::<BigEndian>()?),
-                6 => NBTValue::Double(self.inner.read_f64::<BigEndian>()?),
-                7 => NBTValue::ByteArray(self.parse_byte_array()?),
-                8 => NBTValue::String(self.parse_string()?),
-                9 => NBTValue::List(self.parse_list()?),
-                10 => NBTValue::Compound(self.parse_compound()?),
-                11 => NBTValue::IntArray(self.parse_int_array()?),
-                12 => NBTValue::LongArray(self.parse_long_array()?),
-                _ => return Err(NBTError::InvalidTag(tag)),
-            };
-            root_value.insert(name, value);
+            if self.visit_tag(tag, &name, visitor)? == VisitControl::Stop {
+                return Ok(VisitControl::Stop);
+            }
         }
+
+        Ok(visitor.end_compound())
+    }
+
+    /// Reads a list of nbt values and passes its items to the visitor
+    fn visit_list<V: NBTVisitor>(
+        &mut self,
+        name: &str,
+        visitor: &mut V,
+    ) -> NBTResult<VisitControl> {
+        self.enter()?;
+        let result = self.visit_list_items(name, visitor);
         self.recursion -= 1;
-        Ok(root_value)
+
+        result
+    }
+
+    fn visit_list_items<V: NBTVisitor>(
+        &mut self,
+        name: &str,
+        visitor: &mut V,
+    ) -> NBTResult<VisitControl> {
+        let tag = self.inner.read_u8()?;
+        let length = self.read_length()?;
+        // every item takes at least one byte even if it's stored without data
+        self.reserve(length as u64)?;
+
+        if tag > 12 {
+            return Err(NBTError::InvalidTag(tag));
+        }
+        if visitor.start_list(name, length) == VisitControl::Stop {
+            return Ok(VisitControl::Stop);
+        }
+        for _ in 0..length {
+            // items of a list don't have names
+            if self.visit_tag(tag, "", visitor)? == VisitControl::Stop {
+                return Ok(VisitControl::Stop);
+            }
+        }
+
+        Ok(visitor.end_list())
+    }
+
+    /// Reads the value of a tag and passes it to the visitor
+    fn visit_tag<V: NBTVisitor>(
+        &mut self,
+        tag: u8,
+        name: &str,
+        visitor: &mut V,
+    ) -> NBTResult<VisitControl> {
+        let value = match tag {
+            // only lists can contain values of the end tag
+            0 => NBTValue::Null,
+            1 => NBTValue::Byte(self.inner.read_u8()?),
+            2 => NBTValue::Short(self.inner.read_i16::<BigEndian>()?),
+            3 => NBTValue::Int(self.inner.read_i32::<BigEndian>()?),
+            4 => NBTValue::Long(self.inner.read_i64::<BigEndian>()?),
+            5 => NBTValue::Float(self.inner.read_f32::<BigEndian>()?),
+            6 => NBTValue::Double(self.inner.read_f64::<BigEndian>()?),
+            7 => NBTValue::ByteArray(self.parse_byte_array()?),
+            8 => NBTValue::String(self.parse_string()?),
+            9 => return self.visit_list(name, visitor),
+            10 => return self.visit_compound(name, visitor),
+            11 => NBTValue::IntArray(self.parse_int_array()?),
+            12 => NBTValue::LongArray(self.parse_long_array()?),
+            _ => return Err(NBTError::InvalidTag(tag)),
+        };
+
+        Ok(visitor.value(name, value))
     }
 
     /// Parses an array of bytes
-    fn parse_byte_array(&mut self) -> NBTResult<ByteArrayCache> {
-        let length = self.inner.read_u32::<BigEndian>()?;
-        // store the data of the byte array in a compressed byte array cache to save memory
-        let mut cache = ByteArrayCache::new();
+    fn parse_byte_array(&mut self) -> NBTResult<Vec<u8>> {
+        let length = self.read_length()?;
+        self.reserve(length as u64)?;
         let mut buf = vec![0u8; length as usize];
         self.inner.read_exact(&mut buf)?;
-        cache.write(&buf[..])?;
 
-        Ok(cache)
+        Ok(buf)
     }
 
     /// Parses a string value
@@ -92,44 +238,20 @@ where
         if length == 0 {
             return Ok(String::new());
         }
+        self.reserve(length as u64)?;
         let mut buf = vec![0u8; length as usize];
         self.inner.read_exact(&mut buf)?;
 
-        String::from_utf8(buf).map_err(|_| NBTError::InvalidName)
-    }
-
-    /// Parses a list of nbt values
-    fn parse_list(&mut self) -> NBTResult<Vec<NBTValue>> {
-        let tag = self.inner.read_u8()?;
-        let length = self.inner.read_u32::<BigEndian>()?;
-
-        let parse_fn: Box<dyn Fn(&mut Self) -> NBTResult<NBTValue>> = match tag {
-            0 => Box::new(|_| Ok(NBTValue::Null)),
-            1 => Box::new(|nbt| Ok(NBTValue::Byte(nbt.inner.read_u8()?))),
-            2 => Box::new(|nbt| Ok(NBTValue::Short(nbt.inner.read_i16::<BigEndian>()?))),
-            3 => Box::new(|nbt| Ok(NBTValue::Int(nbt.inner.read_i32::<BigEndian>()?))),
-            4 => Box::new(|nbt| Ok(NBTValue::Long(nbt.inner.read_i64::<BigEndian>()?))),
-            5 => Box::new(|nbt| Ok(NBTValue::Float(nbt.inner.read_f32::<BigEndian>()?))),
-            6 => Box::new(|nbt| Ok(NBTValue::Double(nbt.inner.read_f64::<BigEndian>()?))),
-            7 => Box::new(|nbt| Ok(NBTValue::ByteArray(nbt.parse_byte_array()?))),
-            8 => Box::new(|nbt| Ok(NBTValue::String(nbt.parse_string()?))),
-            9 => Box::new(|nbt| Ok(NBTValue::List(nbt.parse_list()?))),
-            11 => Box::new(|nbt| Ok(NBTValue::IntArray(nbt.parse_int_array()?))),
-            10 => Box::new(|nbt| Ok(NBTValue::Compound(nbt.parse_compound()?))),
-            12 => Box::new(|nbt| Ok(NBTValue::LongArray(nbt.parse_long_array()?))),
-            _ => return Err(NBTError::InvalidTag(tag)),
-        };
-        let mut items = Vec::new();
-        for _ in 0..length {
-            items.push(parse_fn(self)?);
-        }
-
-        Ok(items)
+        decode_modified_utf8(&buf)
+            // some tools write standard utf-8 with 4 byte sequences instead
+            .or_else(|| String::from_utf8(buf).ok())
+            .ok_or(NBTError::InvalidName)
     }
 
     /// Parses an array of 32 bit integers
     fn parse_int_array(&mut self) -> NBTResult<Vec<i32>> {
-        let length = self.inner.read_u32::<BigEndian>()?;
+        let length = self.read_length()?;
+        self.reserve(length as u64 * 4)?;
         let mut items = Vec::new();
         for _ in 0..length {
             items.push(self.inner.read_i32::<BigEndian>()?);
@@ -140,7 +262,8 @@ where
 
     /// Parses an array of 64 bit integers
     fn parse_long_array(&mut self) -> NBTResult<Vec<i64>> {
-        let length = self.inner.read_u32::<BigEndian>()?;
+        let length = self.read_length()?;
+        self.reserve(length as u64 * 8)?;
         let mut items = Vec::new();
         for _ in 0..length {
             items.push(self.inner.read_i64::<BigEndian>()?);
@@ -150,6 +273,293 @@ where
     }
 }
 
+/// Tells the reader if it should continue reading after a visitor callback
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VisitControl {
+    Continue,
+    Stop,
+}
+
+/// Receives the tags read by [NBTReader::visit] in the order they are stored.
+/// All callbacks continue reading by default
+pub trait NBTVisitor {
+    /// Called when a compound starts. The root compound is visited with the root name
+    fn start_compound(&mut self, _name: &str) -> VisitControl {
+        VisitControl::Continue
+    }
+
+    /// Called after all entries of a compound were visited
+    fn end_compound(&mut self) -> VisitControl {
+        VisitControl::Continue
+    }
+
+    /// Called when a list with the given number of items starts
+    fn start_list(&mut self, _name: &str, _length: u32) -> VisitControl {
+        VisitControl::Continue
+    }
+
+    /// Called after all items of a list were visited
+    fn end_list(&mut self) -> VisitControl {
+        VisitControl::Continue
+    }
+
+    /// Called for every value that isn't a compound or list.
+    /// Items of lists are visited with an empty name
+    fn value(&mut self, _name: &str, _value: NBTValue) -> VisitControl {
+        VisitControl::Continue
+    }
+}
+
+/// Visitor building the tree of values returned by [NBTReader::parse]
+#[derive(Default)]
+struct TreeBuilder {
+    stack: Vec<(String, NBTValue)>,
+    root: Option<HashMap<String, NBTValue>>,
+}
+
+impl TreeBuilder {
+    /// Adds the value to the compound or list that is currently built
+    fn add(&mut self, name: &str, value: NBTValue) {
+        match self.stack.last_mut() {
+            Some((_, NBTValue::Compound(entries))) => {
+                entries.insert(name.to_string(), value);
+            }
+            Some((_, NBTValue::List(items))) => items.push(value),
+            _ => {
+                if let NBTValue::Compound(entries) = value {
+                    self.root = Some(entries);
+                }
+            }
+        }
+    }
+
+    /// Adds the compound or list that was built last to its parent
+    fn finish(&mut self) {
+        if let Some((name, value)) = self.stack.pop() {
+            self.add(&name, value);
+        }
+    }
+}
+
+impl NBTVisitor for TreeBuilder {
+    fn start_compound(&mut self, name: &str) -> VisitControl {
+        self.stack
+            .push((name.to_string(), NBTValue::Compound(HashMap::new())));
+        VisitControl::Continue
+    }
+
+    fn end_compound(&mut self) -> VisitControl {
+        self.finish();
+        VisitControl::Continue
+    }
+
+    fn start_list(&mut self, name: &str, _length: u32) -> VisitControl {
+        self.stack
+            .push((name.to_string(), NBTValue::List(Vec::new())));
+        VisitControl::Continue
+    }
+
+    fn end_list(&mut self) -> VisitControl {
+        self.finish();
+        VisitControl::Continue
+    }
+
+    fn value(&mut self, name: &str, value: NBTValue) -> VisitControl {
+        self.add(name, value);
+        VisitControl::Continue
+    }
+}
+
+pub struct NBTWriter<W> {
+    inner: W,
+}
+
+impl<W> NBTWriter<W>
+where
+    W: io::Write,
+{
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// Returns the inner writer
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Writes the root compound tag with all its children
+    pub fn write(&mut self, root: &HashMap<String, NBTValue>) -> io::Result<()> {
+        self.inner.write_u8(10)?;
+        self.write_string("")?;
+
+        self.write_compound(root)
+    }
+
+    /// Writes the entries of a compound tag followed by the end tag
+    fn write_compound(&mut self, compound: &HashMap<String, NBTValue>) -> io::Result<()> {
+        for (name, value) in compound {
+            if let NBTValue::Null = value {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Compound entry {} has no value", name),
+                ));
+            }
+            self.inner.write_u8(value.tag_id())?;
+            self.write_string(name)?;
+            self.write_value(value)?;
+        }
+
+        self.inner.write_u8(0)
+    }
+
+    /// Writes the payload of a value without its tag id
+    fn write_value(&mut self, value: &NBTValue) -> io::Result<()> {
+        match value {
+            NBTValue::Null => Ok(()),
+            NBTValue::Byte(v) => self.inner.write_u8(*v),
+            NBTValue::Short(v) => self.inner.write_i16::<BigEndian>(*v),
+            NBTValue::Int(v) => self.inner.write_i32::<BigEndian>(*v),
+            NBTValue::Long(v) => self.inner.write_i64::<BigEndian>(*v),
+            NBTValue::Float(v) => self.inner.write_f32::<BigEndian>(*v),
+            NBTValue::Double(v) => self.inner.write_f64::<BigEndian>(*v),
+            NBTValue::ByteArray(v) => {
+                self.inner.write_u32::<BigEndian>(v.len() as u32)?;
+                self.inner.write_all(v)
+            }
+            NBTValue::String(v) => self.write_string(v),
+            NBTValue::List(v) => self.write_list(v),
+            NBTValue::Compound(v) => self.write_compound(v),
+            NBTValue::IntArray(v) => {
+                self.inner.write_u32::<BigEndian>(v.len() as u32)?;
+                for item in v {
+                    self.inner.write_i32::<BigEndian>(*item)?;
+                }
+                Ok(())
+            }
+            NBTValue::LongArray(v) => {
+                self.inner.write_u32::<BigEndian>(v.len() as u32)?;
+                for item in v {
+                    self.inner.write_i64::<BigEndian>(*item)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes a string as modified utf-8 prefixed by its length
+    fn write_string(&mut self, value: &str) -> io::Result<()> {
+        let bytes = encode_modified_utf8(value);
+        if bytes.len() > u16::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "String is too long to be stored as nbt",
+            ));
+        }
+        self.inner.write_u16::<BigEndian>(bytes.len() as u16)?;
+
+        self.inner.write_all(&bytes)
+    }
+
+    /// Writes a list of values that all share the tag of the first item
+    fn write_list(&mut self, items: &[NBTValue]) -> io::Result<()> {
+        let tag = items.first().map(|i| i.tag_id()).unwrap_or(0);
+        if items.iter().any(|i| i.tag_id() != tag) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "List items must all be of the same type",
+            ));
+        }
+        self.inner.write_u8(tag)?;
+        self.inner.write_u32::<BigEndian>(items.len() as u32)?;
+        for item in items {
+            self.write_value(item)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Encodes a string with java's modified utf-8 where the null character
+/// is stored as two bytes and supplementary characters are stored as surrogate pairs
+pub fn encode_modified_utf8(value: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(value.len());
+
+    for c in value.encode_utf16() {
+        match c {
+            0x0001..=0x007F => bytes.push(c as u8),
+            0x0000 | 0x0080..=0x07FF => {
+                bytes.push(0xC0 | (c >> 6) as u8);
+                bytes.push(0x80 | (c & 0x3F) as u8);
+            }
+            _ => {
+                bytes.push(0xE0 | (c >> 12) as u8);
+                bytes.push(0x80 | ((c >> 6) & 0x3F) as u8);
+                bytes.push(0x80 | (c & 0x3F) as u8);
+            }
+        }
+    }
+
+    bytes
+}
+
+/// Decodes a string stored with java's modified utf-8.
+/// Returns `None` if the bytes aren't valid modified utf-8
+pub fn decode_modified_utf8(bytes: &[u8]) -> Option<String> {
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+
+    while let Some(&first) = iter.next() {
+        let mut continuation = || iter.next().filter(|b| *b & 0xC0 == 0x80).map(|b| *b as u16);
+        let unit = match first {
+            0x01..=0x7F => first as u16,
+            0xC0..=0xDF => ((first as u16 & 0x1F) << 6) | (continuation()? & 0x3F),
+            0xE0..=0xEF => {
+                ((first as u16 & 0x0F) << 12)
+                    | ((continuation()? & 0x3F) << 6)
+                    | (continuation()? & 0x3F)
+            }
+            _ => return None,
+        };
+        units.push(unit);
+    }
+
+    // supplementary characters are stored as surrogate pairs
+    String::from_utf16(&units).ok()
+}
+
+/// Returns the value at a path of tag names separated by dots like `Level.Structures.References`.
+/// Items of lists are selected with an index in brackets like `Level.Sections[0].Y`.
+/// Returns `None` if any part of the path doesn't exist
+pub fn get_path<'a>(root: &'a HashMap<String, NBTValue>, path: &str) -> Option<&'a NBTValue> {
+    let mut segments = path.split('.');
+    let mut value = get_path_segment(root, segments.next()?)?;
+
+    for segment in segments {
+        value = get_path_segment(value.as_compound()?, segment)?;
+    }
+
+    Some(value)
+}
+
+/// Returns the value of a single segment of a path and resolves its list indices
+fn get_path_segment<'a>(
+    compound: &'a HashMap<String, NBTValue>,
+    segment: &str,
+) -> Option<&'a NBTValue> {
+    let (name, mut indices) = segment.split_at(segment.find('[').unwrap_or(segment.len()));
+    let mut value = compound.get(name)?;
+
+    while !indices.is_empty() {
+        let (index, rest) = indices.strip_prefix('[')?.split_once(']')?;
+        value = value.as_list()?.get(index.parse::<usize>().ok()?)?;
+        indices = rest;
+    }
+
+    Some(value)
+}
+
+/// A parsed NBT value. Typed accessors like `as_int`, `as_string` or `as_compound`
+/// are derived and return `None` if the value is of a different type.
 #[derive(Clone, Debug, EnumAsInner)]
 pub enum NBTValue {
     Null,
@@ -159,7 +569,7 @@ pub enum NBTValue {
     Long(i64),
     Float(f32),
     Double(f64),
-    ByteArray(ByteArrayCache),
+    ByteArray(Vec<u8>),
     String(String),
     List(Vec<NBTValue>),
     Compound(HashMap<String, NBTValue>),
@@ -167,6 +577,352 @@ pub enum NBTValue {
     LongArray(Vec<i64>),
 }
 
+impl NBTValue {
+    /// Returns the id of the tag used to store this value
+    pub fn tag_id(&self) -> u8 {
+        match self {
+            Self::Null => 0,
+            Self::Byte(_) => 1,
+            Self::Short(_) => 2,
+            Self::Int(_) => 3,
+            Self::Long(_) => 4,
+            Self::Float(_) => 5,
+            Self::Double(_) => 6,
+            Self::ByteArray(_) => 7,
+            Self::String(_) => 8,
+            Self::List(_) => 9,
+            Self::Compound(_) => 10,
+            Self::IntArray(_) => 11,
+            Self::LongArray(_) => 12,
+        }
+    }
+
+    /// Returns the name of the tag type in the format used by `TaggedNBTValue`
+    pub fn tag_name(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Byte(_) => "byte",
+            Self::Short(_) => "short",
+            Self::Int(_) => "int",
+            Self::Long(_) => "long",
+            Self::Float(_) => "float",
+            Self::Double(_) => "double",
+            Self::ByteArray(_) => "byte_array",
+            Self::String(_) => "string",
+            Self::List(_) => "list",
+            Self::Compound(_) => "compound",
+            Self::IntArray(_) => "int_array",
+            Self::LongArray(_) => "long_array",
+        }
+    }
+
+    /// Renders the value as an indented tree with one tag per line and its type.
+    /// Arrays are summarized by their length and compound entries are sorted
+    pub fn to_tree(&self) -> String {
+        let mut tree = String::new();
+        self.write_tree(&mut tree, None, 0);
+
+        tree
+    }
+
+    fn write_tree(&self, tree: &mut String, name: Option<&str>, depth: usize) {
+        tree.push_str(&"  ".repeat(depth));
+        tree.push_str(self.tag_name());
+        if let Some(name) = name {
+            tree.push(' ');
+            write_snbt_string(tree, name);
+        }
+        match self {
+            Self::Null => {}
+            Self::Byte(v) => tree.push_str(&format!(": {}", *v as i8)),
+            Self::Short(v) => tree.push_str(&format!(": {}", v)),
+            Self::Int(v) => tree.push_str(&format!(": {}", v)),
+            Self::Long(v) => tree.push_str(&format!(": {}", v)),
+            Self::Float(v) => tree.push_str(&format!(": {}", v)),
+            Self::Double(v) => tree.push_str(&format!(": {}", v)),
+            Self::String(v) => {
+                tree.push_str(": ");
+                write_snbt_string(tree, v);
+            }
+            Self::ByteArray(v) => tree.push_str(&format!(": [{} bytes]", v.len())),
+            Self::IntArray(v) => tree.push_str(&format!(": [{} ints]", v.len())),
+            Self::LongArray(v) => tree.push_str(&format!(": [{} longs]", v.len())),
+            Self::List(v) => {
+                tree.push_str(&format!(" ({} entries)", v.len()));
+                for value in v {
+                    tree.push('\n');
+                    value.write_tree(tree, None, depth + 1);
+                }
+            }
+            Self::Compound(v) => {
+                tree.push_str(&format!(" ({} entries)", v.len()));
+                for (key, value) in v.iter().collect::<BTreeMap<_, _>>() {
+                    tree.push('\n');
+                    value.write_tree(tree, Some(key), depth + 1);
+                }
+            }
+        }
+    }
+
+    /// Renders the value as stringified nbt like it's used in minecraft commands.
+    /// The entries of compounds are sorted so that the output is stable
+    pub fn to_snbt(&self) -> String {
+        let mut snbt = String::new();
+        self.write_snbt(&mut snbt);
+
+        snbt
+    }
+
+    fn write_snbt(&self, snbt: &mut String) {
+        match self {
+            // end tags inside of lists have no snbt representation
+            Self::Null => {}
+            // bytes are signed in minecraft
+            Self::Byte(v) => snbt.push_str(&format!("{}b", *v as i8)),
+            Self::Short(v) => snbt.push_str(&format!("{}s", v)),
+            Self::Int(v) => snbt.push_str(&v.to_string()),
+            Self::Long(v) => snbt.push_str(&format!("{}L", v)),
+            Self::Float(v) => snbt.push_str(&format!("{}f", v)),
+            Self::Double(v) => snbt.push_str(&format!("{}d", v)),
+            Self::ByteArray(v) => write_snbt_array(snbt, "B", v, |v| format!("{}b", *v as i8)),
+            Self::String(v) => write_snbt_string(snbt, v),
+            Self::List(v) => {
+                snbt.push('[');
+                for (i, value) in v.iter().enumerate() {
+                    if i > 0 {
+                        snbt.push(',');
+                    }
+                    value.write_snbt(snbt);
+                }
+                snbt.push(']');
+            }
+            Self::Compound(v) => {
+                snbt.push('{');
+                for (i, (key, value)) in v.iter().collect::<BTreeMap<_, _>>().iter().enumerate() {
+                    if i > 0 {
+                        snbt.push(',');
+                    }
+                    let is_plain = !key.is_empty()
+                        && key
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || "_-.+".contains(c));
+                    if is_plain {
+                        snbt.push_str(key);
+                    } else {
+                        write_snbt_string(snbt, key);
+                    }
+                    snbt.push(':');
+                    value.write_snbt(snbt);
+                }
+                snbt.push('}');
+            }
+            Self::IntArray(v) => write_snbt_array(snbt, "I", v, |v| v.to_string()),
+            Self::LongArray(v) => write_snbt_array(snbt, "L", v, |v| format!("{}L", v)),
+        }
+    }
+
+    /// Returns the paths at which the two values differ in the format used by `get_path`.
+    /// Compounds are compared by their keys and lists element by element
+    pub fn diff(&self, other: &NBTValue) -> Vec<NBTDiff> {
+        let mut differences = Vec::new();
+        self.diff_at(other, String::new(), &mut differences);
+
+        differences
+    }
+
+    fn diff_at(&self, other: &NBTValue, path: String, differences: &mut Vec<NBTDiff>) {
+        match (self, other) {
+            (Self::Compound(a), Self::Compound(b)) => {
+                let keys = a.keys().chain(b.keys()).collect::<BTreeSet<_>>();
+                for key in keys {
+                    let child_path = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", path, key)
+                    };
+                    match (a.get(key), b.get(key)) {
+                        (Some(a), Some(b)) => a.diff_at(b, child_path, differences),
+                        (Some(_), None) => differences.push(NBTDiff::Removed(child_path)),
+                        (None, Some(_)) => differences.push(NBTDiff::Added(child_path)),
+                        (None, None) => {}
+                    }
+                }
+            }
+            (Self::List(a), Self::List(b)) => {
+                for i in 0..a.len().max(b.len()) {
+                    let child_path = format!("{}[{}]", path, i);
+                    match (a.get(i), b.get(i)) {
+                        (Some(a), Some(b)) => a.diff_at(b, child_path, differences),
+                        (Some(_), None) => differences.push(NBTDiff::Removed(child_path)),
+                        (None, Some(_)) => differences.push(NBTDiff::Added(child_path)),
+                        (None, None) => {}
+                    }
+                }
+            }
+            (a, b) if a != b => differences.push(NBTDiff::Changed(path)),
+            _ => {}
+        }
+    }
+}
+
+impl PartialEq for NBTValue {
+    /// Floats are compared bitwise so that `NaN` values are equal to themselves
+    /// and a value always equals its own copy. Compounds are compared independent
+    /// of the order of their entries
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Null, Self::Null) => true,
+            (Self::Byte(a), Self::Byte(b)) => a == b,
+            (Self::Short(a), Self::Short(b)) => a == b,
+            (Self::Int(a), Self::Int(b)) => a == b,
+            (Self::Long(a), Self::Long(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => a.to_bits() == b.to_bits(),
+            (Self::Double(a), Self::Double(b)) => a.to_bits() == b.to_bits(),
+            (Self::ByteArray(a), Self::ByteArray(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::List(a), Self::List(b)) => a == b,
+            (Self::Compound(a), Self::Compound(b)) => a == b,
+            (Self::IntArray(a), Self::IntArray(b)) => a == b,
+            (Self::LongArray(a), Self::LongArray(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// A single difference between two nbt trees with the path of the differing value
+#[derive(Clone, Debug, PartialEq)]
+pub enum NBTDiff {
+    Added(String),
+    Removed(String),
+    Changed(String),
+}
+
+impl NBTDiff {
+    /// Returns the path of the differing value
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Added(path) | Self::Removed(path) | Self::Changed(path) => path,
+        }
+    }
+}
+
+impl Display for NBTDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added(path) => write!(f, "+ {}", path),
+            Self::Removed(path) => write!(f, "- {}", path),
+            Self::Changed(path) => write!(f, "~ {}", path),
+        }
+    }
+}
+
+/// Writes a typed array like `[I;1,2,3]`
+fn write_snbt_array<T, F: Fn(&T) -> String>(snbt: &mut String, prefix: &str, values: &[T], f: F) {
+    snbt.push('[');
+    snbt.push_str(prefix);
+    snbt.push(';');
+    snbt.push_str(&values.iter().map(f).collect::<Vec<_>>().join(","));
+    snbt.push(']');
+}
+
+/// Writes the string in double quotes escaping quotes and backslashes
+fn write_snbt_string(snbt: &mut String, value: &str) {
+    snbt.push('"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            snbt.push('\\');
+        }
+        snbt.push(c);
+    }
+    snbt.push('"');
+}
+
+impl Serialize for NBTValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Null => serializer.serialize_unit(),
+            Self::Byte(v) => serializer.serialize_u8(*v),
+            Self::Short(v) => serializer.serialize_i16(*v),
+            Self::Int(v) => serializer.serialize_i32(*v),
+            Self::Long(v) => serializer.serialize_i64(*v),
+            Self::Float(v) => serializer.serialize_f32(*v),
+            Self::Double(v) => serializer.serialize_f64(*v),
+            Self::ByteArray(v) => serializer.collect_seq(v),
+            Self::String(v) => serializer.serialize_str(v),
+            Self::List(v) => serializer.collect_seq(v),
+            // sort the entries so that the output is stable
+            Self::Compound(v) => serializer.collect_map(v.iter().collect::<BTreeMap<_, _>>()),
+            Self::IntArray(v) => serializer.collect_seq(v),
+            Self::LongArray(v) => serializer.collect_seq(v),
+        }
+    }
+}
+
+/// A representation of nbt values that keeps the type of each value when serialized.
+/// Values are serialized as `{"type": "long", "value": 123}` so that they
+/// can be deserialized back into the same nbt value
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum TaggedNBTValue {
+    Null,
+    Byte(u8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<TaggedNBTValue>),
+    Compound(BTreeMap<String, TaggedNBTValue>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl From<NBTValue> for TaggedNBTValue {
+    fn from(value: NBTValue) -> Self {
+        match value {
+            NBTValue::Null => Self::Null,
+            NBTValue::Byte(v) => Self::Byte(v),
+            NBTValue::Short(v) => Self::Short(v),
+            NBTValue::Int(v) => Self::Int(v),
+            NBTValue::Long(v) => Self::Long(v),
+            NBTValue::Float(v) => Self::Float(v),
+            NBTValue::Double(v) => Self::Double(v),
+            NBTValue::ByteArray(v) => Self::ByteArray(v),
+            NBTValue::String(v) => Self::String(v),
+            NBTValue::List(v) => Self::List(v.into_iter().map(Self::from).collect()),
+            NBTValue::Compound(v) => {
+                Self::Compound(v.into_iter().map(|(k, v)| (k, v.into())).collect())
+            }
+            NBTValue::IntArray(v) => Self::IntArray(v),
+            NBTValue::LongArray(v) => Self::LongArray(v),
+        }
+    }
+}
+
+impl From<TaggedNBTValue> for NBTValue {
+    fn from(value: TaggedNBTValue) -> Self {
+        match value {
+            TaggedNBTValue::Null => Self::Null,
+            TaggedNBTValue::Byte(v) => Self::Byte(v),
+            TaggedNBTValue::Short(v) => Self::Short(v),
+            TaggedNBTValue::Int(v) => Self::Int(v),
+            TaggedNBTValue::Long(v) => Self::Long(v),
+            TaggedNBTValue::Float(v) => Self::Float(v),
+            TaggedNBTValue::Double(v) => Self::Double(v),
+            TaggedNBTValue::ByteArray(v) => Self::ByteArray(v),
+            TaggedNBTValue::String(v) => Self::String(v),
+            TaggedNBTValue::List(v) => Self::List(v.into_iter().map(Self::from).collect()),
+            TaggedNBTValue::Compound(v) => {
+                Self::Compound(v.into_iter().map(|(k, v)| (k, v.into())).collect())
+            }
+            TaggedNBTValue::IntArray(v) => Self::IntArray(v),
+            TaggedNBTValue::LongArray(v) => Self::LongArray(v),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum NBTError {
     IO(io::Error),
@@ -174,6 +930,8 @@ pub enum NBTError {
     InvalidTag(u8),
     InvalidName,
     RecursionError,
+    SizeLimitExceeded(u64),
+    LengthLimit(u32),
 }
 
 impl Display for NBTError {
@@ -184,6 +942,16 @@ impl Display for NBTError {
             Self::MissingRootTag => write!(f, "Missing root tag!"),
             Self::InvalidName => write!(f, "Encountered invalid tag name"),
             Self::RecursionError => write!(f, "Reached recursion limit"),
+            Self::LengthLimit(length) => {
+                write!(
+                    f,
+                    "Declared length of {} elements exceeds the limit",
+                    length
+                )
+            }
+            Self::SizeLimitExceeded(size) => {
+                write!(f, "Declared size of {} bytes exceeds the size limit", size)
+            }
         }
     }
 }
@@ -195,3 +963,287 @@ impl From<io::Error> for NBTError {
         Self::IO(io_err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the root compound and parses it again
+    fn round_trip(root: &HashMap<String, NBTValue>) -> HashMap<String, NBTValue> {
+        let mut writer = NBTWriter::new(Vec::new());
+        writer.write(root).unwrap();
+        let data = writer.into_inner();
+
+        NBTReader::new(data.as_slice()).parse().unwrap()
+    }
+
+    #[test]
+    fn written_values_of_every_tag_are_parsed_unchanged() {
+        let mut nested = HashMap::new();
+        nested.insert(String::from("Byte"), NBTValue::Byte(0xFF));
+        nested.insert(String::from("Short"), NBTValue::Short(-12345));
+        nested.insert(String::from("Int"), NBTValue::Int(i32::MIN));
+        nested.insert(String::from("Long"), NBTValue::Long(i64::MAX));
+        nested.insert(String::from("Float"), NBTValue::Float(-1.5));
+        nested.insert(String::from("Double"), NBTValue::Double(f64::NAN));
+        nested.insert(
+            String::from("ByteArray"),
+            NBTValue::ByteArray(vec![0, 1, 0xFF]),
+        );
+        nested.insert(
+            String::from("String"),
+            NBTValue::String("minecraft:stone".into()),
+        );
+        nested.insert(String::from("IntArray"), NBTValue::IntArray(vec![-1, 0, 1]));
+        nested.insert(
+            String::from("LongArray"),
+            NBTValue::LongArray(vec![i64::MIN, 7]),
+        );
+        nested.insert(String::from("EmptyList"), NBTValue::List(Vec::new()));
+        nested.insert(
+            String::from("Lists"),
+            NBTValue::List(vec![
+                NBTValue::List(vec![NBTValue::Int(1), NBTValue::Int(2)]),
+                NBTValue::List(vec![NBTValue::String("a".into())]),
+            ]),
+        );
+        let mut root = HashMap::new();
+        root.insert(
+            String::from("Compounds"),
+            NBTValue::List(vec![
+                NBTValue::Compound(nested.clone()),
+                NBTValue::Compound(HashMap::new()),
+            ]),
+        );
+        root.insert(String::from("Level"), NBTValue::Compound(nested));
+
+        assert_eq!(round_trip(&root), root);
+    }
+
+    #[test]
+    fn modified_utf8_encodes_null_and_supplementary_characters() {
+        let value = "a\0\u{1F600}";
+        let bytes = encode_modified_utf8(value);
+
+        assert_eq!(
+            bytes,
+            [b'a', 0xC0, 0x80, 0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]
+        );
+        assert_eq!(decode_modified_utf8(&bytes).as_deref(), Some(value));
+    }
+
+    #[test]
+    fn invalid_modified_utf8_is_rejected() {
+        let invalid: [&[u8]; 6] = [
+            // a raw null byte
+            &[0x00],
+            // a continuation byte without a lead byte
+            &[0x80],
+            // truncated sequences
+            &[0xC0],
+            &[0xE0, 0x80],
+            // standard utf-8 with four bytes
+            &[0xF0, 0x9F, 0x98, 0x80],
+            // a surrogate without its pair
+            &[0xED, 0xA0, 0xBD],
+        ];
+
+        for bytes in &invalid {
+            assert_eq!(decode_modified_utf8(bytes), None, "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn strings_with_four_byte_utf8_are_parsed() {
+        let data = single_tag(8, &[0, 4, 0xF0, 0x9F, 0x98, 0x80, 0]);
+        let root = NBTReader::new(data.as_slice()).parse().unwrap();
+
+        assert_eq!(root["a"], NBTValue::String("\u{1F600}".into()));
+    }
+
+    /// Returns a root compound with the given number of compounds nested inside of it
+    fn nested_compounds(depth: usize) -> Vec<u8> {
+        let mut value = HashMap::new();
+        for _ in 0..depth {
+            let mut parent = HashMap::new();
+            parent.insert(String::from("a"), NBTValue::Compound(value));
+            value = parent;
+        }
+        let mut writer = NBTWriter::new(Vec::new());
+        writer.write(&value).unwrap();
+
+        writer.into_inner()
+    }
+
+    #[test]
+    fn compounds_nested_deeper_than_the_limit_fail() {
+        let data = nested_compounds(150);
+
+        assert!(matches!(
+            NBTReader::new(data.as_slice()).parse(),
+            Err(NBTError::RecursionError)
+        ));
+        assert!(NBTReader::with_recursion_limit(data.as_slice(), 200)
+            .parse()
+            .is_ok());
+    }
+
+    #[test]
+    fn readers_can_be_reused_after_reaching_the_recursion_limit() {
+        let data = nested_compounds(150);
+        let mut reader = NBTReader::new(data.as_slice());
+        assert!(matches!(reader.parse(), Err(NBTError::RecursionError)));
+        assert_eq!(reader.recursion, 0);
+
+        // the root and its nested compounds use up the whole limit
+        let data = nested_compounds(DEFAULT_MAX_DEPTH as usize - 1);
+        reader.inner = data.as_slice();
+        assert!(reader.parse().is_ok());
+        assert_eq!(reader.recursion, 0);
+    }
+
+    /// Returns a root compound containing a single tag named `a` whose payload
+    /// starts with the given bytes
+    fn single_tag(tag: u8, payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![10, 0, 0, tag, 0, 1, b'a'];
+        data.extend_from_slice(payload);
+
+        data
+    }
+
+    #[test]
+    fn byte_arrays_keep_their_contents() {
+        let data = single_tag(7, &[0, 0, 0, 4, 0xDE, 0xAD, 0xBE, 0xEF, 0]);
+        let root = NBTReader::new(data.as_slice()).parse().unwrap();
+
+        assert_eq!(root["a"], NBTValue::ByteArray(vec![0xDE, 0xAD, 0xBE, 0xEF]));
+    }
+
+    #[test]
+    fn oversized_lengths_fail_before_allocating() {
+        let length = [0xFF, 0xFF, 0xFF, 0xFF];
+        let mut list = vec![1];
+        list.extend_from_slice(&length);
+
+        for data in [
+            single_tag(7, &length),
+            single_tag(11, &length),
+            single_tag(12, &length),
+            single_tag(9, &list),
+        ] {
+            let result = NBTReader::new(data.as_slice()).parse();
+            assert!(
+                matches!(result, Err(NBTError::LengthLimit(0xFFFFFFFF))),
+                "{:?}",
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn lengths_above_the_element_limit_fail() {
+        let data = single_tag(11, &[0, 0, 0, 3, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0]);
+
+        assert!(NBTReader::with_max_elements(data.as_slice(), 3)
+            .parse()
+            .is_ok());
+        assert!(matches!(
+            NBTReader::with_max_elements(data.as_slice(), 2).parse(),
+            Err(NBTError::LengthLimit(3))
+        ));
+    }
+
+    /// Returns a tree with a level tag storing a position and two sections
+    fn level_tree() -> HashMap<String, NBTValue> {
+        let section =
+            |y| NBTValue::Compound(HashMap::from([(String::from("Y"), NBTValue::Byte(y))]));
+        let level = HashMap::from([
+            (String::from("xPos"), NBTValue::Int(3)),
+            (
+                String::from("Sections"),
+                NBTValue::List(vec![section(0), section(1)]),
+            ),
+        ]);
+
+        HashMap::from([(String::from("Level"), NBTValue::Compound(level))])
+    }
+
+    #[test]
+    fn paths_resolve_nested_tags_and_list_items() {
+        let root = level_tree();
+
+        assert_eq!(get_path(&root, "Level.xPos"), Some(&NBTValue::Int(3)));
+        assert_eq!(
+            get_path(&root, "Level.Sections[1].Y"),
+            Some(&NBTValue::Byte(1))
+        );
+        assert!(get_path(&root, "Level").unwrap().as_compound().is_some());
+    }
+
+    #[test]
+    fn paths_through_missing_or_mismatched_nodes_resolve_to_none() {
+        let root = level_tree();
+
+        for path in [
+            // the intermediate compound doesn't exist
+            "Level.Structures.References",
+            "Missing.xPos",
+            // the intermediate value isn't a compound or list
+            "Level.xPos.Y",
+            "Level.xPos[0]",
+            "Level.Sections[2].Y",
+            "Level.Sections[a].Y",
+            "Level.Sections[0",
+            "",
+        ] {
+            assert_eq!(get_path(&root, path), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn diffs_point_at_a_changed_nested_list_element() {
+        let a = NBTValue::Compound(level_tree());
+        let mut changed = level_tree();
+        let level = changed.get_mut("Level").unwrap().as_compound_mut().unwrap();
+        let sections = level.get_mut("Sections").unwrap().as_list_mut().unwrap();
+        sections[1] = NBTValue::Compound(HashMap::from([(String::from("Y"), NBTValue::Byte(5))]));
+        let b = NBTValue::Compound(changed);
+
+        assert_ne!(a, b);
+        assert_eq!(
+            a.diff(&b),
+            [NBTDiff::Changed(String::from("Level.Sections[1].Y"))]
+        );
+        assert!(a.diff(&a.clone()).is_empty());
+    }
+
+    #[test]
+    fn floats_are_compared_bitwise() {
+        assert_eq!(NBTValue::Double(f64::NAN), NBTValue::Double(f64::NAN));
+        assert_eq!(NBTValue::Float(f32::NAN), NBTValue::Float(f32::NAN));
+        assert_ne!(NBTValue::Double(0.0), NBTValue::Double(-0.0));
+        assert_ne!(NBTValue::Float(1.0), NBTValue::Double(1.0));
+        assert!(NBTValue::Double(f64::NAN)
+            .diff(&NBTValue::Double(f64::NAN))
+            .is_empty());
+    }
+
+    #[test]
+    fn compounds_are_compared_independent_of_their_order() {
+        let entries = [
+            (String::from("a"), NBTValue::Int(1)),
+            (String::from("b"), NBTValue::String("x".into())),
+            (String::from("c"), NBTValue::List(vec![NBTValue::Byte(1)])),
+        ];
+        let forward: HashMap<_, _> = entries.iter().cloned().collect();
+        let backward: HashMap<_, _> = entries.iter().rev().cloned().collect();
+
+        assert_eq!(
+            NBTValue::Compound(forward.clone()),
+            NBTValue::Compound(backward.clone())
+        );
+        assert!(NBTValue::Compound(forward)
+            .diff(&NBTValue::Compound(backward))
+            .is_empty());
+    }
+}
@@ -1,33 +1,210 @@
-use crate::chunk::{Chunk, ChunkScanError};
-use crate::scan::ScanOptions;
-use crate::scan::ScanStatistics;
+use crate::chunk::{
+    compress_nbt_data, decompress_chunk_data, detect_compression_method,
+    is_valid_compression_method, remove_duplicate_tile_entities, remove_misplaced_entities, Chunk,
+    ChunkScanError,
+};
+use crate::nbt::{NBTValue, NBTWriter, DEFAULT_MAX_DEPTH};
+use crate::scan::{DenseChunk, LargeChunk, RepairAction, ScanCheck, ScanEvent, ScanStatistics};
+use crate::scan::{ScanOptions, DEFAULT_MAX_DECOMPRESSED_BYTES};
+use crate::utils::back_up_file;
 use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
-use std::cmp::Ordering;
+use flate2::Compression;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufWriter, Read, Result, Seek, SeekFrom, Write};
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::io::{
+    BufReader, BufWriter, Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write,
+};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub const BLOCK_SIZE: usize = 4096;
+/// The number of seconds a timestamp may lie in the future to allow for clock skew
+const MAX_TIMESTAMP_SKEW: u32 = 60 * 60;
+/// The number of ticks the last update of a chunk may exceed the world time by since
+/// chunks can be saved after the level.dat. This equals one in-game day
+const MAX_LAST_UPDATE_SKEW: i64 = 24000;
+
+/// The kind of folder region files are stored in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegionFolder {
+    Region,
+    Entities,
+    Poi,
+}
+
+impl RegionFolder {
+    /// Returns all kinds of region folders
+    pub fn all() -> Vec<Self> {
+        vec![Self::Region, Self::Entities, Self::Poi]
+    }
+
+    /// Returns the name of the directory the region files are stored in
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            Self::Region => "region",
+            Self::Entities => "entities",
+            Self::Poi => "poi",
+        }
+    }
+
+    /// Returns the kind of folder a region file is stored in.
+    /// Files outside of an entities or poi folder are treated as region files
+    pub fn from_region_path(path: &Path) -> Self {
+        let dir_name = path
+            .parent()
+            .and_then(|p| p.file_name())
+            .and_then(|n| n.to_str());
+
+        match dir_name {
+            Some("entities") => Self::Entities,
+            Some("poi") => Self::Poi,
+            _ => Self::Region,
+        }
+    }
+}
+
+impl FromStr for RegionFolder {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "region" => Ok(Self::Region),
+            "entities" => Ok(Self::Entities),
+            "poi" => Ok(Self::Poi),
+            _ => Err(format!("Unknown region folder {}", s)),
+        }
+    }
+}
+
+/// The format of a region file which is told apart by its file extension
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegionFormat {
+    Anvil,
+    /// The format used before 1.2 which only supports gzip and zlib compression
+    McRegion,
+}
+
+impl RegionFormat {
+    /// Returns the format of a region file path. Paths with an unknown extension
+    /// are treated as anvil files
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(MCREGION_EXTENSION) => Self::McRegion,
+            _ => Self::Anvil,
+        }
+    }
+}
+
+/// The extension of region files in the anvil format
+pub const ANVIL_EXTENSION: &str = "mca";
+/// The extension of region files in the legacy McRegion format
+pub const MCREGION_EXTENSION: &str = "mcr";
 
 pub struct RegionFile {
     path: PathBuf,
-    reader: BufReader<File>,
-    writer: BufWriter<File>,
+    folder: RegionFolder,
+    format: RegionFormat,
+    access: RegionAccess,
     locations: Locations,
-    #[allow(dead_code)]
     timestamps: Timestamps,
     length: u64,
+    original_length: u64,
+    buffer: Vec<u8>,
+    repairs: Vec<RepairAction>,
+    backup: Option<PathBuf>,
 }
 
 impl RegionFile {
     pub fn new(path: &PathBuf) -> Result<Self> {
+        Self::open(path, true, false)
+    }
+
+    /// Opens the region file without write access so that it can be
+    /// scanned even if the user can only read it
+    pub fn open_read_only(path: &PathBuf) -> Result<Self> {
+        Self::open(path, false, false)
+    }
+
+    /// Reads the whole region file into memory to scan it without
+    /// seeking in the file. The file can't be modified
+    pub fn open_in_memory(path: &PathBuf) -> Result<Self> {
+        Self::open(path, false, true)
+    }
+
+    /// Reads the whole region file into memory and applies all modifications
+    /// to the copy in memory so that the file on disk stays untouched
+    pub fn open_detached(path: &PathBuf) -> Result<Self> {
+        let data = std::fs::read(path)?;
+
+        Self::from_storage(path, Cursor::new(data))
+    }
+
+    /// Opens a region file stored in something other than a file on disk like
+    /// a buffer in memory. The path is only used to determine the coordinates
+    /// and kind of the region and to find external chunks
+    pub fn from_storage<S: RegionStorage + 'static>(
+        path: &PathBuf,
+        mut storage: S,
+    ) -> Result<Self> {
+        check_writable(path)?;
+        let length = storage.seek(SeekFrom::End(0))?;
+        let handle = StorageHandle::new(storage);
+        let reader = RegionReader::Storage(BufReader::with_capacity(BLOCK_SIZE, handle.clone()));
+        let writer = BufWriter::with_capacity(2 * BLOCK_SIZE, handle);
+
+        Self::from_parts(path, reader, Some(writer), length)
+    }
+
+    fn open(path: &PathBuf, writable: bool, in_memory: bool) -> Result<Self> {
+        if writable {
+            // the reader and writer share one handle so that reads see the written data
+            let file = OpenOptions::new().read(true).write(true).open(path)?;
+            return Self::from_storage(path, file);
+        }
         let fr = OpenOptions::new().read(true).open(path)?;
-        let fw = OpenOptions::new().write(true).open(path)?;
         let file_size = fr.metadata()?.len();
-        let mut reader = BufReader::with_capacity(BLOCK_SIZE, fr);
-        let writer = BufWriter::with_capacity(2 * BLOCK_SIZE, fw);
+        let reader = if in_memory {
+            let mut data = Vec::with_capacity(file_size as usize);
+            BufReader::new(fr).read_to_end(&mut data)?;
+            RegionReader::InMemory(Cursor::new(data))
+        } else {
+            RegionReader::Buffered(BufReader::with_capacity(BLOCK_SIZE, fr))
+        };
 
+        Self::from_parts(path, reader, None, file_size)
+    }
+
+    /// Reads the header of the region through the opened reader
+    fn from_parts(
+        path: &PathBuf,
+        mut reader: RegionReader,
+        writer: Option<BufWriter<StorageHandle>>,
+        file_size: u64,
+    ) -> Result<Self> {
+        // the file needs to contain at least the locations and timestamps tables
+        if file_size < 2 * BLOCK_SIZE as u64 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "region file too small: {} bytes, expected >= {}",
+                    file_size,
+                    2 * BLOCK_SIZE
+                ),
+            ));
+        }
+        if !file_size.is_multiple_of(BLOCK_SIZE as u64) {
+            log::warn!(
+                "Size of region file {:?} ({} bytes) isn't a multiple of {} bytes",
+                path,
+                file_size,
+                BLOCK_SIZE
+            );
+        }
         let mut locations_raw = [0u8; BLOCK_SIZE];
         let mut timestamps_raw = [0u8; BLOCK_SIZE];
         reader.read_exact(&mut locations_raw)?;
@@ -35,47 +212,561 @@ impl RegionFile {
 
         Ok(Self {
             path: path.clone(),
+            folder: RegionFolder::from_region_path(path),
+            format: RegionFormat::from_path(path),
             locations: Locations::from_bytes(&locations_raw),
             timestamps: Timestamps::from_bytes(&timestamps_raw),
-            reader,
-            writer,
+            access: RegionAccess::new(reader, writer),
             length: file_size,
+            original_length: file_size,
+            buffer: Vec::new(),
+            repairs: Vec::new(),
+            backup: None,
         })
     }
 
+    /// Sets the path the file is copied to right before it's modified for the first time
+    pub fn set_backup(&mut self, backup: Option<PathBuf>) {
+        self.backup = backup;
+    }
+
+    /// Copies the file to its backup path unless it was already backed up.
+    /// Files that are about to be removed need to be backed up explicitly
+    pub fn back_up(&mut self) -> Result<()> {
+        if let Some(backup) = self.backup.take() {
+            // nothing was written yet so the copy contains the original data
+            back_up_file(&self.path, &backup)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the modifications made to the file since the last call
+    pub fn take_repairs(&mut self) -> Vec<RepairAction> {
+        std::mem::take(&mut self.repairs)
+    }
+
+    /// Returns the locations table of the file
+    pub fn locations(&self) -> &Locations {
+        &self.locations
+    }
+
+    /// Returns the timestamps table of the file
+    pub fn timestamps(&self) -> &Timestamps {
+        &self.timestamps
+    }
+
+    /// Returns the size of the file in bytes
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns the size of the file in bytes when it was opened
+    pub fn original_size(&self) -> u64 {
+        self.original_length
+    }
+
     /// Returns the number of chunks in the file
     pub fn count_chunks(&self) -> usize {
-        return self.locations.valid_entries_enumerate().len();
+        self.locations.valid_entries_enumerate().len()
+    }
+
+    /// Returns the global coordinates of the chunk at the index. Files without
+    /// region coordinates in their name are treated like the origin region
+    fn chunk_coords(&self, index: usize) -> (i32, i32) {
+        let (region_x, region_z) = parse_region_coords(&self.path).unwrap_or((0, 0));
+        let (x, z) = local_coords(index);
+
+        (region_x * 32 + x, region_z * 32 + z)
+    }
+
+    /// Returns the global coordinates, number of sectors and timestamp of the chunks present in the file
+    pub fn chunk_entries(&self) -> Vec<(i32, i32, u8, u32)> {
+        self.locations
+            .valid_entries_enumerate()
+            .into_iter()
+            .map(|(index, (_, sections))| {
+                let (x, z) = self.chunk_coords(index);
+
+                (x, z, sections, self.timestamps.get(index))
+            })
+            .collect()
+    }
+
+    /// Returns the size and compression of the chunks present in the file by only reading
+    /// the header of each chunk. Chunks whose header can't be read are skipped
+    pub fn chunk_sizes(&mut self) -> Vec<ChunkSize> {
+        // files without region coordinates in their name are treated like the origin region
+        let (region_x, region_z) = parse_region_coords(&self.path).unwrap_or((0, 0));
+        let mut sizes = Vec::new();
+
+        for (index, (offset, sectors)) in self.locations.valid_entries_enumerate() {
+            let chunk = self
+                .access
+                .seek_reader(offset as u64 * BLOCK_SIZE as u64)
+                .and_then(Chunk::from_buf_reader);
+            let (x, z) = self.chunk_coords(index);
+            match chunk {
+                Ok(chunk) => sizes.push(ChunkSize {
+                    region_x,
+                    region_z,
+                    x,
+                    z,
+                    sectors,
+                    length: chunk.length,
+                    compression_type: chunk.compression_type,
+                    timestamp: self.timestamps.get(index),
+                }),
+                Err(e) => log::warn!(
+                    "Skipping chunk ({},{}) at {} in {:?} that can't be read: {}",
+                    x,
+                    z,
+                    offset,
+                    self.path,
+                    e
+                ),
+            }
+        }
+
+        sizes
+    }
+
+    /// Returns an iterator over the chunks present in the file with their global coordinates.
+    /// The chunks are read lazily and read errors are returned as items of the iterator
+    pub fn chunks(&mut self) -> impl Iterator<Item = Result<(i32, i32, Chunk)>> + '_ {
+        // files without region coordinates in their name are treated like the origin region
+        let (region_x, region_z) = parse_region_coords(&self.path).unwrap_or((0, 0));
+        let access = &mut self.access;
+
+        self.locations
+            .valid_entries_enumerate()
+            .into_iter()
+            .map(move |(index, (offset, _))| {
+                let reader = access.seek_reader(offset as u64 * BLOCK_SIZE as u64)?;
+                let chunk = Chunk::from_buf_reader(reader)?;
+                let (x, z) = local_coords(index);
+
+                Ok((region_x * 32 + x, region_z * 32 + z, chunk))
+            })
+    }
+
+    /// Reads and parses the nbt data of the chunk at the given coordinates.
+    /// Returns `None` if the chunk isn't generated
+    pub fn read_chunk_nbt(
+        &mut self,
+        x: isize,
+        z: isize,
+    ) -> Result<Option<HashMap<String, NBTValue>>> {
+        let (offset, sections) = match (
+            self.locations.get_chunk_offset(x, z),
+            self.locations.get_chunk_sectors(x, z),
+        ) {
+            (Some(offset), Some(sections)) if offset >= 2 && sections > 0 => (offset, sections),
+            _ => return Ok(None),
+        };
+        if (offset + sections as u32) as u64 * BLOCK_SIZE as u64 > self.length {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Chunk ({},{}) points outside of the file", x, z),
+            ));
+        }
+        self.access.seek_reader(offset as u64 * BLOCK_SIZE as u64)?;
+        let chunk = Chunk::from_buf_reader(self.access.reader()?)?;
+        let result = if chunk.is_external() {
+            let mut reader = self.open_external_chunk(get_chunk_index(x, z))?;
+            chunk.read_nbt_data(
+                &mut reader,
+                DEFAULT_MAX_DECOMPRESSED_BYTES,
+                DEFAULT_MAX_DEPTH,
+            )
+        } else {
+            chunk.read_nbt_data(
+                self.access.reader()?,
+                DEFAULT_MAX_DECOMPRESSED_BYTES,
+                DEFAULT_MAX_DEPTH,
+            )
+        };
+
+        match result {
+            Ok(data) => Ok(Some(data)),
+            Err(ChunkScanError::IO(e)) => Err(e),
+            Err(e) => Err(Error::new(ErrorKind::InvalidData, e.to_string())),
+        }
+    }
+
+    /// Computes a sha256 checksum of the present chunks ordered by their index. Only the index,
+    /// compression method and compressed data of each chunk are hashed which makes files
+    /// with the same chunks hash the same regardless of their layout, padding or timestamps
+    pub fn checksum(&mut self) -> Result<Checksum> {
+        let mut hasher = Sha256::new();
+
+        for (index, (offset, sectors)) in self.locations.valid_entries_enumerate() {
+            let (x, z) = self.chunk_coords(index);
+            if (offset + sectors as u32) as u64 * BLOCK_SIZE as u64 > self.length {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Chunk ({},{}) points outside of the file", x, z),
+                ));
+            }
+            self.access.seek_reader(offset as u64 * BLOCK_SIZE as u64)?;
+            let chunk = Chunk::from_buf_reader(self.access.reader()?).map_err(|e| {
+                Error::new(
+                    e.kind(),
+                    format!("Failed to read the header of chunk ({},{}): {}", x, z, e),
+                )
+            })?;
+            self.buffer.clear();
+            if chunk.is_external() {
+                self.open_external_chunk(index)?
+                    .read_to_end(&mut self.buffer)?;
+            } else {
+                self.access
+                    .reader()?
+                    .take(chunk.length.saturating_sub(1) as u64)
+                    .read_to_end(&mut self.buffer)?;
+                if self.buffer.len() < chunk.length.saturating_sub(1) as usize {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        format!("Chunk ({},{}) is truncated", x, z),
+                    ));
+                }
+            }
+            hasher.update((index as u16).to_be_bytes());
+            hasher.update([chunk.compression_method()]);
+            hasher.update((self.buffer.len() as u64).to_be_bytes());
+            hasher.update(&self.buffer);
+        }
+
+        Ok(hasher.finalize().into())
+    }
+
+    /// Counts the chunks of the file by their generation status.
+    /// Chunks that can't be read or don't have a status are counted as `unknown`
+    pub fn count_statuses(&mut self) -> HashMap<String, u64> {
+        let mut statuses = HashMap::new();
+
+        for (index, (offset, _)) in self.locations.valid_entries_enumerate() {
+            let status = self
+                .read_validated_chunk(index, offset)
+                .map(|chunk| chunk.status)
+                .unwrap_or_else(|e| {
+                    let (x, z) = self.chunk_coords(index);
+                    log::debug!(
+                        "Failed to read the status of chunk ({},{}) at {}: {}",
+                        x,
+                        z,
+                        offset,
+                        e
+                    );
+                    None
+                });
+            *statuses
+                .entry(status.unwrap_or_else(|| String::from("unknown")))
+                .or_insert(0) += 1;
+        }
+
+        statuses
+    }
+
+    /// Returns the global coordinates and inhabited time of the chunks present in the file.
+    /// Chunks that can't be read or don't have an inhabited time are returned with 0 ticks
+    pub fn inhabited_times(&mut self) -> Vec<(i32, i32, i64)> {
+        let mut times = Vec::new();
+
+        for (index, (offset, _)) in self.locations.valid_entries_enumerate() {
+            let (x, z) = self.chunk_coords(index);
+            let inhabited_time = self
+                .read_validated_chunk(index, offset)
+                .map(|chunk| chunk.inhabited_time)
+                .unwrap_or_else(|e| {
+                    log::debug!(
+                        "Failed to read the inhabited time of chunk ({},{}) at {}: {}",
+                        x,
+                        z,
+                        offset,
+                        e
+                    );
+                    None
+                });
+            times.push((x, z, inhabited_time.unwrap_or(0)));
+        }
+
+        times
+    }
+
+    /// Reads the chunk at the given index and offset and validates its nbt data
+    fn read_validated_chunk(
+        &mut self,
+        index: usize,
+        offset: u32,
+    ) -> std::result::Result<Chunk, ChunkScanError> {
+        self.access.seek_reader(offset as u64 * BLOCK_SIZE as u64)?;
+        let mut chunk = Chunk::from_buf_reader(self.access.reader()?)?;
+        if chunk.is_external() {
+            let mut reader = self.open_external_chunk(index)?;
+            chunk.validate_nbt_data(
+                &mut reader,
+                self.folder,
+                self.format,
+                DEFAULT_MAX_DECOMPRESSED_BYTES,
+                DEFAULT_MAX_DEPTH,
+                None,
+            )?;
+        } else {
+            chunk.validate_nbt_data(
+                self.access.reader()?,
+                self.folder,
+                self.format,
+                DEFAULT_MAX_DECOMPRESSED_BYTES,
+                DEFAULT_MAX_DEPTH,
+                None,
+            )?;
+        }
+
+        Ok(chunk)
+    }
+
+    /// Serializes the nbt data, compresses it with the given compression type and level and
+    /// writes it as the chunk at the given coordinates. The chunk is moved to the
+    /// end of the file if it doesn't fit into its previous sectors.
+    /// Returns the length of the written chunk
+    pub fn write_chunk_nbt(
+        &mut self,
+        x: isize,
+        z: isize,
+        data: &HashMap<String, NBTValue>,
+        compression: u8,
+        level: Compression,
+    ) -> Result<u32> {
+        let mut nbt_writer = NBTWriter::new(Vec::new());
+        nbt_writer.write(data)?;
+        let compressed = compress_nbt_data(&nbt_writer.into_inner(), compression, level)?;
+
+        self.write_chunk_data(x, z, &compressed, compression)
+    }
+
+    /// Writes the compressed data as the chunk at the given coordinates. The chunk is moved
+    /// to the end of the file if it doesn't fit into its previous sectors.
+    /// Returns the length of the written chunk
+    fn write_chunk_data(
+        &mut self,
+        x: isize,
+        z: isize,
+        compressed: &[u8],
+        compression: u8,
+    ) -> Result<u32> {
+        // the length includes the compression type
+        let length = compressed.len() as u32 + 1;
+        let sections = ((length + 4) as f64 / BLOCK_SIZE as f64).ceil() as u32;
+        if sections > u8::MAX as u32 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Chunk ({},{}) is too large with {} bytes", x, z, length),
+            ));
+        }
+        let index = get_chunk_index(x, z);
+        let (previous_offset, previous_sections) = self.locations.get_chunk_entry_unchecked(index);
+        let offset = if previous_offset >= 2 && sections <= previous_sections as u32 {
+            previous_offset
+        } else {
+            // the end of the file could contain data that isn't referenced by the locations table
+            let file_sections = ((self.length as f64) / BLOCK_SIZE as f64).ceil() as u32;
+            self.locations.max_offset().max(file_sections)
+        };
+        let padding = vec![0u8; sections as usize * BLOCK_SIZE - (length as usize + 4)];
+        log::debug!(
+            "Writing chunk ({},{}) with {} bytes at {}",
+            x,
+            z,
+            length,
+            offset
+        );
+
+        self.locations
+            .replace_entry_unchecked(index, (offset, sections as u8));
+        self.timestamps.set(index, current_timestamp());
+        let (chunk_x, chunk_z) = self.chunk_coords(index);
+        self.repairs.push(RepairAction::RewroteChunk {
+            x: chunk_x,
+            z: chunk_z,
+        });
+        let locations = self.locations.to_bytes();
+        let timestamps = self.timestamps.to_bytes();
+
+        let writer = self.writer()?;
+        writer.seek(SeekFrom::Start(offset as u64 * BLOCK_SIZE as u64))?;
+        writer.write_u32::<BigEndian>(length)?;
+        writer.write_u8(compression)?;
+        writer.write_all(compressed)?;
+        writer.write_all(&padding)?;
+        writer.seek(SeekFrom::Start(0))?;
+        writer.write_all(&locations)?;
+        writer.write_all(&timestamps)?;
+        self.close()?;
+        self.length = self
+            .length
+            .max((offset + sections) as u64 * BLOCK_SIZE as u64);
+
+        Ok(length)
+    }
+
+    /// Rewrites all chunks compressed with zlib at the given level. Chunks stored in
+    /// external files or that can't be read are skipped. The gaps left by chunks that
+    /// shrunk aren't closed. Returns the number of bytes the chunks shrunk by
+    pub fn recompress(&mut self, level: Compression) -> Result<i64> {
+        let mut saved = 0;
+
+        for (index, (offset, _)) in self.locations.valid_entries_enumerate() {
+            self.access.seek_reader(offset as u64 * BLOCK_SIZE as u64)?;
+            let previous_length = match Chunk::from_buf_reader(self.access.reader()?) {
+                Ok(chunk) if !chunk.is_external() => chunk.length,
+                _ => continue,
+            };
+            let (x, z) = local_coords(index);
+            let (x, z) = (x as isize, z as isize);
+            let data = match self.read_chunk_nbt(x, z) {
+                Ok(Some(data)) => data,
+                Ok(None) => continue,
+                Err(e) => {
+                    let (x, z) = self.chunk_coords(index);
+                    log::warn!(
+                        "Skipping chunk ({},{}) at {} in {:?} that can't be read: {}",
+                        x,
+                        z,
+                        offset,
+                        self.path,
+                        e
+                    );
+                    continue;
+                }
+            };
+            let length = self.write_chunk_nbt(x, z, &data, 2, level)?;
+            saved += previous_length as i64 - length as i64;
+        }
+
+        Ok(saved)
+    }
+
+    /// Recompresses the chunk at the given index with zlib at the highest level if it
+    /// occupies fewer sectors afterwards. Other chunks are left untouched.
+    /// Returns the number of sectors that were freed
+    fn recompress_chunk(&mut self, index: usize, options: &ScanOptions) -> Result<u8> {
+        let (offset, sections) = self.locations.get_chunk_entry_unchecked(index);
+        if offset < 2 {
+            return Ok(0);
+        }
+        self.access.seek_reader(offset as u64 * BLOCK_SIZE as u64)?;
+        let chunk = match Chunk::from_buf_reader(self.access.reader()?) {
+            Ok(chunk) if !chunk.is_external() => chunk,
+            _ => return Ok(0),
+        };
+        self.buffer.clear();
+        self.access
+            .reader()?
+            .take(chunk.length.saturating_sub(1) as u64)
+            .read_to_end(&mut self.buffer)?;
+        let data = match decompress_chunk_data(
+            &self.buffer,
+            chunk.compression_method(),
+            options.max_decompressed_bytes,
+        ) {
+            Ok(data) => data,
+            Err(_) => return Ok(0),
+        };
+        let compressed = compress_nbt_data(&data, 2, Compression::best())?;
+        let new_sections = ((compressed.len() + 5) as f64 / BLOCK_SIZE as f64).ceil() as u8;
+        if new_sections >= sections {
+            return Ok(0);
+        }
+        let (x, z) = local_coords(index);
+        self.write_chunk_data(x as isize, z as isize, &compressed, 2)?;
+
+        Ok(sections - new_sections)
+    }
+
+    /// Rewrites the chunk at the given index without the entities positioned outside of it.
+    /// The chunk only shrinks so it's always written into its previous sectors.
+    /// Returns the number of removed entities
+    fn remove_misplaced_entities(
+        &mut self,
+        index: usize,
+        compression: u8,
+        level: Compression,
+    ) -> Result<u64> {
+        let (x, z) = local_coords(index);
+        let (x, z) = (x as isize, z as isize);
+        let mut data = match self.read_chunk_nbt(x, z)? {
+            Some(data) => data,
+            None => return Ok(0),
+        };
+        let removed = remove_misplaced_entities(&mut data);
+        if removed > 0 {
+            self.write_chunk_nbt(x, z, &data, compression, level)?;
+        }
+
+        Ok(removed)
+    }
+
+    /// Rewrites the chunk at the given index keeping only the first tile entity at each
+    /// position. Returns the number of removed tile entities
+    fn remove_duplicate_tile_entities(
+        &mut self,
+        index: usize,
+        compression: u8,
+        level: Compression,
+    ) -> Result<u64> {
+        let (x, z) = local_coords(index);
+        let (x, z) = (x as isize, z as isize);
+        let mut data = match self.read_chunk_nbt(x, z)? {
+            Some(data) => data,
+            None => return Ok(0),
+        };
+        let removed = remove_duplicate_tile_entities(&mut data);
+        if removed > 0 {
+            self.write_chunk_nbt(x, z, &data, compression, level)?;
+        }
+
+        Ok(removed)
     }
 
     /// Scans the chunk entries for possible errors
     pub fn scan_chunks(&mut self, options: &Arc<ScanOptions>) -> Result<ScanStatistics> {
         let mut statistic = ScanStatistics::new();
-        let mut shift_operations: Vec<(usize, isize)> = Vec::new();
 
         let mut entries = self.locations.valid_entries_enumerate();
-        entries.sort_by(|(_, (a, _)), (_, (b, _))| {
-            if a > b {
-                Ordering::Greater
-            } else if a < b {
-                Ordering::Less
-            } else {
-                Ordering::Equal
-            }
-        });
+        entries.sort_by_key(|(_, (offset, _))| *offset);
         statistic.total_chunks = entries.len() as u64;
+        statistic.total_size = self.original_length;
+        let offsets: Vec<u32> = entries.iter().map(|(_, (offset, _))| *offset).collect();
+        let sector_map = SectorMap::new(&self.locations, (self.length / BLOCK_SIZE as u64) as u32);
+        statistic.free_sectors = sector_map.free_sectors();
+        statistic.double_allocated_sectors = sector_map.double_allocated_sectors();
+        if options.runs(ScanCheck::Alignment) {
+            self.check_alignment(&mut statistic, options)?;
+        }
+        if options.runs(ScanCheck::Timestamps) {
+            self.check_timestamps(&mut statistic, options);
+        }
+        if options.runs(ScanCheck::Pointers) {
+            self.check_header_pointers(&mut statistic, options)?;
+        }
         let mut previous_offset = 2;
         let mut previous_sections = 0;
+        let mut previous_index = 0;
+        let region = parse_region_coords(&self.path);
+        // min-heap of the largest chunks so that the smallest one can be replaced
+        let mut largest_chunks = BinaryHeap::new();
 
         for (index, (offset, sections)) in entries {
             // Calculate and seek to the start of the chunk
             let reader_offset = offset as u64 * BLOCK_SIZE as u64;
-            self.reader.seek(SeekFrom::Start(reader_offset))?;
+            self.access.seek_reader(reader_offset)?;
+            let (x, z) = self.chunk_coords(index);
 
             let offset_diff = offset as i32 - (previous_offset as i32 + previous_sections as i32);
             // Check if there is wasted space between the chunks
             // since the chunks are iterated ordered by offset the previous chunk is the closest
-            if offset_diff > 0 {
+            if offset_diff > 0 && options.runs(ScanCheck::Gaps) {
                 statistic.unused_space += (BLOCK_SIZE * offset_diff as usize) as u64;
                 log::debug!(
                     "Gap of unused {:.2} KiB detected between {} and {}",
@@ -83,105 +774,469 @@ impl RegionFile {
                     previous_offset,
                     offset
                 );
-                if options.fix {
-                    shift_operations.push((offset as usize, -(offset_diff as isize)));
-                }
+                statistic.add_event(options, || ScanEvent::GapDetected {
+                    offset: previous_offset + previous_sections,
+                    sectors: offset_diff as u32,
+                });
             }
             // Check if the chunk is longer than the file
             if offset < 2 || self.length < (offset + sections as u32) as u64 * BLOCK_SIZE as u64 {
+                // the chunk can't be read even if the pointers aren't checked
+                if !options.runs(ScanCheck::Pointers) {
+                    continue;
+                }
                 statistic.invalid_chunk_pointer += 1;
                 log::debug!(
-                    "Invalid chunk offset and sections at index {}: {} + {}",
-                    index,
+                    "Invalid chunk offset and sections of chunk ({},{}): {} + {}",
+                    x,
+                    z,
                     offset,
                     sections
                 );
+                statistic.add_event(options, || ScanEvent::InvalidPointer {
+                    x,
+                    z,
+                    offset,
+                    sectors: sections,
+                });
                 if options.fix_delete {
                     self.delete_chunk(index)?;
+                    statistic.invalid_chunk_pointer_fixed += 1;
                 }
                 continue;
             }
-            match Chunk::from_buf_reader(&mut self.reader) {
+            let checks_pointers = options.runs(ScanCheck::Pointers);
+            // Check if the chunk has the same offset as the previous one
+            if checks_pointers && offset == previous_offset && previous_sections > 0 {
+                statistic.duplicate_pointer += 1;
+                log::debug!(
+                    "Pointers {} and {} of chunk ({},{}) point to the same offset {}",
+                    previous_index,
+                    index,
+                    x,
+                    z,
+                    offset
+                );
+                statistic.add_event(options, || ScanEvent::DuplicatePointer { x, z, offset });
+                if options.fix_delete {
+                    statistic.duplicate_pointer_fixed += 1;
+                    // keep the pointer stored at the index matching the position of the chunk
+                    let position = self
+                        .read_validated_chunk(index, offset)
+                        .ok()
+                        .and_then(|chunk| chunk.x_pos.zip(chunk.z_pos));
+                    let keeps_current = position
+                        .map(|(x, z)| get_chunk_index(x as isize, z as isize) == index)
+                        .unwrap_or(false);
+
+                    if !keeps_current {
+                        self.delete_chunk(index)?;
+                        continue;
+                    }
+                    self.delete_chunk(previous_index)?;
+                    self.access.seek_reader(reader_offset)?;
+                }
+            } else if checks_pointers && offset_diff < 0 {
+                // the chunk shares sectors with the previous chunk
+                statistic.overlapping_chunks += 1;
+                log::warn!(
+                    "Chunk ({},{}) with offset {} overlaps chunk at index {} with offset {} in {:?}",
+                    x,
+                    z,
+                    offset,
+                    previous_index,
+                    previous_offset,
+                    self.path
+                );
+                statistic.add_event(options, || ScanEvent::OverlappingChunk { x, z, offset });
+                if options.fix_delete {
+                    statistic.overlapping_chunks_fixed += 1;
+                    if index > previous_index {
+                        self.delete_chunk(index)?;
+                        continue;
+                    }
+                    self.delete_chunk(previous_index)?;
+                }
+            }
+            // remove chunks outside of the area that should be kept
+            if let (Some(area), Some(_)) = (&options.trim, region) {
+                if !area.contains(x, z) {
+                    log::debug!("Trimming chunk ({},{}) at {}", x, z, offset);
+                    self.delete_chunk(index)?;
+                    statistic.pruned_chunks += 1;
+                    statistic.pruned_space += sections as u64 * BLOCK_SIZE as u64;
+
+                    previous_offset = offset;
+                    previous_sections = sections as u32;
+                    previous_index = index;
+                    continue;
+                }
+            }
+            // the chunk data may only extend up to the next chunk or the end of the file
+            let next_offset = offsets
+                .get(offsets.partition_point(|o| *o <= offset))
+                .map(|o| *o as u64 * BLOCK_SIZE as u64)
+                .unwrap_or(self.length)
+                .min(self.length);
+            let available = next_offset - reader_offset;
+
+            // an interrupted write can leave a record without any data behind
+            if options.runs(ScanCheck::Length) {
+                let mut length = [0u8; 4];
+                let is_empty = self.access.reader()?.read_exact(&mut length).is_ok()
+                    && BigEndian::read_u32(&length) == 0;
+                self.access.seek_reader(reader_offset)?;
+                if is_empty {
+                    statistic.empty_chunk_record += 1;
+                    log::debug!("Chunk ({},{}) at {} has a length of zero", x, z, offset);
+                    statistic.add_event(options, || ScanEvent::EmptyChunkRecord { x, z, offset });
+                    if options.fix_delete {
+                        self.delete_chunk(index)?;
+                        statistic.empty_chunk_record_fixed += 1;
+                    }
+                    previous_offset = offset;
+                    previous_sections = sections as u32;
+                    previous_index = index;
+                    continue;
+                }
+            }
+
+            match Chunk::from_buf_reader(self.access.reader()?) {
+                // external chunks only store the compression type in the region file
+                Ok(chunk)
+                    if options.runs(ScanCheck::Length)
+                        && !chunk.is_external()
+                        && chunk.length as u64 + 4 > available =>
+                {
+                    statistic.truncated_chunk += 1;
+                    log::debug!(
+                        "Chunk ({},{}) at {} with a length of {} is truncated to {} bytes",
+                        x,
+                        z,
+                        offset,
+                        chunk.length,
+                        available
+                    );
+                    statistic.add_event(options, || ScanEvent::TruncatedChunk {
+                        x,
+                        z,
+                        length: chunk.length,
+                        available,
+                    });
+                    if options.fix_delete {
+                        self.delete_chunk(index)?;
+                        statistic.truncated_chunk_fixed += 1;
+                    }
+                }
                 Ok(chunk) => {
-                    let exists =
-                        self.scan_chunk(index, offset, sections, chunk, &mut statistic, options)?;
-                    // If scan_chunk returns false the chunk entry was deleted
-                    if !exists && options.fix {
-                        shift_operations
-                            .push((offset as usize + sections as usize, -(sections as isize)))
+                    if options.top_chunks > 0 {
+                        largest_chunks.push(Reverse((chunk.length, x, z)));
+                        if largest_chunks.len() > options.top_chunks {
+                            largest_chunks.pop();
+                        }
+                    }
+                    if !options.samples(&self.path, index) {
+                        statistic.unsampled_chunks += 1;
+                        previous_offset = offset;
+                        previous_sections = sections as u32;
+                        previous_index = index;
+                        continue;
+                    }
+                    let errors = statistic.total_errors();
+                    match self.scan_chunk(index, offset, sections, chunk, &mut statistic, options) {
+                        // the sectors of deleted chunks are reclaimed when the chunks are packed
+                        Ok(_) => {}
+                        // a single unreadable chunk shouldn't prevent scanning the other chunks
+                        Err(e) => {
+                            statistic.failed_to_read += 1;
+                            log::error!(
+                                "Failed to scan chunk ({},{}) at {} in {:?}: {}",
+                                x,
+                                z,
+                                offset,
+                                self.path,
+                                e
+                            );
+                            statistic.add_event(options, || ScanEvent::FailedToRead {
+                                x,
+                                z,
+                                reason: e.to_string(),
+                            });
+                        }
+                    }
+                    if options.sample.is_some() {
+                        statistic.sampled_chunks += 1;
+                        statistic.sampled_errors += statistic.total_errors() - errors;
                     }
                 }
                 Err(e) => {
                     statistic.failed_to_read += 1;
                     log::error!(
-                        "Failed to read chunk at {} in {:?}: {}",
+                        "Failed to read chunk ({},{}) at {} in {:?}: {}",
+                        x,
+                        z,
                         offset,
                         self.path,
                         e
                     );
+                    statistic.add_event(options, || ScanEvent::FailedToRead {
+                        x,
+                        z,
+                        reason: e.to_string(),
+                    });
                     if options.fix_delete {
                         self.delete_chunk(index)?;
-                        shift_operations
-                            .push((offset as usize + sections as usize, -(sections as isize)));
+                        statistic.failed_to_read_fixed += 1;
                     }
                 }
             }
 
             previous_offset = offset;
             previous_sections = sections as u32;
+            previous_index = index;
         }
+        statistic.add_largest_chunks(
+            largest_chunks
+                .into_iter()
+                .map(|Reverse((length, x, z))| LargeChunk { x, z, length })
+                .collect(),
+            options.top_chunks,
+        );
 
-        if options.fix || options.fix_delete {
-            self.perform_shift_operations(shift_operations)?;
+        if options.modifies_files() {
+            let packed = if options.defragments() || options.fix_delete {
+                self.pack_chunks()?
+            } else {
+                true
+            };
+
+            // the file ends with the chunk ending last
+            let new_size = self.locations.estimated_size();
+            self.write_header()?;
+            // sectors past the new size are cut off so only the ones before it are wiped
+            if options.wipe_free_space {
+                let sectors = (new_size.min(self.length) / BLOCK_SIZE as u64) as u32;
+                for (start, end) in SectorMap::new(&self.locations, sectors).free_ranges() {
+                    self.wipe(
+                        start as u64 * BLOCK_SIZE as u64,
+                        end as u64 * BLOCK_SIZE as u64,
+                    )?;
+                }
+            }
+            // the buffered data needs to be on disk before the file is truncated
+            self.close()?;
 
-            // The new size of the file is the estimated size based on the highest chunk offset + sections
-            statistic.shrunk_size = self.locations.estimated_size();
-            self.writer.seek(SeekFrom::Start(0))?;
-            self.writer
-                .write_all(self.locations.to_bytes().as_slice())?;
-            self.writer.flush()?;
+            // remove the sectors that were freed by packing the chunks
+            if packed && new_size < self.length {
+                self.writer()?.get_mut().set_len(new_size)?;
+                statistic.shrunk_size = self.length - new_size;
+                self.repairs.push(RepairAction::ResizedFile {
+                    from: self.length,
+                    to: new_size,
+                });
+                self.length = new_size;
+            }
         }
+        self.close()?;
+        statistic.final_size = self.length;
+        let repairs = self.take_repairs();
+        statistic.add_repairs(&self.path, repairs);
 
         Ok(statistic)
     }
 
-    /// Performs shift operations defined in the shift_operations vector
-    fn perform_shift_operations(
-        &mut self,
-        mut shift_operations: Vec<(usize, isize)>,
-    ) -> Result<()> {
-        // sort the shift operations by resulting offset to have them in the right order
-        shift_operations.sort_by(|(o1, a1), (o2, a2)| {
-            let to_offset1 = *o1 as isize + *a1;
-            let to_offset2 = *o2 as isize + *a2;
-            if to_offset1 > to_offset1 {
-                Ordering::Greater
-            } else if to_offset1 < to_offset2 {
-                Ordering::Less
-            } else {
-                Ordering::Equal
+    /// Writes the locations and timestamps tables into the header of the file.
+    /// Unchanged tables aren't written so that files without errors aren't modified
+    fn write_header(&mut self) -> Result<()> {
+        let mut header = self.locations.to_bytes();
+        header.append(&mut self.timestamps.to_bytes());
+        let mut stored = vec![0u8; header.len()];
+        self.access.seek_reader(0)?;
+        self.access.reader()?.read_exact(&mut stored)?;
+        if stored == header {
+            return Ok(());
+        }
+        let writer = self.writer()?;
+        writer.seek(SeekFrom::Start(0))?;
+        writer.write_all(&header)?;
+
+        Ok(())
+    }
+
+    /// Moves the chunks into the free sectors before them so that no gaps are left.
+    /// Chunks at the end of the file are first moved into the best fitting gap
+    /// before the remaining chunks are moved towards the start of the file.
+    /// Returns `false` if the chunks couldn't be packed
+    fn pack_chunks(&mut self) -> Result<bool> {
+        // chunks rewritten while scanning need to be on disk before they're read again
+        self.close()?;
+        let mut blocks = self.chunk_blocks();
+        // chunks sharing only some of their sectors can't be moved without splitting them
+        if blocks
+            .windows(2)
+            .any(|w| w[0].offset + w[0].sectors as u32 > w[1].offset)
+        {
+            log::warn!(
+                "Not defragmenting {:?} since chunks share sectors",
+                self.path
+            );
+            return Ok(false);
+        }
+        let mut gaps = Vec::new();
+        let mut end_offset = 2;
+        for block in &blocks {
+            if block.offset > end_offset {
+                gaps.push((end_offset, block.offset - end_offset));
             }
-        });
-        let mut shifted = 0isize;
+            end_offset = block.offset + block.sectors as u32;
+        }
 
-        // perform shifting of chunks to close gaps between them
-        let mut operations = shift_operations.iter().peekable();
+        for block in blocks.iter_mut().rev().filter(|b| b.sectors > 0) {
+            let sectors = block.sectors as u32;
+            let best_gap = gaps
+                .iter_mut()
+                .filter(|(start, length)| *start < block.offset && *length >= sectors)
+                .min_by_key(|(start, length)| (*length, *start));
+            if let Some(gap) = best_gap {
+                let target = gap.0;
+                gap.0 += sectors;
+                gap.1 -= sectors;
+                self.move_chunk_block(block, target)?;
+            }
+        }
+        blocks.sort_by_key(|block| block.offset);
 
-        while let Some((offset, amount)) = operations.next() {
-            shifted += *amount;
-            let end_offset = if let Some((o, a)) = operations.peek() {
-                (*o as isize + *a) as usize
-            } else {
-                self.locations.max_offset() as usize
-            };
-            if *offset > end_offset {
-                log::error!("Invalid shift ({} - {}) -> {}", offset, end_offset, shifted);
-                break;
+        let mut next_offset = 2;
+        for block in &mut blocks {
+            if block.offset > next_offset {
+                self.move_chunk_block(block, next_offset)?;
+            }
+            next_offset = block.offset + block.sectors as u32;
+        }
+
+        Ok(true)
+    }
+
+    /// Returns the sectors occupied by chunks ordered by their offset.
+    /// Location entries pointing at the same offset are grouped into one block
+    fn chunk_blocks(&self) -> Vec<ChunkBlock> {
+        let mut blocks: Vec<ChunkBlock> = Vec::new();
+        let mut entries = self.locations.valid_entries_enumerate();
+        entries.sort_by_key(|(_, (offset, _))| *offset);
+
+        for (index, (offset, sectors)) in entries {
+            match blocks.last_mut() {
+                Some(block) if block.offset == offset => {
+                    block.sectors = block.sectors.max(sectors);
+                    block.indices.push(index);
+                }
+                _ => blocks.push(ChunkBlock {
+                    offset,
+                    sectors,
+                    indices: vec![index],
+                }),
             }
-            self.shift_right(*offset, end_offset, shifted)?;
+        }
+
+        blocks
+    }
+
+    /// Copies the sectors of the block to the offset and points its location entries at it.
+    /// The block is read completely before it's written so the ranges may overlap.
+    /// Fails before writing anything if the block would be moved into the header
+    fn move_chunk_block(&mut self, block: &mut ChunkBlock, offset: u32) -> Result<()> {
+        // the first two sectors contain the locations and timestamps tables
+        if offset < 2 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Moving sectors from {} to {} would overwrite the header of {:?}",
+                    block.offset, offset, self.path
+                ),
+            ));
+        }
+        log::debug!(
+            "Moving {} sectors from {} to {} in {:?}",
+            block.sectors,
+            block.offset,
+            offset,
+            self.path
+        );
+        let length = block.sectors as usize * BLOCK_SIZE;
+        let mut buf = Vec::with_capacity(length);
+        // the last sector might not be complete if the file wasn't padded
+        self.access
+            .seek_reader(block.offset as u64 * BLOCK_SIZE as u64)?
+            .take(length as u64)
+            .read_to_end(&mut buf)?;
+        buf.resize(length, 0);
+
+        let writer = self.writer()?;
+        writer.seek(SeekFrom::Start(offset as u64 * BLOCK_SIZE as u64))?;
+        writer.write_all(&buf)?;
+        // the moved data can be read again when later blocks are moved
+        writer.flush()?;
+
+        for index in &block.indices {
+            let (_, sectors) = self.locations.get_chunk_entry_unchecked(*index);
             self.locations
-                .shift_entries(*offset as u32, end_offset as u32, shifted as i32);
+                .replace_entry_unchecked(*index, (offset, sectors));
         }
+        self.repairs.push(RepairAction::MovedSectors {
+            from: block.offset,
+            to: offset,
+            sectors: block.sectors,
+        });
+        block.offset = offset;
+
+        Ok(())
+    }
+
+    /// Returns the compression method the data of the chunk starting at the offset
+    /// can actually be read with or `None` if there is none
+    fn detect_compression_method(
+        &mut self,
+        chunk: &Chunk,
+        reader_offset: u64,
+        options: &ScanOptions,
+    ) -> Result<Option<u8>> {
+        self.access.seek_reader(reader_offset + 5)?;
+        self.buffer.clear();
+        self.access
+            .reader()?
+            .take(chunk.length.saturating_sub(1) as u64)
+            .read_to_end(&mut self.buffer)?;
+        // McRegion files only support GZIP and ZLIB
+        let methods: &[u8] = if self.format == RegionFormat::McRegion {
+            &[1, 2]
+        } else {
+            &[1, 2, 3, 4]
+        };
+
+        Ok(detect_compression_method(
+            &self.buffer,
+            methods,
+            options.max_decompressed_bytes,
+            options.max_nbt_depth,
+        ))
+    }
+
+    /// Overwrites the compression method of the chunk starting at the offset
+    fn set_compression_method(
+        &mut self,
+        reader_offset: u64,
+        x: i32,
+        z: i32,
+        method: u8,
+    ) -> Result<()> {
+        let writer = self.writer()?;
+        writer.seek(SeekFrom::Start(reader_offset + 4))?;
+        writer.write_u8(method)?;
+        self.repairs.push(RepairAction::SetCompressionMethod {
+            x,
+            z,
+            compression_type: method,
+        });
 
         Ok(())
     }
@@ -189,131 +1244,998 @@ impl RegionFile {
     /// Scans a single chunk for errors
     fn scan_chunk(
         &mut self,
-        index: usize,
+        mut index: usize,
         offset: u32,
         sections: u8,
         mut chunk: Chunk,
         statistic: &mut ScanStatistics,
         options: &Arc<ScanOptions>,
     ) -> Result<bool> {
+        // the stored length already includes the compression type byte
+        // so the chunk occupies the length field and `length` bytes
         let chunk_sections = ((chunk.length + 4) as f64 / BLOCK_SIZE as f64).ceil();
         let reader_offset = offset as u64 * BLOCK_SIZE as u64;
+        let (slot_x, slot_z) = self.chunk_coords(index);
 
-        // Valid compression types are:
-        // 0 - uncompressed
-        // 1 - GZIP
-        // 2 - ZLIB
-        if chunk.compression_type > 3 {
+        if !is_valid_compression_method(chunk.compression_method())
+            && options.runs(ScanCheck::Compression)
+        {
             statistic.invalid_compression_method += 1;
-            if options.fix {
-                self.writer.seek(SeekFrom::Start(reader_offset + 4))?;
-                self.writer.write_u8(1)?;
+            statistic.add_event(options, || ScanEvent::InvalidCompressionMethod {
+                x: slot_x,
+                z: slot_z,
+                compression_type: chunk.compression_type,
+            });
+            // the data of external chunks isn't stored in the region file to detect the method
+            let detected = if (options.fix || options.fix_compression) && !chunk.is_external() {
+                self.detect_compression_method(&chunk, reader_offset, options)?
+            } else {
+                None
+            };
+            match detected {
+                Some(method) => {
+                    self.set_compression_method(reader_offset, slot_x, slot_z, method)?;
+                    statistic.invalid_compression_method_fixed += 1;
+                }
+                None if options.fix_delete => {
+                    self.delete_chunk(index)?;
+                    statistic.invalid_compression_method_fixed += 1;
+                    return Ok(false);
+                }
+                None => {}
             }
-        } else {
-            // seek to the start of the actual chunk data
-            self.reader.seek(SeekFrom::Start(reader_offset + 5))?;
+        } else if !options.skip_nbt && options.runs(ScanCheck::Nbt) {
+            chunk.world_data_version = options.data_version;
+            let result = if chunk.is_external() {
+                match self.open_external_chunk(index) {
+                    Ok(mut reader) => chunk.validate_nbt_data(
+                        &mut reader,
+                        self.folder,
+                        self.format,
+                        options.max_decompressed_bytes,
+                        options.max_nbt_depth,
+                        options.required_tags.as_deref(),
+                    ),
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
+                        Err(ChunkScanError::MissingExternalFile)
+                    }
+                    Err(e) => Err(ChunkScanError::IO(e)),
+                }
+            } else {
+                // seek to the start of the actual chunk data
+                self.access.seek_reader(reader_offset + 5)?;
+                // the buffer is reused to avoid allocating it for every chunk
+                self.buffer.clear();
+                self.access
+                    .reader()?
+                    .take(chunk.length.saturating_sub(1) as u64)
+                    .read_to_end(&mut self.buffer)?;
+                chunk.validate_nbt_data(
+                    &mut Cursor::new(self.buffer.as_slice()),
+                    self.folder,
+                    self.format,
+                    options.max_decompressed_bytes,
+                    options.max_nbt_depth,
+                    options.required_tags.as_deref(),
+                )
+            };
 
-            if let Err(e) = chunk.validate_nbt_data(&mut self.reader) {
+            if let Err(e) = result {
+                // the data may have been compressed with another method than the stored one
+                let detected = match &e {
+                    ChunkScanError::IO(_) | ChunkScanError::NBTError(_)
+                        if options.fix_compression && !chunk.is_external() =>
+                    {
+                        self.detect_compression_method(&chunk, reader_offset, options)?
+                            .filter(|method| *method != chunk.compression_method())
+                    }
+                    _ => None,
+                };
+                let fixed = (options.fix_delete || detected.is_some()) as u64;
                 match e {
                     ChunkScanError::IO(e) => {
-                        log::debug!("Compression error at chunk {}: {}", offset, e);
+                        log::debug!("Compression error at chunk ({},{}): {}", slot_x, slot_z, e);
                         statistic.corrupted_compression += 1;
+                        statistic.add_event(options, || ScanEvent::CorruptCompression {
+                            x: slot_x,
+                            z: slot_z,
+                            reason: e.to_string(),
+                        });
+                        statistic.corrupted_compression_fixed += fixed;
                     }
                     ChunkScanError::NBTError(e) => {
-                        log::debug!("Corrupted nbt data for chunk {}: {}", offset, e);
+                        log::debug!(
+                            "Corrupted nbt data for chunk ({},{}): {}",
+                            slot_x,
+                            slot_z,
+                            e
+                        );
                         statistic.corrupted_nbt += 1;
+                        statistic.add_event(options, || ScanEvent::CorruptNbt {
+                            x: slot_x,
+                            z: slot_z,
+                            reason: e.to_string(),
+                        });
+                        statistic.corrupted_nbt_fixed += fixed;
+                    }
+                    ChunkScanError::MissingExternalFile => {
+                        log::debug!("Missing external file for chunk ({},{})", slot_x, slot_z);
+                        statistic.missing_external_file += 1;
+                        statistic.add_event(options, || ScanEvent::MissingExternalFile {
+                            x: slot_x,
+                            z: slot_z,
+                        });
+                        statistic.missing_external_file_fixed += fixed;
                     }
                     _ => {
-                        log::debug!("Missing nbt data for chunk {}: {}", offset, e);
+                        log::debug!("Missing nbt data for chunk ({},{}): {}", slot_x, slot_z, e);
                         statistic.missing_nbt += 1;
+                        statistic.add_event(options, || ScanEvent::MissingNbt {
+                            x: slot_x,
+                            z: slot_z,
+                            reason: e.to_string(),
+                        });
+                        statistic.missing_nbt_fixed += fixed;
                     }
                 }
+                if let Some(method) = detected {
+                    self.set_compression_method(reader_offset, slot_x, slot_z, method)?;
+                    return Ok(true);
+                }
                 if options.fix_delete {
                     self.delete_chunk(index)?;
                     return Ok(false);
                 }
             } else {
                 // validate that the chunk is the one the index should be pointing at
-                if let Some(x) = chunk.x_pos {
-                    if let Some(z) = chunk.z_pos {
-                        if get_chunk_index(x as isize, z as isize) != index {
-                            statistic.invalid_chunk_pointer += 1;
-                            log::debug!("Pointer {} pointing to wrong chunk ({},{})", index, x, z);
-
-                            if options.fix_delete {
-                                // Delete the entry of the chunk from the locations table
-                                self.delete_chunk(index)?;
-                            }
+                if let (Some(x), Some(z), true) =
+                    (chunk.x_pos, chunk.z_pos, options.runs(ScanCheck::Pointers))
+                {
+                    let in_region = parse_region_coords(&self.path)
+                        .map(|(region_x, region_z)| {
+                            x.div_euclid(32) == region_x && z.div_euclid(32) == region_z
+                        })
+                        .unwrap_or(true);
+
+                    let expected_index = get_chunk_index(x as isize, z as isize);
+
+                    if !in_region {
+                        // the index check would accept these since the coordinates wrap around
+                        statistic.foreign_chunk += 1;
+                        statistic.add_event(options, || ScanEvent::ForeignChunk {
+                            x: slot_x,
+                            z: slot_z,
+                            found_x: x,
+                            found_z: z,
+                        });
+                        log::debug!(
+                            "Pointer of chunk ({},{}) pointing to chunk ({},{}) of another region",
+                            slot_x,
+                            slot_z,
+                            x,
+                            z
+                        );
+
+                        if options.fix_delete {
+                            self.delete_chunk(index)?;
+                            statistic.foreign_chunk_fixed += 1;
+                            return Ok(false);
                         }
+                    } else if expected_index != index
+                        && get_chunk_index(z as isize, x as isize) == index
+                    {
+                        statistic.swapped_pointer += 1;
+                        statistic.add_event(options, || ScanEvent::SwappedPointer {
+                            x: slot_x,
+                            z: slot_z,
+                        });
+                        log::debug!(
+                            "Pointer of chunk ({},{}) pointing to chunk ({},{}) with swapped coordinates",
+                            slot_x,
+                            slot_z,
+                            x,
+                            z
+                        );
+                        // the chunk can only be moved if its actual slot isn't used
+                        if options.fix
+                            && self.locations.get_chunk_entry_unchecked(expected_index).0 == 0
+                        {
+                            self.relocate_chunk(index, expected_index)?;
+                            index = expected_index;
+                            statistic.swapped_pointer_fixed += 1;
+                        }
+                    } else if expected_index != index {
+                        statistic.invalid_chunk_pointer += 1;
+                        log::debug!(
+                            "Pointer of chunk ({},{}) pointing to wrong chunk ({},{})",
+                            slot_x,
+                            slot_z,
+                            x,
+                            z
+                        );
+                        statistic.add_event(options, || ScanEvent::WrongChunk {
+                            x: slot_x,
+                            z: slot_z,
+                            found_x: x,
+                            found_z: z,
+                        });
+
+                        if options.fix_delete {
+                            // Delete the entry of the chunk from the locations table
+                            self.delete_chunk(index)?;
+                            statistic.invalid_chunk_pointer_fixed += 1;
+                        }
+                    }
+                }
+                if options.count_entities {
+                    // the index changes if a swapped pointer was moved
+                    let (x, z) = self.chunk_coords(index);
+                    statistic.add_entities(DenseChunk {
+                        x: chunk.x_pos.unwrap_or(x),
+                        z: chunk.z_pos.unwrap_or(z),
+                        entities: chunk.entities.unwrap_or(0),
+                        block_entities: chunk.block_entities.unwrap_or(0),
+                    });
+                }
+                if let (Some(world_time), Some(last_update)) =
+                    (options.world_time, chunk.last_update)
+                {
+                    if last_update > world_time.saturating_add(MAX_LAST_UPDATE_SKEW) {
+                        statistic.future_timestamp += 1;
+                        statistic.add_event(options, || ScanEvent::FutureTimestamp {
+                            x: slot_x,
+                            z: slot_z,
+                        });
+                        log::debug!(
+                            "Chunk ({},{}) was last updated at tick {} after the world time {}",
+                            slot_x,
+                            slot_z,
+                            last_update,
+                            world_time
+                        );
+                    }
+                }
+                if chunk.invalid_heightmaps > 0 {
+                    statistic.invalid_heightmap += 1;
+                    statistic.add_event(options, || ScanEvent::InvalidHeightmaps {
+                        x: slot_x,
+                        z: slot_z,
+                        count: chunk.invalid_heightmaps,
+                    });
+                    log::debug!(
+                        "Chunk ({},{}) contains {} heightmaps of an invalid length",
+                        slot_x,
+                        slot_z,
+                        chunk.invalid_heightmaps
+                    );
+                }
+                if chunk.invalid_structures {
+                    statistic.invalid_structures += 1;
+                    statistic.add_event(options, || ScanEvent::InvalidStructures {
+                        x: slot_x,
+                        z: slot_z,
+                    });
+                    log::debug!(
+                        "Chunk ({},{}) contains invalid structure data",
+                        slot_x,
+                        slot_z
+                    );
+                }
+                if chunk.invalid_biomes {
+                    statistic.invalid_biomes += 1;
+                    statistic.add_event(options, || ScanEvent::InvalidBiomes {
+                        x: slot_x,
+                        z: slot_z,
+                    });
+                    log::debug!("Chunk ({},{}) contains invalid biome data", slot_x, slot_z);
+                }
+                if chunk.invalid_sections {
+                    statistic.invalid_sections += 1;
+                    statistic.add_event(options, || ScanEvent::InvalidSections {
+                        x: slot_x,
+                        z: slot_z,
+                    });
+                    log::debug!(
+                        "Chunk ({},{}) contains duplicate or out of range sections",
+                        slot_x,
+                        slot_z
+                    );
+                }
+                if chunk.invalid_block_states {
+                    statistic.invalid_block_states += 1;
+                    statistic.add_event(options, || ScanEvent::InvalidBlockStates {
+                        x: slot_x,
+                        z: slot_z,
+                    });
+                    log::debug!(
+                        "Chunk ({},{}) contains block states not matching their palette",
+                        slot_x,
+                        slot_z
+                    );
+                }
+                if chunk.misplaced_entities > 0 {
+                    statistic.misplaced_entities += chunk.misplaced_entities;
+                    statistic.add_event(options, || ScanEvent::MisplacedEntities {
+                        x: slot_x,
+                        z: slot_z,
+                        count: chunk.misplaced_entities,
+                    });
+                    log::debug!(
+                        "Chunk ({},{}) contains {} entities positioned outside of it",
+                        slot_x,
+                        slot_z,
+                        chunk.misplaced_entities
+                    );
+                    // external chunks would need to be moved back into the region file
+                    if options.fix_entities && !chunk.is_external() {
+                        let compression = match chunk.compression_method() {
+                            0 => 3,
+                            method => method,
+                        };
+                        statistic.misplaced_entities_fixed += self.remove_misplaced_entities(
+                            index,
+                            compression,
+                            Compression::new(options.compression_level),
+                        )?;
+                        statistic.misplaced_entity_chunks_fixed += 1;
+                    }
+                }
+                if chunk.misplaced_tile_entities > 0 || chunk.duplicate_tile_entities > 0 {
+                    statistic.misplaced_tile_entities += chunk.misplaced_tile_entities;
+                    statistic.duplicate_tile_entities += chunk.duplicate_tile_entities;
+                    statistic.add_event(options, || ScanEvent::InvalidTileEntities {
+                        x: slot_x,
+                        z: slot_z,
+                        misplaced: chunk.misplaced_tile_entities,
+                        duplicates: chunk.duplicate_tile_entities,
+                    });
+                    log::debug!(
+                        "Chunk ({},{}) contains {} tile entities outside of it and {} duplicates",
+                        slot_x,
+                        slot_z,
+                        chunk.misplaced_tile_entities,
+                        chunk.duplicate_tile_entities
+                    );
+                    if options.fix_tile_entities
+                        && chunk.duplicate_tile_entities > 0
+                        && !chunk.is_external()
+                    {
+                        let compression = match chunk.compression_method() {
+                            0 => 3,
+                            method => method,
+                        };
+                        statistic.duplicate_tile_entities_fixed += self
+                            .remove_duplicate_tile_entities(
+                                index,
+                                compression,
+                                Compression::new(options.compression_level),
+                            )?;
+                    }
+                }
+                if options.entity_histogram {
+                    for id in chunk.entity_ids.drain(..) {
+                        *statistic.entity_types.entry(id).or_insert(0) += 1;
+                    }
+                }
+                if options.inventory {
+                    for namespace in chunk.namespaces.drain(..) {
+                        *statistic.namespaces.entry(namespace).or_insert(0) += 1;
+                    }
+                }
+                // remove chunks that players haven't spent enough time in
+                if let (Some(max_ticks), Some(inhabited_time)) =
+                    (options.prune, chunk.inhabited_time)
+                {
+                    if inhabited_time <= max_ticks {
+                        log::debug!(
+                            "Pruning chunk ({},{}) with an inhabited time of {} ticks",
+                            slot_x,
+                            slot_z,
+                            inhabited_time
+                        );
+                        self.delete_chunk(index)?;
+                        statistic.pruned_chunks += 1;
+                        statistic.pruned_space += sections as u64 * BLOCK_SIZE as u64;
+                        return Ok(false);
+                    }
+                }
+                // chunks without a status are kept since their state is unknown
+                if let (true, Some(status)) = (options.delete_protochunks, &chunk.status) {
+                    if status != "full" && status != "minecraft:full" {
+                        log::debug!(
+                            "Deleting proto-chunk ({},{}) with the status {}",
+                            slot_x,
+                            slot_z,
+                            status
+                        );
+                        self.delete_chunk(index)?;
+                        statistic.protochunks_deleted += 1;
+                        return Ok(false);
                     }
                 }
             }
         }
 
-        if sections != chunk_sections as u8 || chunk.length >= 1_048_576 {
-            statistic.invalid_length += 1;
-            if options.fix {
-                self.locations
-                    .replace_entry_unchecked(index, (offset, chunk_sections as u8));
+        if options.runs(ScanCheck::Length)
+            && (sections != chunk_sections as u8 || chunk.length >= 1_048_576)
+        {
+            statistic.invalid_length += 1;
+            statistic.add_event(options, || ScanEvent::InvalidLength {
+                x: slot_x,
+                z: slot_z,
+                length: chunk.length,
+                sectors: sections,
+            });
+            if options.fix {
+                self.locations
+                    .replace_entry_unchecked(index, (offset, chunk_sections as u8));
+                statistic.invalid_length_fixed += 1;
+                self.repairs.push(RepairAction::SetSectors {
+                    x: slot_x,
+                    z: slot_z,
+                    sectors: chunk_sections as u8,
+                });
+            }
+        }
+        if options.recompress {
+            // the entry is read again since fixes may have moved or deleted the chunk
+            let freed = self.recompress_chunk(index, options)?;
+            if freed > 0 {
+                statistic.recompressed_chunks += 1;
+                statistic.recompressed_space += freed as u64 * BLOCK_SIZE as u64;
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Checks the location entries pointing into the header. These aren't part of the
+    /// valid entries and would otherwise be ignored by the scan
+    fn check_header_pointers(
+        &mut self,
+        statistic: &mut ScanStatistics,
+        options: &Arc<ScanOptions>,
+    ) -> Result<()> {
+        for (index, (offset, sections)) in self.locations.header_entries_enumerate() {
+            let (x, z) = self.chunk_coords(index);
+            // offset 1 is used by external chunks but without an .mcc file
+            // there is nothing the pointer can refer to
+            let orphan_external = offset == 1
+                && !self
+                    .external_chunk_path(index)
+                    .map(|path| path.exists())
+                    .unwrap_or(false);
+
+            if orphan_external {
+                statistic.orphan_external_pointer += 1;
+                log::debug!(
+                    "Chunk ({},{}) points at offset 1 but has no external file",
+                    x,
+                    z
+                );
+                statistic.add_event(options, || ScanEvent::OrphanExternalPointer { x, z });
+                if options.fix_delete {
+                    self.delete_chunk(index)?;
+                    statistic.orphan_external_pointer_fixed += 1;
+                }
+            } else {
+                statistic.invalid_chunk_pointer += 1;
+                log::debug!(
+                    "Chunk ({},{}) points into the header: {} + {}",
+                    x,
+                    z,
+                    offset,
+                    sections
+                );
+                statistic.add_event(options, || ScanEvent::InvalidPointer {
+                    x,
+                    z,
+                    offset,
+                    sectors: sections,
+                });
+                if options.fix_delete {
+                    self.delete_chunk(index)?;
+                    statistic.invalid_chunk_pointer_fixed += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Opens the external .mcc file storing the data of the chunk at the given index
+    fn open_external_chunk(&self, index: usize) -> Result<BufReader<File>> {
+        let file = File::open(self.external_chunk_path(index)?)?;
+
+        Ok(BufReader::new(file))
+    }
+
+    /// Returns the path of the external .mcc file of the chunk at the given index
+    fn external_chunk_path(&self, index: usize) -> Result<PathBuf> {
+        let (region_x, region_z) = parse_region_coords(&self.path).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid region file name {:?}", self.path),
+            )
+        })?;
+        let (x, z) = local_coords(index);
+        let (x, z) = (region_x * 32 + x, region_z * 32 + z);
+
+        Ok(self.path.with_file_name(format!("c.{}.{}.mcc", x, z)))
+    }
+
+    /// Deletes a chunk and shifts all other chunks
+    pub fn delete_chunk(&mut self, index: usize) -> Result<()> {
+        let (offset, _) = self.locations.delete_chunk_entry(index).ok_or_else(|| {
+            log::error!("Failed to delete chunk at invalid index {}", index);
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid chunk index {}", index),
+            )
+        })?;
+        log::debug!("Deleting chunk at {}", offset);
+        self.timestamps.set(index, 0);
+        let (x, z) = self.chunk_coords(index);
+        self.repairs.push(RepairAction::DeletedChunk { x, z });
+        Ok(())
+    }
+
+    /// Moves the locations entry and timestamp of a chunk to another index keeping its data
+    fn relocate_chunk(&mut self, index: usize, new_index: usize) -> Result<()> {
+        let entry = self.locations.delete_chunk_entry(index).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid chunk index {}", index),
+            )
+        })?;
+        self.locations
+            .replace_entry(new_index, entry)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Invalid chunk index {}", new_index),
+                )
+            })?;
+        log::debug!(
+            "Moving chunk at {} from index {} to {}",
+            entry.0,
+            index,
+            new_index
+        );
+        self.timestamps.set(new_index, self.timestamps.get(index));
+        self.timestamps.set(index, 0);
+        let (x, z) = self.chunk_coords(index);
+        let (to_x, to_z) = self.chunk_coords(new_index);
+        self.repairs
+            .push(RepairAction::MovedChunk { x, z, to_x, to_z });
+
+        Ok(())
+    }
+
+    /// Checks that the file ends at a sector boundary and pads it with zeros if it doesn't
+    /// since shifting chunks expects every sector to be complete
+    fn check_alignment(
+        &mut self,
+        statistic: &mut ScanStatistics,
+        options: &Arc<ScanOptions>,
+    ) -> Result<()> {
+        if self.length.is_multiple_of(BLOCK_SIZE as u64) {
+            return Ok(());
+        }
+        statistic.unaligned_file += 1;
+
+        if options.fix {
+            let length = self.length;
+            let padded_length = length.next_multiple_of(BLOCK_SIZE as u64);
+            log::debug!(
+                "Padding region file {:?} from {} to {} bytes",
+                self.path,
+                length,
+                padded_length
+            );
+            self.writer()?.get_mut().set_len(padded_length)?;
+            self.length = padded_length;
+            self.repairs.push(RepairAction::ResizedFile {
+                from: length,
+                to: padded_length,
+            });
+            statistic.unaligned_file_fixed += 1;
+            statistic.add_event(options, || ScanEvent::PaddedFile {
+                length,
+                padded_length,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks that exactly the chunks with a location entry have a timestamp
+    fn check_timestamps(&mut self, statistic: &mut ScanStatistics, options: &Arc<ScanOptions>) {
+        let now = current_timestamp();
+
+        for index in 0..BLOCK_SIZE / 4 {
+            let has_location = self.locations.get_chunk_entry_unchecked(index).0 != 0;
+            let timestamp = self.timestamps.get(index);
+            let (x, z) = self.chunk_coords(index);
+            if has_location && timestamp > now.saturating_add(MAX_TIMESTAMP_SKEW) {
+                statistic.future_timestamp += 1;
+                log::debug!(
+                    "Timestamp {} of chunk ({},{}) lies in the future",
+                    timestamp,
+                    x,
+                    z
+                );
+                statistic.add_event(options, || ScanEvent::FutureTimestamp { x, z });
+
+                if options.fix {
+                    self.timestamps.set(index, now);
+                    statistic.future_timestamp_fixed += 1;
+                    self.repairs.push(RepairAction::SetTimestamp {
+                        x,
+                        z,
+                        timestamp: now,
+                    });
+                }
+                continue;
+            }
+            if has_location == (timestamp != 0) {
+                continue;
+            }
+            statistic.mismatched_timestamps += 1;
+            log::debug!(
+                "Timestamp {} doesn't match the location entry of chunk ({},{})",
+                timestamp,
+                x,
+                z
+            );
+            statistic.add_event(options, || ScanEvent::MismatchedTimestamp { x, z });
+
+            if options.fix {
+                let timestamp = if has_location { now } else { 0 };
+                self.timestamps.set(index, timestamp);
+                statistic.mismatched_timestamps_fixed += 1;
+                self.repairs
+                    .push(RepairAction::SetTimestamp { x, z, timestamp });
+            }
+        }
+    }
+
+    /// Reconstructs the locations and timestamps tables by searching every sector of
+    /// the file for valid chunks and writes them to the file. Recovered chunks get the
+    /// modification time of the file as their timestamp.
+    /// Returns the number of recovered chunks
+    pub fn rebuild_locations(&mut self) -> Result<usize> {
+        let mut locations = Locations::from_bytes(&[0u8; BLOCK_SIZE]);
+        let mut timestamps = Timestamps::from_bytes(&[0u8; BLOCK_SIZE]);
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or_else(current_timestamp);
+        let sector_count = (self.length / BLOCK_SIZE as u64) as u32;
+        let region = parse_region_coords(&self.path);
+        let mut recovered = 0;
+        let mut offset = 2;
+
+        while offset < sector_count {
+            let reader_offset = offset as u64 * BLOCK_SIZE as u64;
+            self.access.seek_reader(reader_offset)?;
+
+            let mut chunk = match Chunk::from_buf_reader(self.access.reader()?) {
+                Ok(chunk)
+                    if !chunk.is_external()
+                        && is_valid_compression_method(chunk.compression_method()) =>
+                {
+                    chunk
+                }
+                _ => {
+                    offset += 1;
+                    continue;
+                }
+            };
+            if chunk
+                .validate_nbt_data(
+                    self.access.reader()?,
+                    self.folder,
+                    self.format,
+                    DEFAULT_MAX_DECOMPRESSED_BYTES,
+                    DEFAULT_MAX_DEPTH,
+                    None,
+                )
+                .is_err()
+            {
+                offset += 1;
+                continue;
+            }
+            let sections = ((chunk.length + 4) as f64 / BLOCK_SIZE as f64).ceil() as u32;
+
+            if let (Some(x), Some(z)) = (chunk.x_pos, chunk.z_pos) {
+                let in_region = region
+                    .map(|(region_x, region_z)| {
+                        x.div_euclid(32) == region_x && z.div_euclid(32) == region_z
+                    })
+                    .unwrap_or(true);
+                let index = get_chunk_index(x as isize, z as isize);
+
+                if !in_region {
+                    log::debug!(
+                        "Ignoring chunk ({},{}) at {} from another region",
+                        x,
+                        z,
+                        offset
+                    );
+                } else if locations.get_chunk_entry_unchecked(index).0 != 0 {
+                    log::debug!("Ignoring duplicate of chunk ({},{}) at {}", x, z, offset);
+                } else if sections > u8::MAX as u32 {
+                    log::debug!(
+                        "Ignoring chunk ({},{}) at {} spanning {} sectors",
+                        x,
+                        z,
+                        offset,
+                        sections
+                    );
+                } else {
+                    log::debug!("Recovered chunk ({},{}) at {}", x, z, offset);
+                    locations.replace_entry_unchecked(index, (offset, sections as u8));
+                    timestamps.set(index, modified);
+                    recovered += 1;
+                }
+            }
+            offset += sections.max(1);
+        }
+
+        let locations_raw = locations.to_bytes();
+        let timestamps_raw = timestamps.to_bytes();
+        let writer = self.writer()?;
+        writer.seek(SeekFrom::Start(0))?;
+        writer.write_all(locations_raw.as_slice())?;
+        writer.write_all(timestamps_raw.as_slice())?;
+        self.close()?;
+        self.locations = locations;
+        self.timestamps = timestamps;
+
+        Ok(recovered)
+    }
+
+    /// Overwrites the data between the `start` and `end` byte positions with zeros
+    fn wipe(&mut self, start: u64, end: u64) -> Result<()> {
+        log::debug!("Wiping free space between {} and {}", start, end);
+        let buf = [0u8; BLOCK_SIZE];
+        let writer = self.writer()?;
+        writer.seek(SeekFrom::Start(start))?;
+        let mut position = start;
+
+        while position < end {
+            let amount = (end - position).min(BLOCK_SIZE as u64);
+            writer.write_all(&buf[..amount as usize])?;
+            position += amount;
+        }
+        writer.flush()
+    }
+
+    /// Closes the region file by flushing the writer
+    pub fn close(&mut self) -> Result<()> {
+        self.access.flush()
+    }
+
+    /// Returns the writer or an error if the file was opened read-only
+    fn writer(&mut self) -> Result<&mut BufWriter<StorageHandle>> {
+        if self.access.is_writable() {
+            self.back_up()?;
+        }
+        let path = &self.path;
+        self.access.writer().ok_or_else(|| {
+            Error::new(
+                ErrorKind::PermissionDenied,
+                format!("Region file {:?} was opened read-only", path),
+            )
+        })
+    }
+}
+
+/// Returns an error if the region file at the path must not be modified
+fn check_writable(path: &Path) -> Result<()> {
+    // fixing could write chunks in a compression the game can't read in these files
+    if RegionFormat::from_path(path) == RegionFormat::McRegion {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("McRegion file {:?} can only be read", path),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// A storage a region file can be read from and modified in place
+pub trait RegionStorage: Read + Write + Seek + Send {
+    /// Truncates or extends the storage to the given length
+    fn set_len(&mut self, length: u64) -> Result<()>;
+}
+
+impl RegionStorage for File {
+    fn set_len(&mut self, length: u64) -> Result<()> {
+        File::set_len(self, length)
+    }
+}
+
+impl RegionStorage for Cursor<Vec<u8>> {
+    fn set_len(&mut self, length: u64) -> Result<()> {
+        self.get_mut().resize(length as usize, 0);
+
+        Ok(())
+    }
+}
+
+/// A handle to a shared storage with its own position so that the reader and
+/// writer of a region file can use the same storage independently
+#[derive(Clone)]
+struct StorageHandle {
+    storage: Arc<Mutex<Box<dyn RegionStorage>>>,
+    position: u64,
+}
+
+impl StorageHandle {
+    fn new<S: RegionStorage + 'static>(storage: S) -> Self {
+        Self {
+            storage: Arc::new(Mutex::new(Box::new(storage))),
+            position: 0,
+        }
+    }
+
+    /// Truncates or extends the storage to the given length
+    fn set_len(&mut self, length: u64) -> Result<()> {
+        self.with_storage(|storage| storage.set_len(length))
+    }
+
+    /// Runs the operation on the storage at the position of this handle
+    fn with_storage<T, F: FnOnce(&mut dyn RegionStorage) -> Result<T>>(
+        &mut self,
+        operation: F,
+    ) -> Result<T> {
+        let mut storage = self
+            .storage
+            .lock()
+            .map_err(|_| Error::other("Region storage is poisoned"))?;
+        storage.seek(SeekFrom::Start(self.position))?;
+        let result = operation(storage.as_mut())?;
+        self.position = storage.stream_position()?;
+
+        Ok(result)
+    }
+}
+
+impl Read for StorageHandle {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.with_storage(|storage| storage.read(buf))
+    }
+}
+
+impl Write for StorageHandle {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.with_storage(|storage| storage.write(buf))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.with_storage(|storage| storage.flush())
+    }
+}
+
+impl Seek for StorageHandle {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.with_storage(|storage| storage.seek(pos))
+    }
+}
+
+/// The reader of a region file either reading from the file, a copy in memory
+/// or a storage passed to [RegionFile::from_storage]
+enum RegionReader {
+    Buffered(BufReader<File>),
+    InMemory(Cursor<Vec<u8>>),
+    Storage(BufReader<StorageHandle>),
+}
+
+impl Read for RegionReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match self {
+            Self::Buffered(reader) => reader.read(buf),
+            Self::InMemory(reader) => reader.read(buf),
+            Self::Storage(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl Seek for RegionReader {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        match self {
+            Self::Buffered(reader) => reader.seek(pos),
+            Self::InMemory(reader) => reader.seek(pos),
+            Self::Storage(reader) => reader.seek(pos),
+        }
+    }
+}
+
+use access::RegionAccess;
+
+mod access {
+    use super::{RegionReader, StorageHandle};
+    use std::io::{BufWriter, Result, Seek, SeekFrom, Write};
+
+    /// The reader and writer of a region file. The reader is only handed out after
+    /// flushing the writer so that reads always see the data written before
+    pub(super) struct RegionAccess {
+        reader: RegionReader,
+        writer: Option<BufWriter<StorageHandle>>,
+        /// If the writer was handed out since the buffer of the reader was discarded
+        stale: bool,
+    }
+
+    impl RegionAccess {
+        pub(super) fn new(reader: RegionReader, writer: Option<BufWriter<StorageHandle>>) -> Self {
+            Self {
+                reader,
+                writer,
+                stale: false,
+            }
+        }
+
+        /// Returns the reader at its current position. If data was written since the
+        /// last read the writer is flushed and the buffered data of the reader discarded
+        pub(super) fn reader(&mut self) -> Result<&mut RegionReader> {
+            if self.stale {
+                let position = self.reader.stream_position()?;
+                self.seek_reader(position)?;
             }
+
+            Ok(&mut self.reader)
         }
 
-        Ok(true)
-    }
+        /// Seeks the reader to the position after flushing the writer so that
+        /// everything written before can be read
+        pub(super) fn seek_reader(&mut self, position: u64) -> Result<&mut RegionReader> {
+            self.flush()?;
+            self.reader.seek(SeekFrom::Start(position))?;
+            self.stale = false;
 
-    /// Deletes a chunk and shifts all other chunks
-    pub fn delete_chunk(&mut self, index: usize) -> Result<()> {
-        log::debug!(
-            "Deleting chunk at {}",
-            self.locations.get_chunk_entry_unchecked(index).0
-        );
-        self.locations.delete_chunk_entry_unchecked(index);
-        Ok(())
-    }
+            Ok(&mut self.reader)
+        }
 
-    /// Shifts the file from the `offset` position `amount` blocks to the right
-    pub fn shift_right(
-        &mut self,
-        start_offset: usize,
-        end_offset: usize,
-        amount: isize,
-    ) -> Result<()> {
-        log::debug!(
-            "Shifting chunk blocks starting from {} by {} until {}",
-            start_offset,
-            amount,
-            end_offset,
-        );
-        // seek to the start of the data to be shifted
-        self.reader
-            .seek(SeekFrom::Start((start_offset * BLOCK_SIZE) as u64))?;
-        // seek to the start of the data to be shifted
-        self.writer
-            .seek(SeekFrom::Start((start_offset * BLOCK_SIZE) as u64))?;
-        // seek the amount the data should be shifted
-        self.writer
-            .seek(SeekFrom::Current(amount as i64 * BLOCK_SIZE as i64))?;
-
-        for _ in 0..(end_offset - start_offset) {
-            // since the offset is based on the fixed BLOCK_SIZE we can use that as our buffer size
-            let mut buf = [0u8; BLOCK_SIZE];
-            let read = self.reader.read(&mut buf)?;
-            self.writer.write(&buf)?;
-
-            if read < BLOCK_SIZE {
-                break;
-            }
+        /// Returns the writer or `None` if the file was opened read-only
+        pub(super) fn writer(&mut self) -> Option<&mut BufWriter<StorageHandle>> {
+            self.stale = self.writer.is_some();
+            self.writer.as_mut()
         }
 
-        Ok(())
-    }
+        pub(super) fn is_writable(&self) -> bool {
+            self.writer.is_some()
+        }
 
-    /// Closes the region file by flushing the writer
-    pub fn close(&mut self) -> Result<()> {
-        self.writer.flush()
+        /// Writes the buffered data of the writer to the storage
+        pub(super) fn flush(&mut self) -> Result<()> {
+            match &mut self.writer {
+                Some(writer) => writer.flush(),
+                None => Ok(()),
+            }
+        }
     }
 }
 
+/// A sha256 checksum of the chunks of a region file or world
+pub type Checksum = [u8; 32];
+
+/// The location and size of a chunk read from the header tables and the chunk header
+#[derive(Clone, Debug, Serialize)]
+pub struct ChunkSize {
+    pub region_x: i32,
+    pub region_z: i32,
+    pub x: i32,
+    pub z: i32,
+    pub sectors: u8,
+    pub length: u32,
+    pub compression_type: u8,
+    pub timestamp: u32,
+}
+
 #[derive(Debug)]
 pub struct Locations {
     inner: Vec<(u32, u8)>,
@@ -350,12 +2272,12 @@ impl Locations {
 
     /// Returns the offset of a chunk
     pub fn get_chunk_offset(&self, x: isize, z: isize) -> Option<u32> {
-        self.inner.get(get_chunk_index(x, z)).map(|e| (*e).0)
+        self.inner.get(get_chunk_index(x, z)).map(|e| e.0)
     }
 
     /// Returns the number of sectors for a chunk
     pub fn get_chunk_sectors(&self, x: isize, z: isize) -> Option<u8> {
-        self.inner.get(get_chunk_index(x, z)).map(|e| (*e).1)
+        self.inner.get(get_chunk_index(x, z)).map(|e| e.1)
     }
 
     /// Returns chunk entry list
@@ -363,9 +2285,9 @@ impl Locations {
         self.inner
             .iter()
             .enumerate()
-            .filter_map(|e| {
-                if (*e.1).0 >= 2 {
-                    Some((e.0, *e.1))
+            .filter_map(|(index, entry)| {
+                if entry.0 >= 2 {
+                    Some((index, *entry))
                 } else {
                     None
                 }
@@ -373,27 +2295,29 @@ impl Locations {
             .collect()
     }
 
-    /// The maximum offset in the file
-    pub fn max_offset(&self) -> u32 {
-        let largest = self
-            .inner
+    /// Returns the entries that aren't empty but point into the header
+    pub fn header_entries_enumerate(&self) -> Vec<(usize, (u32, u8))> {
+        self.inner
             .iter()
-            .max_by(|(a, _), (b, _)| {
-                if a > b {
-                    Ordering::Greater
-                } else if a < b {
-                    Ordering::Less
-                } else {
-                    Ordering::Equal
-                }
-            })
-            .cloned()
-            .unwrap_or((2, 0));
+            .enumerate()
+            .filter(|(_, entry)| entry.0 < 2 && **entry != (0, 0))
+            .map(|(index, entry)| (index, *entry))
+            .collect()
+    }
 
-        largest.0 + largest.1 as u32
+    /// Returns the sector after the chunk ending last. Chunks starting earlier
+    /// can end after the one starting last if they overlap
+    pub fn max_offset(&self) -> u32 {
+        self.inner
+            .iter()
+            .filter(|(offset, _)| *offset >= 2)
+            .map(|(offset, sectors)| offset + *sectors as u32)
+            .max()
+            // the header always occupies the first two blocks
+            .unwrap_or(2)
     }
 
-    /// Returns the estimated of all chunks combined including the header
+    /// Returns the size of the header and all chunks up to the end of the chunk ending last
     pub fn estimated_size(&self) -> u64 {
         self.max_offset() as u64 * BLOCK_SIZE as u64
     }
@@ -413,27 +2337,92 @@ impl Locations {
         self.inner[index] = (0, 0);
     }
 
-    /// Shifts all entries starting from `start_index` by `amount`
-    pub fn shift_entries(&mut self, start_offset: u32, end_offset: u32, amount: i32) {
-        log::debug!(
-            "Shifting location entries starting from {} by {} until {}",
-            start_offset,
-            amount,
-            end_offset
-        );
-        self.inner = self
-            .inner
+    /// Replaces an entry with a new one.
+    /// Returns the previous entry or `None` if the index doesn't exist
+    pub fn replace_entry(&mut self, index: usize, entry: (u32, u8)) -> Option<(u32, u8)> {
+        self.inner
+            .get_mut(index)
+            .map(|e| std::mem::replace(e, entry))
+    }
+
+    /// Returns a chunk entry for an index or `None` if it doesn't exist
+    pub fn get_chunk_entry(&self, index: usize) -> Option<(u32, u8)> {
+        self.inner.get(index).cloned()
+    }
+
+    /// Sets a chunk entry to not generated.
+    /// Returns the deleted entry or `None` if the index doesn't exist
+    pub fn delete_chunk_entry(&mut self, index: usize) -> Option<(u32, u8)> {
+        self.replace_entry(index, (0, 0))
+    }
+}
+
+/// The sectors occupied by the chunks whose location entries point at the same offset
+struct ChunkBlock {
+    offset: u32,
+    sectors: u8,
+    indices: Vec<usize>,
+}
+
+/// The number of chunks occupying each sector of a region file
+#[derive(Debug)]
+pub struct SectorMap {
+    inner: Vec<u8>,
+}
+
+impl SectorMap {
+    /// Marks the sectors of all chunks of the locations table. Sectors past
+    /// the given number of sectors of the file are ignored
+    pub fn new(locations: &Locations, sector_count: u32) -> Self {
+        let mut inner = vec![0u8; sector_count as usize];
+        // the header tables occupy the first two sectors
+        for sector in inner.iter_mut().take(2) {
+            *sector = 1;
+        }
+        for (_, (offset, sectors)) in locations.valid_entries_enumerate() {
+            let start = (offset as usize).min(inner.len());
+            let end = (offset as usize + sectors as usize).min(inner.len());
+            for sector in &mut inner[start..end] {
+                *sector = sector.saturating_add(1);
+            }
+        }
+
+        Self { inner }
+    }
+
+    /// Returns if no chunk occupies the sector
+    pub fn is_free(&self, sector: u32) -> bool {
+        self.inner.get(sector as usize) == Some(&0)
+    }
+
+    /// Returns the number of unused sectors before the last used one
+    pub fn free_sectors(&self) -> u64 {
+        let end = self.inner.iter().rposition(|count| *count > 0).unwrap_or(0);
+
+        self.inner[..end]
             .iter()
-            .map(|e| {
-                let mut entry = *e;
+            .filter(|count| **count == 0)
+            .count() as u64
+    }
 
-                if e.0 >= start_offset && e.0 <= end_offset {
-                    entry.0 = (entry.0 as i32 + amount) as u32;
-                }
+    /// Returns the start and end sector of each run of unused sectors
+    pub fn free_ranges(&self) -> Vec<(u32, u32)> {
+        let mut ranges: Vec<(u32, u32)> = Vec::new();
 
-                entry
-            })
-            .collect();
+        for (sector, _) in self.inner.iter().enumerate().filter(|(_, c)| **c == 0) {
+            let sector = sector as u32;
+            match ranges.last_mut() {
+                Some((_, end)) if *end == sector => *end += 1,
+                _ => ranges.push((sector, sector + 1)),
+            }
+        }
+
+        ranges
+    }
+
+    /// Returns the number of sectors occupied by more than one chunk
+    pub fn double_allocated_sectors(&self) -> u64 {
+        self.inner.iter().filter(|count| **count > 1).count() as u64
     }
 }
 
@@ -452,6 +2441,75 @@ impl Timestamps {
 
         Self { inner: timestamps }
     }
+
+    /// Returns the byte representation of the timestamps table
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0u8; BLOCK_SIZE];
+
+        for (i, timestamp) in self.inner.iter().enumerate() {
+            BigEndian::write_u32(&mut bytes[i * 4..i * 4 + 4], *timestamp);
+        }
+
+        bytes
+    }
+
+    /// Returns the timestamp of the last modification of the chunk at the index
+    pub fn get(&self, index: usize) -> u32 {
+        self.inner[index]
+    }
+
+    /// Sets the timestamp of the chunk at the index
+    pub fn set(&mut self, index: usize, timestamp: u32) {
+        self.inner[index] = timestamp;
+    }
+}
+
+/// Returns the current time in seconds since the unix epoch as stored in the timestamps table
+fn current_timestamp() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0)
+}
+
+/// Returns the region coordinates encoded in a r.<x>.<z>.mca or r.<x>.<z>.mcr file name.
+/// Returns `None` if the name has a different format
+pub fn parse_region_coords(path: &Path) -> Option<(i32, i32)> {
+    let name = path.file_name()?.to_str()?;
+    let mut parts = name.split('.');
+    parts.next().filter(|p| *p == "r")?;
+    let x = parts.next()?.parse().ok()?;
+    let z = parts.next()?.parse().ok()?;
+    parts
+        .next()
+        .filter(|p| *p == ANVIL_EXTENSION || *p == MCREGION_EXTENSION)?;
+
+    if parts.next().is_some() {
+        None
+    } else {
+        Some((x, z))
+    }
+}
+
+/// Returns the chunk coordinates encoded in a c.<x>.<z>.mcc file name
+pub(crate) fn external_chunk_coords(path: &Path) -> Option<(i32, i32)> {
+    let name = path.file_name()?.to_str()?;
+    let mut parts = name.split('.');
+    parts.next().filter(|p| *p == "c")?;
+    let x = parts.next()?.parse().ok()?;
+    let z = parts.next()?.parse().ok()?;
+    parts.next().filter(|p| *p == "mcc")?;
+
+    if parts.next().is_some() {
+        None
+    } else {
+        Some((x, z))
+    }
+}
+
+/// Returns the coordinates of the chunk at the index inside of its region
+pub fn local_coords(index: usize) -> (i32, i32) {
+    ((index % 32) as i32, (index / 32) as i32)
 }
 
 #[inline]
@@ -467,3 +2525,732 @@ fn get_chunk_index(x: isize, z: isize) -> usize {
 
     x as usize + z as usize * 32
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Returns an empty directory for the files of a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "minecraft-regions-tool-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    /// Returns the serialized nbt data of a valid chunk with `size` bytes that don't compress
+    fn chunk_nbt(x: i32, z: i32, size: usize) -> Vec<u8> {
+        let mut seed = (x as u32).wrapping_mul(31).wrapping_add(z as u32) as u64 + 1;
+        let data = (0..size)
+            .map(|_| {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (seed >> 56) as u8
+            })
+            .collect();
+        let mut level = HashMap::new();
+        level.insert(String::from("xPos"), NBTValue::Int(x));
+        level.insert(String::from("zPos"), NBTValue::Int(z));
+        level.insert(String::from("Data"), NBTValue::ByteArray(data));
+        level.insert(String::from("Sections"), NBTValue::List(Vec::new()));
+        level.insert(String::from("LastUpdate"), NBTValue::Long(0));
+        level.insert(String::from("InhabitedTime"), NBTValue::Long(0));
+        level.insert(String::from("Entities"), NBTValue::List(Vec::new()));
+        level.insert(String::from("TileEntities"), NBTValue::List(Vec::new()));
+        level.insert(String::from("Status"), NBTValue::String("full".into()));
+        let mut root = HashMap::new();
+        root.insert(String::from("Level"), NBTValue::Compound(level));
+        let mut writer = NBTWriter::new(Vec::new());
+        writer.write(&root).unwrap();
+
+        writer.into_inner()
+    }
+
+    /// Returns the record of a chunk with its length, compression method and data
+    fn chunk_record(compression: u8, data: &[u8]) -> Vec<u8> {
+        let mut record = Vec::new();
+        record
+            .write_u32::<BigEndian>(data.len() as u32 + 1)
+            .unwrap();
+        record.push(compression);
+        record.extend_from_slice(data);
+
+        record
+    }
+
+    /// Returns the record of a zlib compressed chunk at the local coordinates
+    fn zlib_record(x: i32, z: i32, size: usize) -> Vec<u8> {
+        let data = compress_nbt_data(&chunk_nbt(x, z, size), 2, Compression::default()).unwrap();
+
+        chunk_record(2, &data)
+    }
+
+    /// Writes a region file with the records at the given offsets and sector counts
+    /// and returns its path
+    fn write_region(dir: &Path, chunks: &[(usize, u32, u8, Vec<u8>)], sectors: u32) -> PathBuf {
+        let mut data = vec![0u8; sectors as usize * BLOCK_SIZE];
+        for (index, offset, chunk_sectors, record) in chunks {
+            let entry = (offset << 8) | *chunk_sectors as u32;
+            BigEndian::write_u32(&mut data[index * 4..index * 4 + 4], entry);
+            let start = *offset as usize * BLOCK_SIZE;
+            data[start..start + record.len()].copy_from_slice(record);
+        }
+        let path = dir.join("r.0.0.mca");
+        fs::write(&path, data).unwrap();
+
+        path
+    }
+
+    /// Returns the record of the chunk at the index as it's stored in the file
+    fn read_record(path: &PathBuf, index: usize) -> Option<Vec<u8>> {
+        let region_file = RegionFile::open_read_only(path).unwrap();
+        let (offset, _) = region_file.locations().get_chunk_entry_unchecked(index);
+        if offset < 2 {
+            return None;
+        }
+        let data = fs::read(path).unwrap();
+        let start = offset as usize * BLOCK_SIZE;
+        let length = BigEndian::read_u32(&data[start..start + 4]) as usize;
+
+        Some(data[start..start + 4 + length].to_vec())
+    }
+
+    fn defrag_options() -> Arc<ScanOptions> {
+        Arc::new(ScanOptions::new().defrag(true).skip_nbt(true))
+    }
+
+    #[test]
+    fn region_coordinates_are_parsed_from_file_names() {
+        let coords = |name: &str| parse_region_coords(Path::new(name));
+
+        assert_eq!(coords("r.0.0.mca"), Some((0, 0)));
+        assert_eq!(coords("world/region/r.-1.-1.mca"), Some((-1, -1)));
+        assert_eq!(coords("r.5.-3.mcr"), Some((5, -3)));
+        assert_eq!(coords("r.a.0.mca"), None);
+        assert_eq!(coords("r.0.0.0.mca"), None);
+        assert_eq!(coords("r.0.0.mca.bak"), None);
+        assert_eq!(coords("c.0.0.mcc"), None);
+        assert_eq!(coords("level.dat"), None);
+    }
+
+    #[test]
+    fn files_too_small_for_the_header_fail_to_open() {
+        let dir = test_dir("too-small");
+        let path = dir.join("r.0.0.mca");
+
+        for size in [0, BLOCK_SIZE, 2 * BLOCK_SIZE - 1] {
+            fs::write(&path, vec![0u8; size]).unwrap();
+            let error = RegionFile::open_read_only(&path).err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "region file too small: {} bytes, expected >= {}",
+                    size,
+                    2 * BLOCK_SIZE
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn chunks_ending_on_a_sector_boundary_have_a_valid_length() {
+        let dir = test_dir("sector-boundary");
+        // the length field and the data fill exactly one sector
+        let exact = chunk_record(2, &vec![0u8; BLOCK_SIZE - 5]);
+        assert_eq!(exact.len(), BLOCK_SIZE);
+        // one more byte needs a second sector
+        let longer = chunk_record(2, &vec![0u8; BLOCK_SIZE - 4]);
+        let path = write_region(&dir, &[(0, 2, 1, exact), (1, 3, 1, longer)], 5);
+
+        let mut region_file = RegionFile::open_read_only(&path).unwrap();
+        let options = Arc::new(ScanOptions::new().skip_nbt(true));
+        let statistic = region_file.scan_chunks(&options).unwrap();
+
+        assert_eq!(statistic.invalid_length, 1);
+    }
+
+    #[test]
+    fn files_ending_in_a_partial_sector_are_padded() {
+        let dir = test_dir("unaligned");
+        // the record ends two bytes into its second sector
+        let record = chunk_record(2, &vec![0u8; BLOCK_SIZE - 3]);
+        let path = write_region(&dir, &[(0, 2, 2, record)], 4);
+        let original = fs::read(&path).unwrap()[..12290].to_vec();
+        fs::write(&path, &original).unwrap();
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        let options = Arc::new(ScanOptions::new().fix(true).skip_nbt(true));
+        let statistic = region_file.scan_chunks(&options).unwrap();
+        drop(region_file);
+
+        assert_eq!(statistic.unaligned_file, 1);
+        assert_eq!(statistic.unaligned_file_fixed, 1);
+        let data = fs::read(&path).unwrap();
+        assert_eq!(data.len(), 4 * BLOCK_SIZE);
+        assert_eq!(data[2 * BLOCK_SIZE..12290], original[2 * BLOCK_SIZE..]);
+        assert!(data[12290..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn duplicate_pointers_keep_the_slot_matching_the_chunk() {
+        let dir = test_dir("duplicate-pointer");
+        let record = zlib_record(0, 0, 100);
+        let path = write_region(&dir, &[(0, 2, 1, record.clone()), (5, 2, 1, Vec::new())], 3);
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        let options = Arc::new(ScanOptions::new().fix(true).fix_delete(true));
+        let statistic = region_file.scan_chunks(&options).unwrap();
+        drop(region_file);
+
+        assert_eq!(statistic.duplicate_pointer, 1);
+        assert_eq!(statistic.duplicate_pointer_fixed, 1);
+        let region_file = RegionFile::open_read_only(&path).unwrap();
+        assert_eq!(region_file.count_chunks(), 1);
+        assert_eq!(region_file.locations().get_chunk_entry_unchecked(5), (0, 0));
+        assert_eq!(read_record(&path, 0), Some(record));
+    }
+
+    #[test]
+    fn swapped_pointers_move_the_chunk_to_its_slot() {
+        let dir = test_dir("swapped-pointer");
+        let (slot, swapped) = (get_chunk_index(3, 1), get_chunk_index(1, 3));
+        let record = zlib_record(3, 1, 100);
+        let path = write_region(&dir, &[(swapped, 2, 1, record.clone())], 3);
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        let statistic = region_file
+            .scan_chunks(&Arc::new(ScanOptions::new().fix(true)))
+            .unwrap();
+        drop(region_file);
+
+        assert_eq!(statistic.swapped_pointer, 1);
+        assert_eq!(statistic.swapped_pointer_fixed, 1);
+        let region_file = RegionFile::open_read_only(&path).unwrap();
+        assert_eq!(
+            region_file.locations().get_chunk_entry_unchecked(slot),
+            (2, 1)
+        );
+        assert_eq!(
+            region_file.locations().get_chunk_entry_unchecked(swapped),
+            (0, 0)
+        );
+        assert_eq!(read_record(&path, slot), Some(record));
+    }
+
+    #[test]
+    fn swapped_pointers_leave_an_occupied_slot_alone() {
+        let dir = test_dir("swapped-pointer-occupied");
+        let (slot, swapped) = (get_chunk_index(3, 1), get_chunk_index(1, 3));
+        let record = zlib_record(3, 1, 100);
+        let copy = zlib_record(3, 1, 100);
+        let path = write_region(
+            &dir,
+            &[(slot, 2, 1, record.clone()), (swapped, 3, 1, copy.clone())],
+            4,
+        );
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        let statistic = region_file
+            .scan_chunks(&Arc::new(ScanOptions::new().fix(true)))
+            .unwrap();
+        drop(region_file);
+
+        assert_eq!(statistic.swapped_pointer, 1);
+        assert_eq!(statistic.swapped_pointer_fixed, 0);
+        let region_file = RegionFile::open_read_only(&path).unwrap();
+        assert_eq!(
+            region_file.locations().get_chunk_entry_unchecked(slot),
+            (2, 1)
+        );
+        assert_eq!(
+            region_file.locations().get_chunk_entry_unchecked(swapped),
+            (3, 1)
+        );
+        assert_eq!(read_record(&path, slot), Some(record));
+        assert_eq!(read_record(&path, swapped), Some(copy));
+    }
+
+    #[test]
+    fn overlapping_chunks_are_counted_and_the_later_one_deleted() {
+        let dir = test_dir("overlapping");
+        let first = zlib_record(0, 0, 100);
+        let chunks = [(0, 2, 2, first.clone()), (1, 3, 1, zlib_record(1, 0, 100))];
+
+        let path = write_region(&dir, &chunks, 4);
+        let mut region_file = RegionFile::open_read_only(&path).unwrap();
+        let statistic = region_file
+            .scan_chunks(&Arc::new(ScanOptions::new()))
+            .unwrap();
+        assert_eq!(statistic.overlapping_chunks, 1);
+        assert_eq!(statistic.overlapping_chunks_fixed, 0);
+        drop(region_file);
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        let options = Arc::new(ScanOptions::new().fix(true).fix_delete(true));
+        let statistic = region_file.scan_chunks(&options).unwrap();
+        drop(region_file);
+
+        assert_eq!(statistic.overlapping_chunks, 1);
+        assert_eq!(statistic.overlapping_chunks_fixed, 1);
+        let region_file = RegionFile::open_read_only(&path).unwrap();
+        assert_eq!(region_file.count_chunks(), 1);
+        assert_eq!(region_file.locations().get_chunk_entry_unchecked(1), (0, 0));
+        assert_eq!(read_record(&path, 0), Some(first));
+    }
+
+    #[test]
+    fn uncompressed_chunks_are_valid_unlike_method_zero() {
+        let dir = test_dir("uncompressed");
+        let path = write_region(
+            &dir,
+            &[
+                (0, 2, 1, chunk_record(3, &chunk_nbt(0, 0, 100))),
+                (1, 3, 1, chunk_record(0, &chunk_nbt(1, 0, 100))),
+            ],
+            4,
+        );
+
+        let mut region_file = RegionFile::open_read_only(&path).unwrap();
+        let statistic = region_file
+            .scan_chunks(&Arc::new(ScanOptions::new()))
+            .unwrap();
+
+        assert_eq!(statistic.invalid_compression_method, 1);
+        assert_eq!(statistic.corrupted_compression, 0);
+        assert_eq!(statistic.corrupted_nbt, 0);
+        assert_eq!(statistic.missing_nbt, 0);
+    }
+
+    #[test]
+    fn unreadable_chunks_dont_stop_the_scan_of_their_neighbours() {
+        let dir = test_dir("unreadable");
+        // the length exceeds the largest possible chunk so the chunk header can't be read
+        let mut unreadable = chunk_record(2, &[0u8; 16]);
+        unreadable[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        let invalid_nbt = compress_nbt_data(&[10, 0, 0, 99], 2, Compression::default()).unwrap();
+        let path = write_region(
+            &dir,
+            &[
+                (0, 2, 1, zlib_record(0, 0, 100)),
+                (1, 3, 1, unreadable.clone()),
+                (2, 4, 1, chunk_record(2, &invalid_nbt)),
+            ],
+            5,
+        );
+        let mut region_file = RegionFile::open_read_only(&path).unwrap();
+        let statistic = region_file
+            .scan_chunks(&Arc::new(ScanOptions::new()))
+            .unwrap();
+        assert_eq!(statistic.failed_to_read, 1);
+        // the chunk after the unreadable one is still validated
+        assert_eq!(statistic.corrupted_nbt, 1);
+        drop(region_file);
+
+        let first = zlib_record(0, 0, 100);
+        let last = zlib_record(2, 0, 100);
+        let path = write_region(
+            &dir,
+            &[
+                (0, 2, 1, first.clone()),
+                (1, 3, 1, unreadable),
+                (2, 4, 1, last.clone()),
+            ],
+            5,
+        );
+        let mut region_file = RegionFile::new(&path).unwrap();
+        let options = Arc::new(ScanOptions::new().fix(true).fix_delete(true));
+        let statistic = region_file.scan_chunks(&options).unwrap();
+        drop(region_file);
+
+        assert_eq!(statistic.failed_to_read, 1);
+        assert_eq!(statistic.failed_to_read_fixed, 1);
+        assert_eq!(statistic.corrupted_nbt + statistic.corrupted_compression, 0);
+        let region_file = RegionFile::open_read_only(&path).unwrap();
+        assert_eq!(region_file.count_chunks(), 2);
+        assert_eq!(region_file.locations().get_chunk_entry_unchecked(1), (0, 0));
+        assert_eq!(read_record(&path, 0), Some(first));
+        assert_eq!(read_record(&path, 2), Some(last));
+    }
+
+    #[test]
+    fn chunks_of_other_regions_are_foreign_outside_of_the_origin() {
+        let dir = test_dir("foreign");
+        let (owned, foreign) = (get_chunk_index(3, 1), get_chunk_index(4, 1));
+        let record = zlib_record(5 * 32 + 3, -3 * 32 + 1, 100);
+        let written = write_region(
+            &dir,
+            &[
+                (owned, 2, 1, record.clone()),
+                // the coordinates of the origin region map to the same slot
+                (foreign, 3, 1, zlib_record(4, 1, 100)),
+            ],
+            4,
+        );
+        let path = dir.join("r.5.-3.mca");
+        fs::rename(&written, &path).unwrap();
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        let options = Arc::new(ScanOptions::new().fix(true).fix_delete(true));
+        let statistic = region_file.scan_chunks(&options).unwrap();
+        drop(region_file);
+
+        assert_eq!(statistic.foreign_chunk, 1);
+        assert_eq!(statistic.foreign_chunk_fixed, 1);
+        assert_eq!(statistic.invalid_chunk_pointer, 0);
+        let region_file = RegionFile::open_read_only(&path).unwrap();
+        assert_eq!(region_file.count_chunks(), 1);
+        assert_eq!(
+            region_file.locations().get_chunk_entry_unchecked(foreign),
+            (0, 0)
+        );
+        assert_eq!(read_record(&path, owned), Some(record));
+    }
+
+    #[test]
+    fn zlib_chunks_labelled_as_gzip_get_the_detected_method() {
+        let dir = test_dir("mislabelled-compression");
+        let data = compress_nbt_data(&chunk_nbt(0, 0, 100), 2, Compression::default()).unwrap();
+        let path = write_region(&dir, &[(0, 2, 1, chunk_record(1, &data))], 3);
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        let options = Arc::new(ScanOptions::new().fix_compression(true));
+        let statistic = region_file.scan_chunks(&options).unwrap();
+        drop(region_file);
+
+        // the data can't be read as gzip
+        assert_eq!(statistic.corrupted_nbt, 1);
+        assert_eq!(statistic.corrupted_nbt_fixed, 1);
+        assert_eq!(fs::read(&path).unwrap()[2 * BLOCK_SIZE + 4], 2);
+        let mut region_file = RegionFile::open_read_only(&path).unwrap();
+        let statistic = region_file
+            .scan_chunks(&Arc::new(ScanOptions::new()))
+            .unwrap();
+        assert_eq!(statistic.corrupted_nbt + statistic.corrupted_compression, 0);
+    }
+
+    #[test]
+    fn truncating_keeps_chunks_ending_after_the_last_one() {
+        let dir = test_dir("truncate-overlap");
+        // the first chunk overlaps the second one and ends after it
+        let path = write_region(
+            &dir,
+            &[
+                (0, 2, 4, zlib_record(0, 0, 3 * BLOCK_SIZE)),
+                (1, 3, 1, zlib_record(1, 0, 100)),
+            ],
+            6,
+        );
+        let original = fs::read(&path).unwrap();
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        region_file.scan_chunks(&defrag_options()).unwrap();
+        drop(region_file);
+
+        let data = fs::read(&path).unwrap();
+        assert_eq!(data.len(), 6 * BLOCK_SIZE);
+        assert_eq!(data[4 * BLOCK_SIZE..], original[4 * BLOCK_SIZE..]);
+    }
+
+    #[test]
+    fn defragmenting_keeps_every_chunk_byte_identical() {
+        let dir = test_dir("defrag-round-trip");
+        let chunks = vec![
+            (0, 2, 1, zlib_record(0, 0, 1000)),
+            (1, 5, 3, zlib_record(1, 0, 2 * BLOCK_SIZE + 100)),
+            (32, 10, 1, zlib_record(0, 1, 10)),
+            (33, 14, 2, zlib_record(1, 1, BLOCK_SIZE + 1)),
+            (1023, 20, 1, zlib_record(31, 31, 2000)),
+        ];
+        let path = write_region(&dir, &chunks, 22);
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        region_file.scan_chunks(&defrag_options()).unwrap();
+        drop(region_file);
+
+        // the chunks occupy 8 sectors after the header without any gaps
+        assert_eq!(fs::metadata(&path).unwrap().len(), 10 * BLOCK_SIZE as u64);
+        for (index, _, _, record) in chunks {
+            assert_eq!(read_record(&path, index), Some(record));
+        }
+    }
+
+    #[test]
+    fn packing_moves_a_last_chunk_ending_inside_of_a_sector_unchanged() {
+        let dir = test_dir("pack-unaligned");
+        let first = zlib_record(0, 0, 100);
+        let last = zlib_record(1, 0, 300);
+        let path = write_region(
+            &dir,
+            &[(0, 2, 1, first.clone()), (1, 4, 1, last.clone())],
+            5,
+        );
+        // the file ends with the data of the last chunk instead of a complete sector
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(4 * BLOCK_SIZE as u64 + last.len() as u64)
+            .unwrap();
+        drop(file);
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        region_file.scan_chunks(&defrag_options()).unwrap();
+        drop(region_file);
+
+        let expected_dir = test_dir("pack-unaligned-expected");
+        let expected = write_region(&expected_dir, &[(0, 2, 1, first), (1, 3, 1, last)], 4);
+        assert_eq!(fs::read(&path).unwrap(), fs::read(&expected).unwrap());
+    }
+
+    #[test]
+    fn deleted_chunks_stay_absent_after_packing() {
+        let dir = test_dir("delete-pack");
+        let last = zlib_record(2, 0, 100);
+        let path = write_region(
+            &dir,
+            &[
+                (0, 2, 1, zlib_record(0, 0, 100)),
+                (1, 3, 1, zlib_record(1, 0, 100)),
+                (2, 4, 1, last.clone()),
+            ],
+            5,
+        );
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        region_file.delete_chunk(1).unwrap();
+        region_file.scan_chunks(&defrag_options()).unwrap();
+        drop(region_file);
+
+        let region_file = RegionFile::open_read_only(&path).unwrap();
+        assert_eq!(region_file.locations().get_chunk_entry_unchecked(1), (0, 0));
+        assert_eq!(region_file.locations().get_chunk_entry_unchecked(2), (3, 1));
+        assert_eq!(region_file.count_chunks(), 2);
+        assert_eq!(read_record(&path, 2), Some(last));
+    }
+
+    #[test]
+    fn wiping_zeroes_the_sectors_of_deleted_chunks() {
+        let dir = test_dir("wipe-deleted");
+        let last = zlib_record(2, 0, 100);
+        let path = write_region(
+            &dir,
+            &[
+                (0, 2, 1, zlib_record(0, 0, 100)),
+                (1, 3, 1, zlib_record(1, 0, 100)),
+                (2, 4, 1, last.clone()),
+            ],
+            5,
+        );
+        let options = ScanOptions::new()
+            .fix_compression(true)
+            .skip_nbt(true)
+            .wipe_free_space(true);
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        region_file.delete_chunk(1).unwrap();
+        region_file.scan_chunks(&Arc::new(options)).unwrap();
+        drop(region_file);
+
+        let data = fs::read(&path).unwrap();
+        assert_eq!(data.len(), 5 * BLOCK_SIZE);
+        assert!(data[3 * BLOCK_SIZE..4 * BLOCK_SIZE].iter().all(|b| *b == 0));
+        assert_eq!(read_record(&path, 2), Some(last));
+    }
+
+    #[test]
+    fn free_ranges_cover_the_unused_sectors() {
+        let mut locations = Locations::from_bytes(&[0u8; BLOCK_SIZE]);
+        locations.replace_entry_unchecked(0, (3, 1));
+        locations.replace_entry_unchecked(1, (6, 2));
+
+        assert_eq!(
+            SectorMap::new(&locations, 10).free_ranges(),
+            [(2, 3), (4, 6), (8, 10)]
+        );
+    }
+
+    #[test]
+    fn moving_chunks_into_the_header_fails() {
+        let dir = test_dir("move-header");
+        let path = write_region(&dir, &[(0, 3, 1, zlib_record(0, 0, 100))], 4);
+        let original = fs::read(&path).unwrap();
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        let mut block = region_file.chunk_blocks().remove(0);
+        assert!(region_file.move_chunk_block(&mut block, 1).is_err());
+        assert_eq!(region_file.locations().get_chunk_entry_unchecked(0), (3, 1));
+        drop(region_file);
+
+        assert_eq!(fs::read(&path).unwrap(), original);
+    }
+
+    #[test]
+    fn scanned_changes_are_on_disk_before_the_file_is_dropped() {
+        let dir = test_dir("close-scan");
+        // the moved chunk is larger than the buffer of the writer
+        let record = zlib_record(0, 0, 3 * BLOCK_SIZE);
+        let sectors = record.len().div_ceil(BLOCK_SIZE);
+        let path = write_region(
+            &dir,
+            &[(0, 4, sectors as u8, record.clone())],
+            4 + sectors as u32,
+        );
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        region_file.scan_chunks(&defrag_options()).unwrap();
+
+        let data = fs::read(&path).unwrap();
+        assert_eq!(data.len(), (2 + sectors) * BLOCK_SIZE);
+        assert_eq!(BigEndian::read_u32(&data[..4]), (2 << 8) | sectors as u32);
+        assert_eq!(
+            data[2 * BLOCK_SIZE..2 * BLOCK_SIZE + record.len()],
+            record[..]
+        );
+        drop(region_file);
+    }
+
+    #[test]
+    fn moving_sectors_to_overlapping_ranges_keeps_their_bytes() {
+        let dir = test_dir("move-overlapping");
+        let record = zlib_record(0, 0, 3 * BLOCK_SIZE);
+        let sectors = record.len().div_ceil(BLOCK_SIZE);
+        let path = write_region(&dir, &[(0, 4, sectors as u8, record)], sectors as u32 + 6);
+        let sector_bytes = |offset: usize| {
+            fs::read(&path).unwrap()[offset * BLOCK_SIZE..(offset + sectors) * BLOCK_SIZE].to_vec()
+        };
+        let original = sector_bytes(4);
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        let expected = region_file.read_chunk_nbt(0, 0).unwrap();
+        for offset in [3, 5] {
+            let mut block = region_file.chunk_blocks().remove(0);
+            region_file.move_chunk_block(&mut block, offset).unwrap();
+
+            // the data written by the move is read back through the same file
+            assert_eq!(region_file.read_chunk_nbt(0, 0).unwrap(), expected);
+            assert_eq!(sector_bytes(offset as usize), original);
+        }
+    }
+
+    #[test]
+    fn packing_never_places_chunks_in_the_header() {
+        let dir = test_dir("pack-header");
+        // an entry pointing into the header next to chunks separated by gaps
+        let path = write_region(
+            &dir,
+            &[
+                (0, 1, 1, Vec::new()),
+                (1, 4, 2, zlib_record(1, 0, BLOCK_SIZE)),
+                (2, 9, 1, zlib_record(2, 0, 100)),
+                (3, 11, 1, zlib_record(3, 0, 100)),
+            ],
+            12,
+        );
+        let header = fs::read(&path).unwrap()[4..2 * BLOCK_SIZE].to_vec();
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        region_file.pack_chunks().unwrap();
+        region_file.close().unwrap();
+
+        let entries = region_file.locations().valid_entries_enumerate();
+        assert_eq!(entries.len(), 3);
+        assert!(entries.iter().all(|(_, (offset, _))| *offset >= 2));
+        assert_eq!(region_file.locations().max_offset(), 6);
+        drop(region_file);
+        // the moved sectors didn't overwrite the stored tables
+        let data = fs::read(&path).unwrap();
+        assert_eq!(data[16..2 * BLOCK_SIZE], header[12..]);
+    }
+
+    #[test]
+    fn estimated_size_ends_with_the_chunk_ending_last() {
+        let mut locations = Locations::from_bytes(&[0u8; BLOCK_SIZE]);
+        locations.replace_entry_unchecked(0, (2, 5));
+        locations.replace_entry_unchecked(1, (4, 1));
+
+        assert_eq!(locations.max_offset(), 7);
+        assert_eq!(
+            Locations::from_bytes(&[0u8; BLOCK_SIZE]).estimated_size(),
+            2 * BLOCK_SIZE as u64
+        );
+    }
+
+    #[test]
+    fn rebuilding_the_header_recovers_locations_and_timestamps() {
+        let dir = test_dir("rebuild-header");
+        let big = zlib_record(2, 0, 1100 * 1024);
+        let big_sectors = big.len().div_ceil(BLOCK_SIZE);
+        assert!(big_sectors > u8::MAX as usize);
+        let last = 3 + big_sectors as u32;
+        let path = write_region(
+            &dir,
+            &[
+                (0, 2, 1, zlib_record(0, 0, 100)),
+                (2, 3, 0, big),
+                (33, last, 1, zlib_record(1, 1, 100)),
+            ],
+            last + 1,
+        );
+        let mut data = fs::read(&path).unwrap();
+        data[..2 * BLOCK_SIZE].fill(0xab);
+        fs::write(&path, data).unwrap();
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        assert_eq!(region_file.rebuild_locations().unwrap(), 2);
+        drop(region_file);
+
+        let region_file = RegionFile::open_read_only(&path).unwrap();
+        let locations = region_file.locations();
+        let timestamps = region_file.timestamps();
+        assert_eq!(locations.get_chunk_entry_unchecked(0), (2, 1));
+        assert_eq!(locations.get_chunk_entry_unchecked(33), (last, 1));
+        // the chunk spanning more sectors than an entry can hold is skipped
+        assert_eq!(locations.get_chunk_entry_unchecked(2), (0, 0));
+        assert_ne!(timestamps.get(0), 0);
+        assert_ne!(timestamps.get(33), 0);
+        for index in (0..1024).filter(|i| *i != 0 && *i != 33) {
+            assert_eq!(locations.get_chunk_entry_unchecked(index), (0, 0));
+            assert_eq!(timestamps.get(index), 0);
+        }
+    }
+
+    #[test]
+    fn packing_closes_two_gaps_between_three_chunks() {
+        let dir = test_dir("two-gaps");
+        // the indices are in a different order than the offsets of the chunks
+        let records = [
+            (5, zlib_record(5, 0, 100)),
+            (1, zlib_record(1, 0, BLOCK_SIZE + 100)),
+            (3, zlib_record(3, 0, 200)),
+        ];
+        let path = write_region(
+            &dir,
+            &[
+                (5, 2, 1, records[0].1.clone()),
+                (1, 4, 2, records[1].1.clone()),
+                (3, 8, 1, records[2].1.clone()),
+            ],
+            9,
+        );
+
+        let mut region_file = RegionFile::new(&path).unwrap();
+        region_file.scan_chunks(&defrag_options()).unwrap();
+        drop(region_file);
+
+        let region_file = RegionFile::open_read_only(&path).unwrap();
+        let locations = region_file.locations();
+        // the last chunk fits into the first gap which leaves the others contiguous
+        assert_eq!(locations.get_chunk_entry_unchecked(5), (2, 1));
+        assert_eq!(locations.get_chunk_entry_unchecked(3), (3, 1));
+        assert_eq!(locations.get_chunk_entry_unchecked(1), (4, 2));
+        assert_eq!(fs::metadata(&path).unwrap().len(), 6 * BLOCK_SIZE as u64);
+        for (index, record) in &records {
+            assert_eq!(read_record(&path, *index).as_ref(), Some(record));
+        }
+    }
+}
@@ -1,50 +1,831 @@
-use std::fmt::{Display, Formatter, Result};
+use crate::nbt::DEFAULT_MAX_DEPTH;
+use crate::utils::format_bytes;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Display, Formatter, Result};
 use std::ops::Add;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct ScanStatistics {
+    pub total_files: u64,
+    pub skipped_files: u64,
+    pub skipped_oversize: u64,
+    pub failed_files: Vec<PathBuf>,
     pub total_chunks: u64,
     pub invalid_length: u64,
+    pub truncated_chunk: u64,
+    pub empty_chunk_record: u64,
     pub invalid_compression_method: u64,
     pub missing_nbt: u64,
     pub corrupted_nbt: u64,
     pub failed_to_read: u64,
     pub corrupted_compression: u64,
+    pub missing_external_file: u64,
+    pub orphan_external_pointer: u64,
+    pub overlapping_chunks: u64,
+    pub mismatched_timestamps: u64,
     pub invalid_chunk_pointer: u64,
+    pub swapped_pointer: u64,
+    pub foreign_chunk: u64,
+    pub duplicate_pointer: u64,
+    pub misplaced_entities: u64,
+    pub misplaced_tile_entities: u64,
+    pub duplicate_tile_entities: u64,
+    pub invalid_heightmap: u64,
+    pub invalid_structures: u64,
+    pub invalid_biomes: u64,
+    pub invalid_sections: u64,
+    pub invalid_block_states: u64,
+    pub future_timestamp: u64,
+    pub unaligned_file: u64,
+    pub truncated_header: u64,
+    pub failed_to_read_fixed: u64,
+    pub invalid_chunk_pointer_fixed: u64,
+    pub swapped_pointer_fixed: u64,
+    pub foreign_chunk_fixed: u64,
+    pub duplicate_pointer_fixed: u64,
+    pub misplaced_entities_fixed: u64,
+    pub duplicate_tile_entities_fixed: u64,
+    pub misplaced_entity_chunks_fixed: u64,
+    pub future_timestamp_fixed: u64,
+    pub unaligned_file_fixed: u64,
+    pub truncated_header_fixed: u64,
+    pub invalid_length_fixed: u64,
+    pub truncated_chunk_fixed: u64,
+    pub empty_chunk_record_fixed: u64,
+    pub invalid_compression_method_fixed: u64,
+    pub missing_nbt_fixed: u64,
+    pub corrupted_nbt_fixed: u64,
+    pub corrupted_compression_fixed: u64,
+    pub missing_external_file_fixed: u64,
+    pub orphan_external_pointer_fixed: u64,
+    pub overlapping_chunks_fixed: u64,
+    pub mismatched_timestamps_fixed: u64,
+    pub pruned_chunks: u64,
+    pub pruned_space: u64,
+    pub protochunks_deleted: u64,
+    pub recompressed_chunks: u64,
+    /// The space freed by chunks that occupy fewer sectors after being recompressed
+    pub recompressed_space: u64,
+    pub empty_regions: u64,
+    pub empty_regions_removed: u64,
     pub shrunk_size: u64,
+    /// The size of the scanned files before they were modified
+    pub total_size: u64,
+    /// The size of the scanned files after they were fixed. Removed files have no size
+    pub final_size: u64,
+    /// The chunks whose data was validated when only a sample of chunks is scanned
+    pub sampled_chunks: u64,
+    /// The chunks whose data wasn't validated since they weren't part of the sample
+    pub unsampled_chunks: u64,
+    /// The errors found in the data of the sampled chunks
+    pub sampled_errors: u64,
     pub unused_space: u64,
+    pub free_sectors: u64,
+    pub double_allocated_sectors: u64,
+    pub elapsed_ms: u64,
+    pub entities: u64,
+    pub block_entities: u64,
+    pub densest_chunks: Vec<DenseChunk>,
+    pub largest_chunks: Vec<LargeChunk>,
+    #[serde(skip)]
+    pub largest_chunks_limit: usize,
+    pub entity_types: HashMap<String, u64>,
+    /// The number of chunks containing content of each namespace
+    pub namespaces: HashMap<String, u64>,
+    pub events: Vec<ScanEvent>,
+    /// The modifications made to each file
+    pub repairs: BTreeMap<PathBuf, Vec<RepairAction>>,
+}
+
+/// A problem found while scanning a region file. Chunks are identified by the global
+/// coordinates of the location entry pointing at them
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScanEvent {
+    GapDetected {
+        offset: u32,
+        sectors: u32,
+    },
+    PaddedFile {
+        length: u64,
+        padded_length: u64,
+    },
+    InvalidPointer {
+        x: i32,
+        z: i32,
+        offset: u32,
+        sectors: u8,
+    },
+    DuplicatePointer {
+        x: i32,
+        z: i32,
+        offset: u32,
+    },
+    OverlappingChunk {
+        x: i32,
+        z: i32,
+        offset: u32,
+    },
+    FailedToRead {
+        x: i32,
+        z: i32,
+        reason: String,
+    },
+    InvalidCompressionMethod {
+        x: i32,
+        z: i32,
+        compression_type: u8,
+    },
+    CorruptCompression {
+        x: i32,
+        z: i32,
+        reason: String,
+    },
+    CorruptNbt {
+        x: i32,
+        z: i32,
+        reason: String,
+    },
+    MissingNbt {
+        x: i32,
+        z: i32,
+        reason: String,
+    },
+    MissingExternalFile {
+        x: i32,
+        z: i32,
+    },
+    OrphanExternalPointer {
+        x: i32,
+        z: i32,
+    },
+    ForeignChunk {
+        x: i32,
+        z: i32,
+        found_x: i32,
+        found_z: i32,
+    },
+    SwappedPointer {
+        x: i32,
+        z: i32,
+    },
+    WrongChunk {
+        x: i32,
+        z: i32,
+        found_x: i32,
+        found_z: i32,
+    },
+    InvalidLength {
+        x: i32,
+        z: i32,
+        length: u32,
+        sectors: u8,
+    },
+    TruncatedChunk {
+        x: i32,
+        z: i32,
+        length: u32,
+        available: u64,
+    },
+    EmptyChunkRecord {
+        x: i32,
+        z: i32,
+        offset: u32,
+    },
+    MismatchedTimestamp {
+        x: i32,
+        z: i32,
+    },
+    FutureTimestamp {
+        x: i32,
+        z: i32,
+    },
+    InvalidHeightmaps {
+        x: i32,
+        z: i32,
+        count: u64,
+    },
+    InvalidStructures {
+        x: i32,
+        z: i32,
+    },
+    InvalidBiomes {
+        x: i32,
+        z: i32,
+    },
+    InvalidSections {
+        x: i32,
+        z: i32,
+    },
+    InvalidBlockStates {
+        x: i32,
+        z: i32,
+    },
+    MisplacedEntities {
+        x: i32,
+        z: i32,
+        count: u64,
+    },
+    InvalidTileEntities {
+        x: i32,
+        z: i32,
+        misplaced: u64,
+        duplicates: u64,
+    },
+}
+
+impl Display for ScanEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::GapDetected { offset, sectors } => {
+                write!(f, "Gap of {} unused sectors at {}", sectors, offset)
+            }
+            Self::PaddedFile {
+                length,
+                padded_length,
+            } => write!(
+                f,
+                "Padded the file from {} to {} bytes",
+                length, padded_length
+            ),
+            Self::InvalidPointer {
+                x,
+                z,
+                offset,
+                sectors,
+            } => write!(
+                f,
+                "Chunk ({},{}): points outside of the file at {} + {}",
+                x, z, offset, sectors
+            ),
+            Self::DuplicatePointer { x, z, offset } => write!(
+                f,
+                "Chunk ({},{}): shares the offset {} with another chunk",
+                x, z, offset
+            ),
+            Self::OverlappingChunk { x, z, offset } => write!(
+                f,
+                "Chunk ({},{}): overlaps another chunk at {}",
+                x, z, offset
+            ),
+            Self::FailedToRead { x, z, reason } => {
+                write!(f, "Chunk ({},{}): failed to read: {}", x, z, reason)
+            }
+            Self::InvalidCompressionMethod {
+                x,
+                z,
+                compression_type,
+            } => write!(
+                f,
+                "Chunk ({},{}): invalid compression method {}",
+                x, z, compression_type
+            ),
+            Self::CorruptCompression { x, z, reason } => write!(
+                f,
+                "Chunk ({},{}): corrupted compressed data: {}",
+                x, z, reason
+            ),
+            Self::CorruptNbt { x, z, reason } => {
+                write!(f, "Chunk ({},{}): corrupted nbt data: {}", x, z, reason)
+            }
+            Self::MissingNbt { x, z, reason } => {
+                write!(f, "Chunk ({},{}): missing nbt data: {}", x, z, reason)
+            }
+            Self::MissingExternalFile { x, z } => {
+                write!(f, "Chunk ({},{}): missing external file", x, z)
+            }
+            Self::OrphanExternalPointer { x, z } => write!(
+                f,
+                "Chunk ({},{}): points at offset 1 without an external file",
+                x, z
+            ),
+            Self::ForeignChunk {
+                x,
+                z,
+                found_x,
+                found_z,
+            } => write!(
+                f,
+                "Chunk ({},{}): points at chunk ({},{}) of another region",
+                x, z, found_x, found_z
+            ),
+            Self::SwappedPointer { x, z } => write!(
+                f,
+                "Chunk ({},{}): points at the chunk with swapped coordinates",
+                x, z
+            ),
+            Self::WrongChunk {
+                x,
+                z,
+                found_x,
+                found_z,
+            } => write!(
+                f,
+                "Chunk ({},{}): points at chunk ({},{})",
+                x, z, found_x, found_z
+            ),
+            Self::InvalidLength {
+                x,
+                z,
+                length,
+                sectors,
+            } => write!(
+                f,
+                "Chunk ({},{}): length {} doesn't match {} sectors",
+                x, z, length, sectors
+            ),
+            Self::TruncatedChunk {
+                x,
+                z,
+                length,
+                available,
+            } => write!(
+                f,
+                "Chunk ({},{}): length {} exceeds the {} bytes before the next chunk",
+                x, z, length, available
+            ),
+            Self::EmptyChunkRecord { x, z, offset } => write!(
+                f,
+                "Chunk ({},{}): record at sector {} has a length of zero",
+                x, z, offset
+            ),
+            Self::MismatchedTimestamp { x, z } => write!(
+                f,
+                "Chunk ({},{}): timestamp doesn't match the location entry",
+                x, z
+            ),
+            Self::FutureTimestamp { x, z } => {
+                write!(f, "Chunk ({},{}): timestamp lies in the future", x, z)
+            }
+            Self::InvalidHeightmaps { x, z, count } => {
+                write!(f, "Chunk ({},{}): {} invalid heightmaps", x, z, count)
+            }
+            Self::InvalidStructures { x, z } => {
+                write!(f, "Chunk ({},{}): invalid structure data", x, z)
+            }
+            Self::InvalidBiomes { x, z } => {
+                write!(f, "Chunk ({},{}): invalid biome data", x, z)
+            }
+            Self::InvalidSections { x, z } => {
+                write!(f, "Chunk ({},{}): duplicate or out of range sections", x, z)
+            }
+            Self::InvalidBlockStates { x, z } => write!(
+                f,
+                "Chunk ({},{}): block states not matching their palette",
+                x, z
+            ),
+            Self::MisplacedEntities { x, z, count } => write!(
+                f,
+                "Chunk ({},{}): {} entities positioned outside of the chunk",
+                x, z, count
+            ),
+            Self::InvalidTileEntities {
+                x,
+                z,
+                misplaced,
+                duplicates,
+            } => write!(
+                f,
+                "Chunk ({},{}): {} tile entities outside of the chunk, {} duplicates",
+                x, z, misplaced, duplicates
+            ),
+        }
+    }
+}
+
+/// A modification made to a region file while fixing it. Chunks are identified by
+/// the global coordinates of their location entry and sectors by their offset
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RepairAction {
+    DeletedChunk {
+        x: i32,
+        z: i32,
+    },
+    MovedChunk {
+        x: i32,
+        z: i32,
+        to_x: i32,
+        to_z: i32,
+    },
+    RewroteChunk {
+        x: i32,
+        z: i32,
+    },
+    SetCompressionMethod {
+        x: i32,
+        z: i32,
+        compression_type: u8,
+    },
+    SetSectors {
+        x: i32,
+        z: i32,
+        sectors: u8,
+    },
+    SetTimestamp {
+        x: i32,
+        z: i32,
+        timestamp: u32,
+    },
+    MovedSectors {
+        from: u32,
+        to: u32,
+        sectors: u8,
+    },
+    ResizedFile {
+        from: u64,
+        to: u64,
+    },
+    RemovedFile,
 }
 
+impl Display for RepairAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::DeletedChunk { x, z } => write!(f, "deleted chunk ({},{})", x, z),
+            Self::MovedChunk { x, z, to_x, to_z } => write!(
+                f,
+                "moved chunk ({},{}) to the location entry of ({},{})",
+                x, z, to_x, to_z
+            ),
+            Self::RewroteChunk { x, z } => write!(f, "rewrote chunk ({},{})", x, z),
+            Self::SetCompressionMethod {
+                x,
+                z,
+                compression_type,
+            } => write!(
+                f,
+                "set the compression method of chunk ({},{}) to {}",
+                x, z, compression_type
+            ),
+            Self::SetSectors { x, z, sectors } => {
+                write!(f, "set the sectors of chunk ({},{}) to {}", x, z, sectors)
+            }
+            Self::SetTimestamp { x, z, timestamp } => write!(
+                f,
+                "set the timestamp of chunk ({},{}) to {}",
+                x, z, timestamp
+            ),
+            Self::MovedSectors { from, to, sectors } => {
+                write!(f, "moved {} sectors from {} to {}", sectors, from, to)
+            }
+            Self::ResizedFile { from, to } => {
+                write!(f, "resized the file from {} to {} bytes", from, to)
+            }
+            Self::RemovedFile => write!(f, "removed the file"),
+        }
+    }
+}
+
+/// A chunk with the number of entities and block entities it contains
+#[derive(Clone, Debug, Serialize)]
+pub struct DenseChunk {
+    pub x: i32,
+    pub z: i32,
+    pub entities: u64,
+    pub block_entities: u64,
+}
+
+impl DenseChunk {
+    /// Returns the combined number of entities and block entities
+    pub fn total(&self) -> u64 {
+        self.entities + self.block_entities
+    }
+}
+
+/// A chunk with the length of its compressed data
+#[derive(Clone, Debug, Serialize)]
+pub struct LargeChunk {
+    pub x: i32,
+    pub z: i32,
+    pub length: u32,
+}
+
+/// The number of chunks with the most entities that are kept in the statistics
+pub const MAX_DENSEST_CHUNKS: usize = 10;
+
 impl ScanStatistics {
     pub fn new() -> Self {
         Self {
+            total_files: 0,
+            skipped_files: 0,
+            skipped_oversize: 0,
+            failed_files: Vec::new(),
             total_chunks: 0,
             invalid_length: 0,
+            truncated_chunk: 0,
+            empty_chunk_record: 0,
             invalid_compression_method: 0,
             missing_nbt: 0,
             corrupted_nbt: 0,
             corrupted_compression: 0,
+            missing_external_file: 0,
+            orphan_external_pointer: 0,
+            overlapping_chunks: 0,
+            mismatched_timestamps: 0,
             invalid_chunk_pointer: 0,
+            swapped_pointer: 0,
+            foreign_chunk: 0,
+            duplicate_pointer: 0,
+            misplaced_entities: 0,
+            misplaced_tile_entities: 0,
+            duplicate_tile_entities: 0,
+            invalid_heightmap: 0,
+            invalid_structures: 0,
+            invalid_biomes: 0,
+            invalid_sections: 0,
+            invalid_block_states: 0,
+            future_timestamp: 0,
+            unaligned_file: 0,
+            truncated_header: 0,
             failed_to_read: 0,
+            failed_to_read_fixed: 0,
+            invalid_chunk_pointer_fixed: 0,
+            swapped_pointer_fixed: 0,
+            foreign_chunk_fixed: 0,
+            duplicate_pointer_fixed: 0,
+            misplaced_entities_fixed: 0,
+            duplicate_tile_entities_fixed: 0,
+            misplaced_entity_chunks_fixed: 0,
+            future_timestamp_fixed: 0,
+            unaligned_file_fixed: 0,
+            truncated_header_fixed: 0,
+            invalid_length_fixed: 0,
+            truncated_chunk_fixed: 0,
+            empty_chunk_record_fixed: 0,
+            invalid_compression_method_fixed: 0,
+            missing_nbt_fixed: 0,
+            corrupted_nbt_fixed: 0,
+            corrupted_compression_fixed: 0,
+            missing_external_file_fixed: 0,
+            orphan_external_pointer_fixed: 0,
+            overlapping_chunks_fixed: 0,
+            mismatched_timestamps_fixed: 0,
+            pruned_chunks: 0,
+            pruned_space: 0,
+            protochunks_deleted: 0,
+            recompressed_chunks: 0,
+            recompressed_space: 0,
+            empty_regions: 0,
+            empty_regions_removed: 0,
             shrunk_size: 0,
+            total_size: 0,
+            final_size: 0,
+            sampled_chunks: 0,
+            unsampled_chunks: 0,
+            sampled_errors: 0,
             unused_space: 0,
+            free_sectors: 0,
+            double_allocated_sectors: 0,
+            elapsed_ms: 0,
+            entities: 0,
+            block_entities: 0,
+            densest_chunks: Vec::new(),
+            largest_chunks: Vec::new(),
+            largest_chunks_limit: 0,
+            entity_types: HashMap::new(),
+            namespaces: HashMap::new(),
+            events: Vec::new(),
+            repairs: BTreeMap::new(),
+        }
+    }
+
+    /// Returns if all files could be scanned and no errors were found in their chunks
+    pub fn is_healthy(&self) -> bool {
+        self.skipped_files == 0 && self.total_errors() == 0
+    }
+
+    /// Returns the message explicitly confirming a clean scan with the level it's logged at.
+    /// Scans without any region files are warned about since the path is most likely wrong.
+    /// Returns `None` if errors were found since the statistics already report them
+    pub fn clean_report(&self) -> Option<(log::Level, String)> {
+        if self.total_files == 0 {
+            Some((
+                log::Level::Warn,
+                String::from("No region files were scanned. Is the world path correct?"),
+            ))
+        } else if self.is_healthy() {
+            Some((
+                log::Level::Info,
+                format!(
+                    "World healthy: {} chunks across {} files, no errors",
+                    self.total_chunks, self.total_files
+                ),
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Adds the entities of a chunk to the totals and keeps it
+    /// if it's one of the chunks with the most entities
+    pub fn add_entities(&mut self, chunk: DenseChunk) {
+        self.entities += chunk.entities;
+        self.block_entities += chunk.block_entities;
+        if chunk.total() > 0 {
+            self.add_dense_chunks(vec![chunk]);
+        }
+    }
+
+    /// Merges the chunks into the list of the largest chunks which keeps
+    /// at most as many chunks as the larger one of both limits
+    pub fn add_largest_chunks(&mut self, chunks: Vec<LargeChunk>, limit: usize) {
+        self.largest_chunks_limit = self.largest_chunks_limit.max(limit);
+        self.largest_chunks.extend(chunks);
+        self.largest_chunks
+            .sort_by_key(|chunk| std::cmp::Reverse(chunk.length));
+        self.largest_chunks.truncate(self.largest_chunks_limit);
+    }
+
+    /// Records the event if the options collect events. The event is only created if it's kept
+    pub fn add_event<F: FnOnce() -> ScanEvent>(&mut self, options: &ScanOptions, event: F) {
+        if options.collect_events {
+            self.events.push(event());
+        }
+    }
+
+    /// Adds the modifications made to the file
+    pub fn add_repairs(&mut self, path: &Path, actions: Vec<RepairAction>) {
+        if !actions.is_empty() {
+            self.repairs
+                .entry(path.to_path_buf())
+                .or_default()
+                .extend(actions);
+        }
+    }
+
+    /// Merges the chunks into the list of chunks with the most entities
+    fn add_dense_chunks(&mut self, chunks: Vec<DenseChunk>) {
+        self.densest_chunks.extend(chunks);
+        self.densest_chunks
+            .sort_by_key(|chunk| std::cmp::Reverse(chunk.total()));
+        self.densest_chunks.truncate(MAX_DENSEST_CHUNKS);
+    }
+
+    /// Returns the number of errors found in the chunks
+    pub fn total_errors(&self) -> u64 {
+        self.failed_to_read
+            + self.invalid_chunk_pointer
+            + self.swapped_pointer
+            + self.foreign_chunk
+            + self.duplicate_pointer
+            + self.misplaced_entities
+            + self.misplaced_tile_entities
+            + self.duplicate_tile_entities
+            + self.invalid_heightmap
+            + self.invalid_structures
+            + self.invalid_biomes
+            + self.invalid_sections
+            + self.invalid_block_states
+            + self.future_timestamp
+            + self.unaligned_file
+            + self.truncated_header
+            + self.invalid_length
+            + self.truncated_chunk
+            + self.empty_chunk_record
+            + self.invalid_compression_method
+            + self.missing_nbt
+            + self.corrupted_nbt
+            + self.corrupted_compression
+            + self.missing_external_file
+            + self.orphan_external_pointer
+            + self.overlapping_chunks
+            + self.mismatched_timestamps
+    }
+
+    /// Returns the share of errors in the scanned chunks from 0 to 1.
+    /// Errors of whole files count like chunk errors so the rate is capped at 1
+    pub fn error_rate(&self) -> f64 {
+        if self.total_chunks == 0 {
+            0.0
+        } else {
+            (self.total_errors() as f64 / self.total_chunks as f64).min(1.0)
         }
     }
+
+    /// Returns the number of errors expected in all chunks if only a sample of them was
+    /// validated. The errors of the sample are extrapolated to the chunks outside of it
+    pub fn estimated_errors(&self) -> u64 {
+        if self.sampled_chunks == 0 {
+            return self.total_errors();
+        }
+        let validated = self.sampled_chunks + self.unsampled_chunks;
+        let estimated = self.sampled_errors as f64 * validated as f64 / self.sampled_chunks as f64;
+
+        self.total_errors() - self.sampled_errors + estimated.round() as u64
+    }
+
+    /// Returns the share of unused space between the chunks in the scanned files from 0 to 1.
+    /// It's relative to the size of the files before the scan since `shrunk_size`
+    /// only counts the bytes reclaimed by fixing and is 0 if nothing was modified
+    pub fn fragmentation_ratio(&self) -> f64 {
+        if self.total_size == 0 {
+            0.0
+        } else {
+            self.unused_space as f64 / self.total_size as f64
+        }
+    }
+}
+
+impl Default for ScanStatistics {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Add for ScanStatistics {
     type Output = Self;
 
     fn add(mut self, rhs: Self) -> Self::Output {
+        self.total_files += rhs.total_files;
+        self.skipped_files += rhs.skipped_files;
+        self.skipped_oversize += rhs.skipped_oversize;
+        self.failed_files.extend(rhs.failed_files);
         self.invalid_length += rhs.invalid_length;
+        self.truncated_chunk += rhs.truncated_chunk;
+        self.empty_chunk_record += rhs.empty_chunk_record;
         self.total_chunks += rhs.total_chunks;
         self.invalid_compression_method += rhs.invalid_compression_method;
         self.failed_to_read += rhs.failed_to_read;
         self.missing_nbt += rhs.missing_nbt;
         self.corrupted_compression += rhs.corrupted_compression;
+        self.missing_external_file += rhs.missing_external_file;
+        self.orphan_external_pointer += rhs.orphan_external_pointer;
+        self.overlapping_chunks += rhs.overlapping_chunks;
+        self.mismatched_timestamps += rhs.mismatched_timestamps;
         self.invalid_chunk_pointer += rhs.invalid_chunk_pointer;
+        self.swapped_pointer += rhs.swapped_pointer;
+        self.foreign_chunk += rhs.foreign_chunk;
+        self.duplicate_pointer += rhs.duplicate_pointer;
+        self.misplaced_entities += rhs.misplaced_entities;
+        self.misplaced_tile_entities += rhs.misplaced_tile_entities;
+        self.duplicate_tile_entities += rhs.duplicate_tile_entities;
+        self.invalid_heightmap += rhs.invalid_heightmap;
+        self.invalid_structures += rhs.invalid_structures;
+        self.invalid_biomes += rhs.invalid_biomes;
+        self.invalid_sections += rhs.invalid_sections;
+        self.invalid_block_states += rhs.invalid_block_states;
+        self.future_timestamp += rhs.future_timestamp;
+        self.unaligned_file += rhs.unaligned_file;
+        self.truncated_header += rhs.truncated_header;
         self.corrupted_nbt += rhs.corrupted_nbt;
         self.unused_space += rhs.unused_space;
+        self.total_size += rhs.total_size;
+        self.final_size += rhs.final_size;
+        self.sampled_chunks += rhs.sampled_chunks;
+        self.unsampled_chunks += rhs.unsampled_chunks;
+        self.sampled_errors += rhs.sampled_errors;
+        self.free_sectors += rhs.free_sectors;
+        self.double_allocated_sectors += rhs.double_allocated_sectors;
+        self.elapsed_ms += rhs.elapsed_ms;
+        self.entities += rhs.entities;
+        self.block_entities += rhs.block_entities;
+        self.add_dense_chunks(rhs.densest_chunks);
+        self.add_largest_chunks(rhs.largest_chunks, rhs.largest_chunks_limit);
+        self.events.extend(rhs.events);
+        for (path, actions) in rhs.repairs {
+            self.add_repairs(&path, actions);
+        }
+        for (id, count) in rhs.entity_types {
+            *self.entity_types.entry(id).or_insert(0) += count;
+        }
+        for (namespace, count) in rhs.namespaces {
+            *self.namespaces.entry(namespace).or_insert(0) += count;
+        }
+        self.shrunk_size += rhs.shrunk_size;
+        self.pruned_chunks += rhs.pruned_chunks;
+        self.pruned_space += rhs.pruned_space;
+        self.protochunks_deleted += rhs.protochunks_deleted;
+        self.recompressed_chunks += rhs.recompressed_chunks;
+        self.recompressed_space += rhs.recompressed_space;
+        self.empty_regions += rhs.empty_regions;
+        self.empty_regions_removed += rhs.empty_regions_removed;
+        self.failed_to_read_fixed += rhs.failed_to_read_fixed;
+        self.invalid_chunk_pointer_fixed += rhs.invalid_chunk_pointer_fixed;
+        self.swapped_pointer_fixed += rhs.swapped_pointer_fixed;
+        self.foreign_chunk_fixed += rhs.foreign_chunk_fixed;
+        self.duplicate_pointer_fixed += rhs.duplicate_pointer_fixed;
+        self.misplaced_entities_fixed += rhs.misplaced_entities_fixed;
+        self.duplicate_tile_entities_fixed += rhs.duplicate_tile_entities_fixed;
+        self.misplaced_entity_chunks_fixed += rhs.misplaced_entity_chunks_fixed;
+        self.future_timestamp_fixed += rhs.future_timestamp_fixed;
+        self.unaligned_file_fixed += rhs.unaligned_file_fixed;
+        self.truncated_header_fixed += rhs.truncated_header_fixed;
+        self.invalid_length_fixed += rhs.invalid_length_fixed;
+        self.truncated_chunk_fixed += rhs.truncated_chunk_fixed;
+        self.empty_chunk_record_fixed += rhs.empty_chunk_record_fixed;
+        self.invalid_compression_method_fixed += rhs.invalid_compression_method_fixed;
+        self.missing_nbt_fixed += rhs.missing_nbt_fixed;
+        self.corrupted_nbt_fixed += rhs.corrupted_nbt_fixed;
+        self.corrupted_compression_fixed += rhs.corrupted_compression_fixed;
+        self.missing_external_file_fixed += rhs.missing_external_file_fixed;
+        self.orphan_external_pointer_fixed += rhs.orphan_external_pointer_fixed;
+        self.overlapping_chunks_fixed += rhs.overlapping_chunks_fixed;
+        self.mismatched_timestamps_fixed += rhs.mismatched_timestamps_fixed;
 
         self
     }
@@ -55,32 +836,235 @@ impl Display for ScanStatistics {
         write!(
             f,
             "
+            Total Files: {}
+            Skipped Files: {} ({} too large)
             Total Chunks: {}
-            Failed to Read: {}
-            Invalid chunk pointers: {}
-            Chunks with invalid length: {}
-            Chunks with invalid compression method: {}
-            Chunks with missing nbt data: {}
-            Chunks with corrupted nbt data: {}
-            Chunks with corrupted compressed data: {}
-            Unused space: {} KiB",
+            Failed to Read: {} found, {} fixed
+            Invalid chunk pointers: {} found, {} fixed
+            Swapped chunk pointers: {} found, {} fixed
+            Chunks of other regions: {} found, {} fixed
+            Duplicate chunk pointers: {} found, {} fixed
+            Misplaced entities: {} found, {} removed from {} chunks
+            Misplaced tile entities: {} found
+            Duplicate tile entities: {} found, {} removed
+            Chunks with invalid heightmaps: {} found
+            Chunks with invalid structures: {} found
+            Chunks with invalid biomes: {} found
+            Chunks with invalid sections: {} found
+            Chunks with invalid block states: {} found
+            Chunks with invalid length: {} found, {} fixed
+            Truncated chunks: {} found, {} fixed
+            Empty chunk records: {} found, {} fixed
+            Chunks with invalid compression method: {} found, {} fixed
+            Chunks with missing nbt data: {} found, {} fixed
+            Chunks with corrupted nbt data: {} found, {} fixed
+            Chunks with corrupted compressed data: {} found, {} fixed
+            Chunks with missing external files: {} found, {} fixed
+            Orphaned external chunk pointers: {} found, {} fixed
+            Overlapping chunks: {} found, {} fixed
+            Mismatched timestamps: {} found, {} fixed
+            Timestamps in the future: {} found, {} fixed
+            Files with a partial last sector: {} found, {} fixed
+            Files too small for the header: {} found, {} removed
+            Pruned chunks: {} ({})
+            Deleted proto-chunks: {}
+            Recompressed chunks: {} ({} saved)
+            Empty region files: {} found, {} removed
+            Unused space: {}
+            Free sectors: {}
+            Sectors used by multiple chunks: {}
+            Reclaimed space: {}
+            Size of region files: {} before, {} after ({} saved)
+            Health: {:.1}% chunks clean, {:.1}% space wasted",
+            self.total_files,
+            self.skipped_files,
+            self.skipped_oversize,
             self.total_chunks,
             self.failed_to_read,
+            self.failed_to_read_fixed,
             self.invalid_chunk_pointer,
+            self.invalid_chunk_pointer_fixed,
+            self.swapped_pointer,
+            self.swapped_pointer_fixed,
+            self.foreign_chunk,
+            self.foreign_chunk_fixed,
+            self.duplicate_pointer,
+            self.duplicate_pointer_fixed,
+            self.misplaced_entities,
+            self.misplaced_entities_fixed,
+            self.misplaced_entity_chunks_fixed,
+            self.misplaced_tile_entities,
+            self.duplicate_tile_entities,
+            self.duplicate_tile_entities_fixed,
+            self.invalid_heightmap,
+            self.invalid_structures,
+            self.invalid_biomes,
+            self.invalid_sections,
+            self.invalid_block_states,
             self.invalid_length,
+            self.invalid_length_fixed,
+            self.truncated_chunk,
+            self.truncated_chunk_fixed,
+            self.empty_chunk_record,
+            self.empty_chunk_record_fixed,
             self.invalid_compression_method,
+            self.invalid_compression_method_fixed,
             self.missing_nbt,
+            self.missing_nbt_fixed,
             self.corrupted_nbt,
+            self.corrupted_nbt_fixed,
             self.corrupted_compression,
-            self.unused_space / 1024,
-        )
+            self.corrupted_compression_fixed,
+            self.missing_external_file,
+            self.missing_external_file_fixed,
+            self.orphan_external_pointer,
+            self.orphan_external_pointer_fixed,
+            self.overlapping_chunks,
+            self.overlapping_chunks_fixed,
+            self.mismatched_timestamps,
+            self.mismatched_timestamps_fixed,
+            self.future_timestamp,
+            self.future_timestamp_fixed,
+            self.unaligned_file,
+            self.unaligned_file_fixed,
+            self.truncated_header,
+            self.truncated_header_fixed,
+            self.pruned_chunks,
+            format_bytes(self.pruned_space),
+            self.protochunks_deleted,
+            self.recompressed_chunks,
+            format_bytes(self.recompressed_space),
+            self.empty_regions,
+            self.empty_regions_removed,
+            format_bytes(self.unused_space),
+            self.free_sectors,
+            self.double_allocated_sectors,
+            format_bytes(self.shrunk_size),
+            format_bytes(self.total_size),
+            format_bytes(self.final_size),
+            format_bytes(self.total_size.saturating_sub(self.final_size)),
+            (1.0 - self.error_rate()) * 100.0,
+            self.fragmentation_ratio() * 100.0,
+        )?;
+        if self.unsampled_chunks > 0 {
+            write!(
+                f,
+                "\n            Sampled chunks: {} of {}, about {} errors expected in total",
+                self.sampled_chunks,
+                self.sampled_chunks + self.unsampled_chunks,
+                self.estimated_errors()
+            )?;
+        }
+        if !self.failed_files.is_empty() {
+            write!(f, "\n            Failed files:")?;
+            for path in &self.failed_files {
+                write!(f, "\n                {:?}", path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The progress of a scan passed to the progress callback after each file
+#[derive(Clone, Debug)]
+pub struct ScanProgress {
+    pub files_done: u64,
+    pub files_total: u64,
+    pub path: PathBuf,
+}
+
+/// Callback receiving the progress of a scan. It's called from multiple threads
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(ScanProgress) + Send + Sync>);
+
+impl ProgressCallback {
+    pub fn new<F: Fn(ScanProgress) + Send + Sync + 'static>(callback: F) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    /// Passes the progress to the callback
+    pub fn call(&self, progress: ScanProgress) {
+        (self.0)(progress)
+    }
+}
+
+impl Debug for ProgressCallback {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "ProgressCallback")
+    }
+}
+
+type FileCallbackFn = dyn Fn(&Path, &ScanStatistics) + Send + Sync;
+
+/// Callback receiving the statistics of each scanned file. It's called from multiple threads
+#[derive(Clone)]
+pub struct FileCallback(Arc<FileCallbackFn>);
+
+impl FileCallback {
+    pub fn new<F: Fn(&Path, &ScanStatistics) + Send + Sync + 'static>(callback: F) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    /// Passes the statistics of the file to the callback
+    pub fn call(&self, path: &Path, statistic: &ScanStatistics) {
+        (self.0)(path, statistic)
     }
 }
 
+impl Debug for FileCallback {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "FileCallback")
+    }
+}
+
+/// The default maximum size of the decompressed data of a chunk
+pub const DEFAULT_MAX_DECOMPRESSED_BYTES: u64 = 16 * 1024 * 1024;
+/// The default maximum size of region files. Files of the game stay far below it
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 512 * 1024 * 1024;
+
 #[derive(Clone, Debug)]
 pub struct ScanOptions {
     pub fix: bool,
     pub fix_delete: bool,
+    pub backup: Option<PathBuf>,
+    pub backup_in_place: bool,
+    pub output_dir: Option<PathBuf>,
+    pub atomic: bool,
+    pub wipe_free_space: bool,
+    pub prune: Option<i64>,
+    pub trim: Option<TrimArea>,
+    pub defrag: bool,
+    pub skip_nbt: bool,
+    pub delete_protochunks: bool,
+    pub threads: usize,
+    pub progress: bool,
+    pub on_progress: Option<ProgressCallback>,
+    pub on_file_scanned: Option<FileCallback>,
+    pub in_memory: bool,
+    pub count_entities: bool,
+    pub top_chunks: usize,
+    pub entity_histogram: bool,
+    pub inventory: bool,
+    pub fix_entities: bool,
+    pub fix_tile_entities: bool,
+    pub fix_compression: bool,
+    pub recompress: bool,
+    pub world_time: Option<i64>,
+    pub data_version: Option<i32>,
+    pub collect_events: bool,
+    pub compression_level: u32,
+    pub max_decompressed_bytes: u64,
+    pub max_nbt_depth: u64,
+    pub max_file_size: u64,
+    pub io_retries: u32,
+    pub preserve_mtime: bool,
+    pub dry_run: bool,
+    pub sample: Option<f64>,
+    pub seed: u64,
+    pub fail_fast: bool,
+    pub checks: Vec<ScanCheck>,
+    pub required_tags: Option<Vec<String>>,
 }
 
 impl ScanOptions {
@@ -88,6 +1072,44 @@ impl ScanOptions {
         ScanOptions {
             fix: false,
             fix_delete: false,
+            backup: None,
+            backup_in_place: false,
+            output_dir: None,
+            atomic: false,
+            wipe_free_space: false,
+            prune: None,
+            trim: None,
+            defrag: false,
+            skip_nbt: false,
+            delete_protochunks: false,
+            threads: 0,
+            progress: true,
+            on_progress: None,
+            on_file_scanned: None,
+            in_memory: false,
+            count_entities: false,
+            top_chunks: 0,
+            entity_histogram: false,
+            inventory: false,
+            fix_entities: false,
+            fix_tile_entities: false,
+            fix_compression: false,
+            recompress: false,
+            world_time: None,
+            data_version: None,
+            collect_events: false,
+            compression_level: 6,
+            max_decompressed_bytes: DEFAULT_MAX_DECOMPRESSED_BYTES,
+            max_nbt_depth: DEFAULT_MAX_DEPTH,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            io_retries: 0,
+            preserve_mtime: false,
+            dry_run: false,
+            sample: None,
+            seed: 0,
+            fail_fast: false,
+            checks: ScanCheck::all(),
+            required_tags: None,
         }
     }
 
@@ -102,4 +1124,504 @@ impl ScanOptions {
 
         self
     }
+
+    /// Sets the directory region files are copied to before they're modified
+    pub fn backup(mut self, backup: Option<PathBuf>) -> Self {
+        self.backup = backup;
+
+        self
+    }
+
+    /// Sets if region files are copied to a `.bak` file next to them before they're
+    /// modified. A backup directory takes precedence
+    pub fn backup_in_place(mut self, backup_in_place: bool) -> Self {
+        self.backup_in_place = backup_in_place;
+
+        self
+    }
+
+    /// Sets the directory the region files are copied to and fixed in
+    /// so that the original files stay untouched
+    pub fn output_dir(mut self, output_dir: Option<PathBuf>) -> Self {
+        self.output_dir = output_dir;
+
+        self
+    }
+
+    /// Sets if region files are fixed in a temporary copy that replaces
+    /// the original only once fixing succeeded
+    pub fn atomic(mut self, atomic: bool) -> Self {
+        self.atomic = atomic;
+
+        self
+    }
+
+    /// Sets if the space freed by fixing is overwritten with zeros
+    pub fn wipe_free_space(mut self, wipe_free_space: bool) -> Self {
+        self.wipe_free_space = wipe_free_space;
+
+        self
+    }
+
+    /// Sets the maximum inhabited time in ticks of chunks that are deleted
+    pub fn prune(mut self, max_inhabited_ticks: Option<i64>) -> Self {
+        self.prune = max_inhabited_ticks;
+
+        self
+    }
+
+    /// Sets the area outside of which chunks are deleted
+    pub fn trim(mut self, trim: Option<TrimArea>) -> Self {
+        self.trim = trim;
+
+        self
+    }
+
+    /// Sets if gaps between chunks are closed without fixing other errors
+    pub fn defrag(mut self, defrag: bool) -> Self {
+        self.defrag = defrag;
+
+        self
+    }
+
+    /// Sets if the validation of the nbt data of chunks is skipped
+    pub fn skip_nbt(mut self, skip_nbt: bool) -> Self {
+        self.skip_nbt = skip_nbt;
+
+        self
+    }
+
+    /// Sets if chunks that aren't fully generated are deleted
+    pub fn delete_protochunks(mut self, delete_protochunks: bool) -> Self {
+        self.delete_protochunks = delete_protochunks;
+
+        self
+    }
+
+    /// Sets the number of files that are scanned in parallel. 0 uses all cores
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+
+        self
+    }
+
+    /// Sets if a progress bar is drawn while scanning
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+
+        self
+    }
+
+    /// Sets a callback that is called with the progress after each scanned file
+    pub fn on_progress<F: Fn(ScanProgress) + Send + Sync + 'static>(mut self, callback: F) -> Self {
+        self.on_progress = Some(ProgressCallback::new(callback));
+
+        self
+    }
+
+    /// Sets a callback that is called with the statistics of each file as soon as it's scanned
+    pub fn on_file_scanned<F: Fn(&Path, &ScanStatistics) + Send + Sync + 'static>(
+        mut self,
+        callback: F,
+    ) -> Self {
+        self.on_file_scanned = Some(FileCallback::new(callback));
+
+        self
+    }
+
+    /// Sets if region files are read into memory as a whole when they aren't modified.
+    /// This avoids seeking in the file for every chunk at the cost of memory
+    pub fn in_memory(mut self, in_memory: bool) -> Self {
+        self.in_memory = in_memory;
+
+        self
+    }
+
+    /// Sets the zlib compression level from 0 to 9 used when chunks are rewritten.
+    /// Higher levels result in smaller files but take longer to compress
+    pub fn compression_level(mut self, compression_level: u32) -> Self {
+        self.compression_level = compression_level;
+
+        self
+    }
+
+    /// Sets if the entities and block entities of chunks are counted
+    pub fn count_entities(mut self, count_entities: bool) -> Self {
+        self.count_entities = count_entities;
+
+        self
+    }
+
+    /// Sets the number of the largest chunks that are kept in the statistics
+    pub fn top_chunks(mut self, top_chunks: usize) -> Self {
+        self.top_chunks = top_chunks;
+
+        self
+    }
+
+    /// Sets if the entities are counted by their type
+    pub fn entity_histogram(mut self, entity_histogram: bool) -> Self {
+        self.entity_histogram = entity_histogram;
+
+        self
+    }
+
+    /// Sets if the chunks containing blocks, block entities or entities
+    /// of each namespace are counted
+    pub fn inventory(mut self, inventory: bool) -> Self {
+        self.inventory = inventory;
+
+        self
+    }
+
+    /// Sets if entities positioned outside of the chunk they're stored in are removed
+    pub fn fix_entities(mut self, fix_entities: bool) -> Self {
+        self.fix_entities = fix_entities;
+
+        self
+    }
+
+    /// Sets if all but one tile entity at the same position are removed
+    pub fn fix_tile_entities(mut self, fix_tile_entities: bool) -> Self {
+        self.fix_tile_entities = fix_tile_entities;
+
+        self
+    }
+
+    /// Sets if readable chunks are recompressed with ZLIB at the highest level.
+    /// Only chunks that occupy fewer sectors afterwards are rewritten
+    pub fn recompress(mut self, recompress: bool) -> Self {
+        self.recompress = recompress;
+
+        self
+    }
+
+    /// Sets if the compression method of chunks that can't be read with their stored one
+    /// is replaced by the method their data can actually be read with
+    pub fn fix_compression(mut self, fix_compression: bool) -> Self {
+        self.fix_compression = fix_compression;
+
+        self
+    }
+
+    /// Sets the number of ticks the world has run that the last update of chunks is compared to.
+    /// Scanning a world folder reads it from the level.dat if it isn't set
+    pub fn world_time(mut self, world_time: Option<i64>) -> Self {
+        self.world_time = world_time;
+
+        self
+    }
+
+    /// Sets the data version of the world that selects the validation rules of chunks
+    /// without their own data version. Scanning a world folder reads it from the level.dat if it isn't set
+    pub fn data_version(mut self, data_version: Option<i32>) -> Self {
+        self.data_version = data_version;
+
+        self
+    }
+
+    /// Sets if every problem found is recorded in the events of the statistics
+    pub fn collect_events(mut self, collect_events: bool) -> Self {
+        self.collect_events = collect_events;
+
+        self
+    }
+
+    /// Sets the maximum size of the decompressed data of a chunk
+    pub fn max_decompressed_bytes(mut self, max_decompressed_bytes: u64) -> Self {
+        self.max_decompressed_bytes = max_decompressed_bytes;
+
+        self
+    }
+
+    /// Sets the maximum depth of nested compounds and lists in the nbt data of chunks
+    pub fn max_nbt_depth(mut self, max_nbt_depth: u64) -> Self {
+        self.max_nbt_depth = max_nbt_depth;
+
+        self
+    }
+
+    /// Sets the size in bytes above which region files are skipped without opening them
+    pub fn max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = max_file_size;
+
+        self
+    }
+
+    /// Sets how often opening a region file is retried after errors that might be
+    /// transient like the ones of network file systems
+    pub fn io_retries(mut self, io_retries: u32) -> Self {
+        self.io_retries = io_retries;
+
+        self
+    }
+
+    /// Sets if modified region files keep their previous modification time
+    pub fn preserve_mtime(mut self, preserve_mtime: bool) -> Self {
+        self.preserve_mtime = preserve_mtime;
+
+        self
+    }
+
+    /// Sets if the fixes are only applied to a copy of each region file in memory
+    /// so that they're reported without modifying anything on disk
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+
+        self
+    }
+
+    /// Sets the share of chunks from 0 to 1 whose data is validated.
+    /// The other chunks only get the cheap checks of the region file header
+    pub fn sample(mut self, sample: Option<f64>) -> Self {
+        self.sample = sample;
+
+        self
+    }
+
+    /// Sets the seed used to select the sampled chunks so that scans can be repeated
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+
+        self
+    }
+
+    /// Returns if the data of the chunk at the index of the file is validated.
+    /// The selection only depends on the seed, the file name and the index
+    pub fn samples(&self, path: &Path, index: usize) -> bool {
+        let sample = match self.sample {
+            Some(sample) => sample,
+            None => return true,
+        };
+        let mut hash = self.seed ^ index as u64;
+        for byte in path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .bytes()
+        {
+            hash = mix_hash(hash ^ byte as u64);
+        }
+
+        // the upper 53 bits give an evenly distributed fraction from 0 to 1
+        let fraction = (mix_hash(hash) >> 11) as f64 / (1u64 << 53) as f64;
+
+        fraction < sample
+    }
+
+    /// Aborts the scan on the first region file that can't be scanned
+    /// instead of skipping it
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+
+        self
+    }
+
+    /// Sets the checks that are run. All checks are run by default
+    pub fn checks(mut self, checks: Vec<ScanCheck>) -> Self {
+        self.checks = checks;
+
+        self
+    }
+
+    /// Returns if the check is run
+    pub fn runs(&self, check: ScanCheck) -> bool {
+        self.checks.contains(&check)
+    }
+
+    /// Sets the tags chunks of the region folder need to contain instead of
+    /// the ones required by their format
+    pub fn required_tags(mut self, required_tags: Option<Vec<String>>) -> Self {
+        self.required_tags = required_tags;
+
+        self
+    }
+
+    /// Returns if the scan modifies the region files
+    pub fn modifies_files(&self) -> bool {
+        self.fix
+            || self.fix_delete
+            || self.defrag
+            || self.delete_protochunks
+            || self.fix_entities
+            || self.fix_tile_entities
+            || self.fix_compression
+            || self.recompress
+            || self.prune.is_some()
+            || self.trim.is_some()
+    }
+
+    /// Returns if files on disk are modified. Dry runs only modify copies in memory
+    pub fn writes_files(&self) -> bool {
+        self.modifies_files() && !self.dry_run
+    }
+
+    /// Returns if the space of removed chunks and gaps is reclaimed by shifting the chunks
+    pub fn defragments(&self) -> bool {
+        self.fix
+            || self.defrag
+            || self.recompress
+            || self.delete_protochunks
+            || self.prune.is_some()
+            || self.trim.is_some()
+    }
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A check of a scan that can be run on its own
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScanCheck {
+    /// Pointers outside of the file, sharing their offset or overlapping other chunks
+    Pointers,
+    /// Lengths not matching the sectors of the chunk and truncated chunks
+    Length,
+    Compression,
+    /// Decompresses and validates the nbt data of each chunk which is the slowest check
+    Nbt,
+    /// Unused space between the chunks
+    Gaps,
+    Timestamps,
+    /// Files that don't end at a sector boundary
+    Alignment,
+}
+
+impl ScanCheck {
+    /// Returns all checks
+    pub fn all() -> Vec<Self> {
+        vec![
+            Self::Pointers,
+            Self::Length,
+            Self::Compression,
+            Self::Nbt,
+            Self::Gaps,
+            Self::Timestamps,
+            Self::Alignment,
+        ]
+    }
+}
+
+impl FromStr for ScanCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "pointers" => Ok(Self::Pointers),
+            "length" => Ok(Self::Length),
+            "compression" => Ok(Self::Compression),
+            "nbt" => Ok(Self::Nbt),
+            "gaps" => Ok(Self::Gaps),
+            "timestamps" => Ok(Self::Timestamps),
+            "alignment" => Ok(Self::Alignment),
+            _ => Err(format!("Unknown check {}", s)),
+        }
+    }
+}
+
+/// A circular area of chunks that is kept when trimming a world
+#[derive(Clone, Copy, Debug)]
+pub struct TrimArea {
+    pub center_x: i32,
+    pub center_z: i32,
+    pub radius: u32,
+}
+
+impl TrimArea {
+    pub fn new(center_x: i32, center_z: i32, radius: u32) -> Self {
+        Self {
+            center_x,
+            center_z,
+            radius,
+        }
+    }
+
+    /// Returns if the chunk at the given global coordinates is inside the area
+    pub fn contains(&self, x: i32, z: i32) -> bool {
+        let dx = x as i64 - self.center_x as i64;
+        let dz = z as i64 - self.center_z as i64;
+
+        dx * dx + dz * dz <= self.radius as i64 * self.radius as i64
+    }
+
+    /// Returns if any chunk of the region at the given region coordinates is inside the area
+    pub fn intersects_region(&self, region_x: i32, region_z: i32) -> bool {
+        // the chunk of the region closest to the center
+        let x = self.center_x.clamp(region_x * 32, region_x * 32 + 31);
+        let z = self.center_z.clamp(region_z * 32, region_z * 32 + 31);
+
+        self.contains(x, z)
+    }
+}
+
+/// Scrambles the bits of the value like the splitmix64 generator
+fn mix_hash(value: u64) -> u64 {
+    let mut value = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+    value ^ (value >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_of_empty_scans_are_zero() {
+        let statistic = ScanStatistics::new();
+
+        assert_eq!(statistic.error_rate(), 0.0);
+        assert_eq!(statistic.fragmentation_ratio(), 0.0);
+    }
+
+    #[test]
+    fn rates_are_relative_to_the_chunks_and_the_size() {
+        let mut statistic = ScanStatistics::new();
+        statistic.total_chunks = 4;
+        statistic.corrupted_nbt = 1;
+        statistic.total_size = 10 * 4096;
+        statistic.unused_space = 4096;
+
+        assert_eq!(statistic.error_rate(), 0.25);
+        assert_eq!(statistic.fragmentation_ratio(), 0.1);
+    }
+
+    #[test]
+    fn clean_reports_warn_without_files_and_confirm_healthy_worlds() {
+        let (level, message) = ScanStatistics::new().clean_report().unwrap();
+        assert_eq!(level, log::Level::Warn);
+        assert!(message.starts_with("No region files"));
+
+        let mut statistic = ScanStatistics::new();
+        statistic.total_files = 2;
+        statistic.total_chunks = 10;
+        let (level, message) = statistic.clean_report().unwrap();
+        assert_eq!(level, log::Level::Info);
+        assert_eq!(
+            message,
+            "World healthy: 10 chunks across 2 files, no errors"
+        );
+
+        statistic.corrupted_nbt = 1;
+        assert!(statistic.clean_report().is_none());
+    }
+
+    #[test]
+    fn summed_statistics_add_up_the_reclaimed_space() {
+        let mut first = ScanStatistics::new();
+        first.total_files = 1;
+        first.shrunk_size = 4096;
+        let mut second = ScanStatistics::new();
+        second.total_files = 1;
+        second.shrunk_size = 3 * 4096;
+
+        let total = first + second;
+        assert_eq!(total.total_files, 2);
+        assert_eq!(total.shrunk_size, 4 * 4096);
+        assert!(total.to_string().contains("Reclaimed space: 16.00 KiB"));
+    }
 }
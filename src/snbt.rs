@@ -0,0 +1,346 @@
+use crate::nbt::NBTValue;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+pub type SnbtResult<T> = Result<T, SnbtError>;
+
+/// Parses stringified nbt like it's used in minecraft commands and rendered by `to_snbt`
+pub fn parse_snbt(snbt: &str) -> SnbtResult<NBTValue> {
+    let mut parser = SnbtParser {
+        chars: snbt.chars().collect(),
+        position: 0,
+    };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+
+    if parser.position < parser.chars.len() {
+        Err(SnbtError::TrailingData(parser.position))
+    } else {
+        Ok(value)
+    }
+}
+
+struct SnbtParser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl SnbtParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).cloned()
+    }
+
+    fn next(&mut self) -> SnbtResult<char> {
+        let c = self.peek().ok_or(SnbtError::UnexpectedEnd)?;
+        self.position += 1;
+
+        Ok(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+            self.position += 1;
+        }
+    }
+
+    /// Consumes the next character after whitespace which must be the expected one
+    fn expect(&mut self, expected: char) -> SnbtResult<()> {
+        self.skip_whitespace();
+        match self.next()? {
+            c if c == expected => Ok(()),
+            c => Err(SnbtError::UnexpectedChar(c, self.position - 1)),
+        }
+    }
+
+    fn parse_value(&mut self) -> SnbtResult<NBTValue> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.parse_compound(),
+            Some('[') => self.parse_list(),
+            Some('"') | Some('\'') => Ok(NBTValue::String(self.parse_quoted()?)),
+            Some(_) => {
+                let token = self.parse_unquoted()?;
+                Ok(parse_unquoted_value(token))
+            }
+            None => Err(SnbtError::UnexpectedEnd),
+        }
+    }
+
+    fn parse_compound(&mut self) -> SnbtResult<NBTValue> {
+        self.expect('{')?;
+        let mut compound = HashMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(NBTValue::Compound(compound));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = match self.peek() {
+                Some('"') | Some('\'') => self.parse_quoted()?,
+                _ => self.parse_unquoted()?,
+            };
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            compound.insert(key, value);
+
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => continue,
+                '}' => break,
+                c => return Err(SnbtError::UnexpectedChar(c, self.position - 1)),
+            }
+        }
+
+        Ok(NBTValue::Compound(compound))
+    }
+
+    /// Parses a list or an array with a type prefix like `[I;1,2,3]`
+    fn parse_list(&mut self) -> SnbtResult<NBTValue> {
+        self.expect('[')?;
+        let array_type = match (self.peek(), self.chars.get(self.position + 1)) {
+            (Some(t @ 'B'), Some(';'))
+            | (Some(t @ 'I'), Some(';'))
+            | (Some(t @ 'L'), Some(';')) => {
+                self.position += 2;
+                Some(t)
+            }
+            _ => None,
+        };
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+        } else {
+            loop {
+                let item = match array_type {
+                    Some(_) => {
+                        self.skip_whitespace();
+                        let token = self.parse_unquoted()?;
+                        parse_unquoted_value(token)
+                    }
+                    None => self.parse_value()?,
+                };
+                items.push(item);
+
+                self.skip_whitespace();
+                match self.next()? {
+                    ',' => continue,
+                    ']' => break,
+                    c => return Err(SnbtError::UnexpectedChar(c, self.position - 1)),
+                }
+            }
+        }
+
+        match array_type {
+            Some('B') => collect_array(items, |v| match v {
+                NBTValue::Byte(v) => Some(v),
+                NBTValue::Int(v) => i8::try_from(v).ok().map(|v| v as u8),
+                _ => None,
+            })
+            .map(NBTValue::ByteArray),
+            Some('I') => collect_array(items, |v| match v {
+                NBTValue::Int(v) => Some(v),
+                _ => None,
+            })
+            .map(NBTValue::IntArray),
+            Some(_) => collect_array(items, |v| match v {
+                NBTValue::Long(v) => Some(v),
+                NBTValue::Int(v) => Some(v as i64),
+                _ => None,
+            })
+            .map(NBTValue::LongArray),
+            None => {
+                // all items of a list need to be stored with the same tag
+                if let Some(first) = items.first() {
+                    if items.iter().any(|i| i.tag_id() != first.tag_id()) {
+                        return Err(SnbtError::MixedList);
+                    }
+                }
+                Ok(NBTValue::List(items))
+            }
+        }
+    }
+
+    /// Parses a string in single or double quotes with backslash escapes
+    fn parse_quoted(&mut self) -> SnbtResult<String> {
+        let quote = self.next()?;
+        let mut value = String::new();
+
+        loop {
+            match self.next()? {
+                '\\' => value.push(self.next()?),
+                c if c == quote => break,
+                c => value.push(c),
+            }
+        }
+
+        Ok(value)
+    }
+
+    /// Parses a key or value that isn't quoted
+    fn parse_unquoted(&mut self) -> SnbtResult<String> {
+        let start = self.position;
+        while self
+            .peek()
+            .map(|c| c.is_ascii_alphanumeric() || "_-.+".contains(c))
+            .unwrap_or(false)
+        {
+            self.position += 1;
+        }
+        if start == self.position {
+            return match self.peek() {
+                Some(c) => Err(SnbtError::UnexpectedChar(c, self.position)),
+                None => Err(SnbtError::UnexpectedEnd),
+            };
+        }
+
+        Ok(self.chars[start..self.position].iter().collect())
+    }
+}
+
+/// Converts an unquoted token into a number depending on its suffix or a string
+/// if it isn't a number. Booleans are stored as bytes
+fn parse_unquoted_value(token: String) -> NBTValue {
+    match token.as_str() {
+        "true" => return NBTValue::Byte(1),
+        "false" => return NBTValue::Byte(0),
+        _ => {}
+    }
+    // rust also parses words like inf and NaN as floats
+    let is_numeric = token
+        .chars()
+        .next()
+        .map(|c| c.is_ascii_digit() || "-+.".contains(c))
+        .unwrap_or(false);
+    if !is_numeric {
+        return NBTValue::String(token);
+    }
+    let (number, suffix) = token.split_at(token.len() - 1);
+
+    let value = match suffix {
+        "b" | "B" => number.parse::<i8>().ok().map(|v| NBTValue::Byte(v as u8)),
+        "s" | "S" => number.parse().ok().map(NBTValue::Short),
+        "l" | "L" => number.parse().ok().map(NBTValue::Long),
+        "f" | "F" => number.parse().ok().map(NBTValue::Float),
+        "d" | "D" => number.parse().ok().map(NBTValue::Double),
+        _ => token
+            .parse()
+            .ok()
+            .map(NBTValue::Int)
+            .or_else(|| token.parse().ok().map(NBTValue::Double)),
+    };
+
+    value.unwrap_or(NBTValue::String(token))
+}
+
+/// Converts the items of a typed array into its element type
+fn collect_array<T, F: Fn(NBTValue) -> Option<T>>(
+    items: Vec<NBTValue>,
+    convert: F,
+) -> SnbtResult<Vec<T>> {
+    items
+        .into_iter()
+        .map(|item| convert(item).ok_or(SnbtError::InvalidArrayItem))
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum SnbtError {
+    UnexpectedEnd,
+    UnexpectedChar(char, usize),
+    TrailingData(usize),
+    InvalidArrayItem,
+    MixedList,
+}
+
+impl Display for SnbtError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd => write!(f, "Unexpected end of input"),
+            Self::UnexpectedChar(c, position) => {
+                write!(f, "Unexpected character '{}' at {}", c, position)
+            }
+            Self::TrailingData(position) => {
+                write!(f, "Unexpected data after the value at {}", position)
+            }
+            Self::InvalidArrayItem => write!(f, "Array item doesn't match the type of the array"),
+            Self::MixedList => write!(f, "List items have different types"),
+        }
+    }
+}
+
+impl Error for SnbtError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rendered_snbt_is_parsed_unchanged() {
+        let mut nested = HashMap::new();
+        nested.insert(
+            String::from("id"),
+            NBTValue::String("minecraft:chest".into()),
+        );
+        nested.insert(String::from("Count"), NBTValue::Byte(0xFF));
+        let mut root = HashMap::new();
+        root.insert(String::from("Short"), NBTValue::Short(-7));
+        root.insert(String::from("Int"), NBTValue::Int(42));
+        root.insert(String::from("Long"), NBTValue::Long(i64::MIN));
+        root.insert(String::from("Float"), NBTValue::Float(1.5));
+        root.insert(String::from("Double"), NBTValue::Double(-0.25));
+        root.insert(
+            String::from("ByteArray"),
+            NBTValue::ByteArray(vec![1, 0xFE]),
+        );
+        root.insert(String::from("IntArray"), NBTValue::IntArray(vec![-1, 2]));
+        root.insert(String::from("LongArray"), NBTValue::LongArray(vec![3, -4]));
+        root.insert(String::from("EmptyList"), NBTValue::List(Vec::new()));
+        root.insert(
+            String::from("Items"),
+            NBTValue::List(vec![NBTValue::Compound(nested.clone())]),
+        );
+        root.insert(String::from("key with spaces"), NBTValue::Compound(nested));
+        root.insert(String::from(""), NBTValue::Int(0));
+        root.insert(
+            String::from("Escaped"),
+            NBTValue::String(r#"say "hi" \ bye"#.into()),
+        );
+        let root = NBTValue::Compound(root);
+
+        assert_eq!(parse_snbt(&root.to_snbt()).unwrap(), root);
+    }
+
+    #[test]
+    fn keys_and_strings_are_quoted_and_escaped() {
+        let mut root = HashMap::new();
+        root.insert(String::from("a b"), NBTValue::String(r#""\"#.into()));
+        let snbt = NBTValue::Compound(root).to_snbt();
+
+        assert_eq!(snbt, r#"{"a b":"\"\\"}"#);
+    }
+
+    #[test]
+    fn typed_arrays_are_parsed_with_their_element_type() {
+        assert_eq!(
+            parse_snbt("[B; 1b, -1b]").unwrap(),
+            NBTValue::ByteArray(vec![1, 0xFF])
+        );
+        assert_eq!(
+            parse_snbt("[I;1,2]").unwrap(),
+            NBTValue::IntArray(vec![1, 2])
+        );
+        assert_eq!(
+            parse_snbt("[L;1L,2]").unwrap(),
+            NBTValue::LongArray(vec![1, 2])
+        );
+        assert!(matches!(
+            parse_snbt("[I;1L]"),
+            Err(SnbtError::InvalidArrayItem)
+        ));
+    }
+}
@@ -1,11 +1,17 @@
 use flate2::read::ZlibEncoder;
 use flate2::Compression;
-use std::io::{Read, Result};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
 
 #[derive(Clone, Debug)]
 pub struct ByteArrayCache {
     inner: Vec<u8>,
     position: usize,
+    compression: Compression,
 }
 
 impl ByteArrayCache {
@@ -14,6 +20,7 @@ impl ByteArrayCache {
         Self {
             inner: Vec::new(),
             position: 0,
+            compression: Compression::default(),
         }
     }
 
@@ -22,25 +29,303 @@ impl ByteArrayCache {
         Self {
             inner: Vec::with_capacity(capacity),
             position: 0,
+            compression: Compression::default(),
+        }
+    }
+
+    /// Creates a new byte array cache compressing with the given level.
+    /// Higher levels result in smaller data but take longer to compress
+    pub fn with_compression(compression: Compression) -> Self {
+        Self {
+            inner: Vec::new(),
+            position: 0,
+            compression,
         }
     }
 
     /// Write the data to the inner buffer by using compression
     pub fn write<R: Read>(&mut self, reader: R) -> Result<()> {
-        let mut encoder = ZlibEncoder::new(reader, Compression::default());
+        self.write_with_level(reader, self.compression)
+    }
+
+    /// Write the data to the inner buffer by using compression with the given level
+    pub fn write_with_level<R: Read>(&mut self, reader: R, level: Compression) -> Result<()> {
+        let mut encoder = ZlibEncoder::new(reader, level);
         let mut buffer = Vec::new();
         encoder.read_to_end(&mut buffer)?;
         self.inner.append(&mut buffer);
 
         Ok(())
     }
+
+    /// Moves the read position back to the start of the data
+    pub fn reset(&mut self) {
+        self.position = 0;
+    }
+
+    /// Removes all data and resets the read position
+    pub fn clear(&mut self) {
+        self.inner.clear();
+        self.position = 0;
+    }
+
+    /// Returns the length of the compressed data
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns if the cache doesn't contain any data
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+impl Default for ByteArrayCache {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Read for ByteArrayCache {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let read = (&self.inner[self.position..]).read(buf)?;
+        // the position can be behind the end of the data after seeking
+        let read = self.inner.get(self.position..).unwrap_or(&[]).read(buf)?;
         self.position += read;
 
         Ok(read)
     }
 }
+
+impl Seek for ByteArrayCache {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (self.inner.len() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => (self.position as u64).checked_add_signed(offset),
+        };
+        let position = position.ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                "Seeking to a negative or overflowing position",
+            )
+        })?;
+        self.position = position as usize;
+
+        Ok(position)
+    }
+}
+
+/// Error returned by the [LimitedReader] when more data than allowed was read
+#[derive(Debug)]
+pub(crate) struct SizeLimitExceeded(pub u64);
+
+impl Display for SizeLimitExceeded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Data exceeds the size limit of {} bytes", self.0)
+    }
+}
+
+impl Error for SizeLimitExceeded {}
+
+impl SizeLimitExceeded {
+    /// Returns if the io error was caused by exceeding the size limit
+    pub fn is_cause_of(error: &io::Error) -> bool {
+        error
+            .get_ref()
+            .is_some_and(|inner| inner.is::<SizeLimitExceeded>())
+    }
+}
+
+/// A reader that returns an error once more than `limit` bytes were read from it
+pub(crate) struct LimitedReader<R> {
+    inner: R,
+    limit: u64,
+    read: u64,
+}
+
+impl<R: Read> LimitedReader<R> {
+    pub fn new(inner: R, limit: u64) -> Self {
+        Self {
+            inner,
+            limit,
+            read: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read += read as u64;
+
+        if self.read > self.limit {
+            Err(io::Error::new(
+                ErrorKind::InvalidData,
+                SizeLimitExceeded(self.limit),
+            ))
+        } else {
+            Ok(read)
+        }
+    }
+}
+
+/// Formats a number of bytes with the largest fitting binary unit and two decimals
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+
+    // values that round up to 1024.00 are shown with the next unit instead
+    while value >= 1023.995 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.2} {}", value, UNITS[unit])
+}
+
+/// The delay before the first retry of a failed io operation. It doubles with each retry
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Runs the io operation on the file and retries it up to `retries` times with an
+/// exponentially growing delay if it fails with an error that might be transient.
+/// Errors like missing files or invalid data are returned immediately
+pub(crate) fn retry_io<T, F: FnMut() -> Result<T>>(
+    path: &Path,
+    retries: u32,
+    mut operation: F,
+) -> Result<T> {
+    let mut attempt = 0;
+
+    loop {
+        match operation() {
+            Err(e) if attempt < retries && is_transient(&e) => {
+                let delay = RETRY_DELAY * 2u32.pow(attempt);
+                attempt += 1;
+                log::debug!(
+                    "Retrying {:?} in {}ms ({} of {}) after: {}",
+                    path,
+                    delay.as_millis(),
+                    attempt,
+                    retries,
+                    e
+                );
+                thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Copies the file to the backup path and creates the directories leading to it
+pub(crate) fn back_up_file(path: &Path, backup_path: &Path) -> Result<()> {
+    if let Some(parent) = backup_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    log::debug!("Backing up {:?} to {:?}", path, backup_path);
+    std::fs::copy(path, backup_path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to back up {:?} to {:?}: {}", path, backup_path, e),
+        )
+    })?;
+
+    Ok(())
+}
+
+/// Returns if the error might not occur again like errors of network file systems
+fn is_transient(error: &io::Error) -> bool {
+    !matches!(
+        error.kind(),
+        ErrorKind::NotFound
+            | ErrorKind::PermissionDenied
+            | ErrorKind::InvalidData
+            | ErrorKind::InvalidInput
+            | ErrorKind::UnexpectedEof
+            | ErrorKind::Unsupported
+    )
+}
+
+/// Formats the bytes as a lowercase hexadecimal string
+pub fn format_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+
+    #[test]
+    fn bytes_are_formatted_with_the_largest_fitting_unit() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1.00 KiB");
+        assert_eq!(format_bytes(1_048_575), "1.00 MiB");
+        assert_eq!(format_bytes(1_048_000), "1023.44 KiB");
+        assert_eq!(format_bytes(1_048_576), "1.00 MiB");
+        assert_eq!(format_bytes(1 << 30), "1.00 GiB");
+        // there is no larger unit than GiB
+        assert_eq!(format_bytes(5 << 40), "5120.00 GiB");
+    }
+
+    /// Returns a cache containing the compressed bytes
+    fn filled_cache(data: &[u8]) -> ByteArrayCache {
+        let mut cache = ByteArrayCache::new();
+        cache.write(data).unwrap();
+
+        cache
+    }
+
+    #[test]
+    fn data_compressed_with_the_best_level_can_be_decompressed() {
+        let data = b"minecraft:stone ".repeat(100);
+        let mut cache = ByteArrayCache::with_compression(Compression::best());
+        cache.write(data.as_slice()).unwrap();
+        assert!(cache.len() < data.len());
+
+        let mut decompressed = Vec::new();
+        ZlibDecoder::new(cache)
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+    }
+
+    #[test]
+    fn seeking_backwards_reads_the_data_again() {
+        let mut cache = filled_cache(b"region");
+        let mut first = Vec::new();
+        cache.read_to_end(&mut first).unwrap();
+
+        cache
+            .seek(SeekFrom::Current(-(first.len() as i64)))
+            .unwrap();
+        let mut second = Vec::new();
+        cache.read_to_end(&mut second).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn reading_after_seeking_past_the_end_returns_nothing() {
+        let mut cache = filled_cache(b"region");
+        let end = cache.len() as u64;
+
+        assert_eq!(cache.seek(SeekFrom::End(10)).unwrap(), end + 10);
+        assert_eq!(cache.read(&mut [0u8; 16]).unwrap(), 0);
+    }
+
+    #[test]
+    fn seeking_before_the_start_fails() {
+        let mut cache = filled_cache(b"region");
+        cache.seek(SeekFrom::Start(2)).unwrap();
+
+        let error = cache.seek(SeekFrom::Current(-3)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        // the position isn't changed by the failed seek
+        assert_eq!(cache.stream_position().unwrap(), 2);
+    }
+}
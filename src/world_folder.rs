@@ -1,91 +1,1515 @@
-use crate::region_file::RegionFile;
-use crate::scan::ScanOptions;
-use crate::scan::ScanStatistics;
+use crate::nbt::{NBTValue, TaggedNBTValue};
+use crate::region_file::{
+    external_chunk_coords, parse_region_coords, Checksum, ChunkSize, RegionFile, RegionFolder,
+    RegionFormat, ANVIL_EXTENSION, BLOCK_SIZE, MCREGION_EXTENSION,
+};
+use crate::scan::{RepairAction, ScanStatistics};
+use crate::scan::{ScanOptions, ScanProgress, DEFAULT_MAX_FILE_SIZE};
+use crate::utils::{back_up_file, retry_io};
+use crate::world_info::WorldInfo;
+use filetime::FileTime;
+use flate2::Compression;
 use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use log::LevelFilter;
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
 use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Display, Formatter};
 use std::fs;
-use std::fs::OpenOptions;
-use std::io;
+use std::io::{self, IsTerminal};
 use std::ops::Add;
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant, SystemTime};
+
+/// The file locked by the game while the world is loaded
+const SESSION_LOCK: &str = "session.lock";
+/// The suffix appended to the name of the copy of a region file that is fixed atomically
+const TEMP_FILE_SUFFIX: &str = ".tmp";
+/// The suffix appended to the name of the backup of a file stored next to it
+const BACKUP_SUFFIX: &str = ".bak";
+/// The folder storing the dimensions added by datapacks
+const CUSTOM_DIMENSIONS_DIR: &str = "dimensions";
+
+/// The smallest and largest chunk coordinates of an area as `((min_x, min_z), (max_x, max_z))`
+pub type ChunkBounds = ((i32, i32), (i32, i32));
+
+/// A dimension of the world with its own region folders
+#[derive(Clone, Debug, PartialEq)]
+pub enum Dimension {
+    Overworld,
+    Nether,
+    End,
+    /// A dimension added by a datapack named like `namespace:path`
+    /// which is stored in `dimensions/<namespace>/<path>`
+    Custom(String),
+}
+
+impl Dimension {
+    /// Returns all vanilla dimensions
+    pub fn all() -> Vec<Self> {
+        vec![Self::Overworld, Self::Nether, Self::End]
+    }
+
+    /// Returns the name of the dimension
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Overworld => "overworld",
+            Self::Nether => "nether",
+            Self::End => "end",
+            Self::Custom(name) => name,
+        }
+    }
+
+    /// Returns the path of the dimension inside the world folder
+    pub fn path(&self, world_path: &Path) -> PathBuf {
+        match self {
+            Self::Overworld => world_path.to_path_buf(),
+            Self::Nether => world_path.join("DIM-1"),
+            Self::End => world_path.join("DIM1"),
+            Self::Custom(name) => {
+                let (namespace, path) = name.split_once(':').unwrap_or(("minecraft", name));
+
+                world_path
+                    .join(CUSTOM_DIMENSIONS_DIR)
+                    .join(namespace)
+                    .join(path)
+            }
+        }
+    }
+}
+
+impl FromStr for Dimension {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "overworld" => Ok(Self::Overworld),
+            "nether" => Ok(Self::Nether),
+            "end" => Ok(Self::End),
+            _ if s.contains(':') => Ok(Self::Custom(s.to_string())),
+            _ => Err(format!("Unknown dimension {}", s)),
+        }
+    }
+}
+
+/// A pattern selecting region files by their coordinates like `r.0.*` or `r.5.-3`.
+/// A `*` matches any coordinate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RegionPattern {
+    x: Option<i32>,
+    z: Option<i32>,
+}
+
+impl RegionPattern {
+    /// Returns if the region at the given coordinates matches the pattern
+    pub fn matches(&self, x: i32, z: i32) -> bool {
+        self.x.map(|px| px == x).unwrap_or(true) && self.z.map(|pz| pz == z).unwrap_or(true)
+    }
+}
+
+impl FromStr for RegionPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid region pattern {}. Expected r.<x>.<z> with numbers or *",
+                s
+            )
+        };
+        let parse_coord = |coord: &str| match coord {
+            "*" => Ok(None),
+            _ => coord.parse().map(Some).map_err(|_| invalid()),
+        };
+        let parts: Vec<&str> = s
+            .trim_end_matches(".mca")
+            .trim_end_matches(".mcr")
+            .split('.')
+            .collect();
+
+        match parts.as_slice() {
+            ["r", x, z] => Ok(Self {
+                x: parse_coord(x)?,
+                z: parse_coord(z)?,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// A box of region coordinates like `-2,-2,2,2` selecting the region files
+/// from the first corner up to and including the second one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RegionBounds {
+    min_x: i32,
+    min_z: i32,
+    max_x: i32,
+    max_z: i32,
+}
+
+impl RegionBounds {
+    pub fn new(x1: i32, z1: i32, x2: i32, z2: i32) -> Self {
+        Self {
+            min_x: x1.min(x2),
+            min_z: z1.min(z2),
+            max_x: x1.max(x2),
+            max_z: z1.max(z2),
+        }
+    }
+
+    /// Converts a box of chunk coordinates to the box of the regions containing the chunks
+    pub fn from_chunk_bounds(chunk_bounds: RegionBounds) -> Self {
+        Self::new(
+            chunk_bounds.min_x >> 5,
+            chunk_bounds.min_z >> 5,
+            chunk_bounds.max_x >> 5,
+            chunk_bounds.max_z >> 5,
+        )
+    }
+
+    /// Returns if the region at the given coordinates is inside the bounds
+    pub fn contains(&self, x: i32, z: i32) -> bool {
+        (self.min_x..=self.max_x).contains(&x) && (self.min_z..=self.max_z).contains(&z)
+    }
+}
+
+impl FromStr for RegionBounds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid bounds {}. Expected <x1>,<z1>,<x2>,<z2> with numbers",
+                s
+            )
+        };
+        let coords = s
+            .split(',')
+            .map(|coord| coord.trim().parse::<i32>().map_err(|_| invalid()))
+            .collect::<Result<Vec<i32>, String>>()?;
+
+        match coords.as_slice() {
+            [x1, z1, x2, z2] => Ok(Self::new(*x1, *z1, *x2, *z2)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// How a chunk of the other world differs from the one of the compared world
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChunkDifference {
+    /// The chunk only exists in the other world
+    Added,
+    /// The chunk only exists in the compared world
+    Removed,
+    Changed,
+}
+
+impl Display for ChunkDifference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added => write!(f, "added"),
+            Self::Removed => write!(f, "removed"),
+            Self::Changed => write!(f, "changed"),
+        }
+    }
+}
+
+/// The checksums of the region files of a world
+pub struct WorldChecksum {
+    /// The checksum of each file by its path relative to the world ordered by the path
+    pub files: Vec<(PathBuf, io::Result<Checksum>)>,
+    /// The checksum combining the ones of the files. `None` if any file couldn't be read
+    pub world: Option<Checksum>,
+}
 
 pub struct WorldFolder {
     path: PathBuf,
+    dimensions: Vec<Dimension>,
+    folders: Vec<RegionFolder>,
+    regions: Vec<RegionPattern>,
+    bounds: Option<RegionBounds>,
+    max_file_size: u64,
 }
 
 impl WorldFolder {
     pub fn new(path: PathBuf) -> Self {
-        Self { path }
+        Self {
+            path,
+            dimensions: vec![Dimension::Overworld],
+            folders: vec![RegionFolder::Region],
+            regions: Vec::new(),
+            bounds: None,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+        }
+    }
+
+    /// Sets the dimensions that are scanned
+    pub fn dimensions(mut self, dimensions: Vec<Dimension>) -> Self {
+        self.dimensions = dimensions;
+
+        self
+    }
+
+    /// Sets the kinds of region folders that are scanned
+    pub fn folders(mut self, folders: Vec<RegionFolder>) -> Self {
+        self.folders = folders;
+
+        self
+    }
+
+    /// Sets the size in bytes above which region files are skipped when counting their chunks
+    pub fn max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = max_file_size;
+
+        self
+    }
+
+    /// Reads the information about the world from its level.dat
+    pub fn info(&self) -> io::Result<WorldInfo> {
+        WorldInfo::read(&self.path)
+    }
+
+    /// Reads the number of ticks the world has run from its level.dat.
+    /// Returns `None` if the file doesn't exist or can't be parsed
+    pub fn world_time(&self) -> Option<i64> {
+        self.info().ok()?.time
+    }
+
+    /// Sets the world time and data version of the options that aren't set
+    /// from the level.dat if it can be read
+    fn fill_world_info(&self, options: &mut ScanOptions) {
+        if options.world_time.is_some() && options.data_version.is_some() {
+            return;
+        }
+        match self.info() {
+            Ok(info) => {
+                options.world_time = options.world_time.or(info.time);
+                options.data_version = options.data_version.or(info.data_version);
+            }
+            Err(e) => log::debug!("Failed to read the level.dat of {:?}: {}", self.path, e),
+        }
+    }
+
+    /// Returns if the session.lock of the world is locked by a running game or server.
+    /// Worlds without a session.lock are never considered in use
+    #[cfg(unix)]
+    pub fn is_in_use(&self) -> io::Result<bool> {
+        use std::os::unix::io::AsRawFd;
+
+        let file = match fs::File::open(self.path.join(SESSION_LOCK)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        // java locks the file with fcntl which doesn't conflict with flock
+        let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+        lock.l_type = libc::F_RDLCK as libc::c_short;
+        lock.l_whence = libc::SEEK_SET as libc::c_short;
+        // F_GETLK only reports a conflicting lock without acquiring the shared lock
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &mut lock) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(lock.l_type != libc::F_UNLCK as libc::c_short)
+    }
+
+    /// Returns if the session.lock of the world is locked by a running game or server.
+    /// Worlds without a session.lock are never considered in use
+    #[cfg(not(unix))]
+    pub fn is_in_use(&self) -> io::Result<bool> {
+        // windows denies reading files locked by another process
+        const ERROR_LOCK_VIOLATION: i32 = 33;
+
+        match fs::read(self.path.join(SESSION_LOCK)) {
+            Ok(_) => Ok(false),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) if e.raw_os_error() == Some(ERROR_LOCK_VIOLATION) => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sets the patterns of which region files need to match any to be included.
+    /// All region files are included if no pattern is set
+    pub fn regions(mut self, regions: Vec<RegionPattern>) -> Self {
+        self.regions = regions;
+
+        self
+    }
+
+    /// Sets the box of region coordinates outside of which region files are ignored
+    pub fn bounds(mut self, bounds: Option<RegionBounds>) -> Self {
+        self.bounds = bounds;
+
+        self
+    }
+
+    /// Returns the number of region files of the world
+    pub fn count_region_files(&self) -> io::Result<usize> {
+        Ok(self.region_file_paths()?.len())
     }
 
     /// Counts all chunks of a world
     pub fn count_chunks(&self) -> io::Result<u64> {
-        let mut count = 0u64;
+        let count = self
+            .count_chunks_per_file()?
+            .iter()
+            .map(|(_, count)| *count as u64)
+            .sum();
+
+        Ok(count)
+    }
+
+    /// Counts the chunks of each region file of a world.
+    /// Files that can't be opened or are larger than the maximum file size are skipped
+    pub fn count_chunks_per_file(&self) -> io::Result<Vec<(PathBuf, usize)>> {
+        let paths = self.region_file_paths()?;
+        let counts: Vec<(PathBuf, usize)> = paths
+            .into_par_iter()
+            .filter(|path| !is_oversized(path, self.max_file_size))
+            .filter_map(|path| match RegionFile::open_read_only(&path) {
+                Ok(region_file) => {
+                    let count = region_file.count_chunks();
+                    Some((path, count))
+                }
+                Err(e) => {
+                    log::warn!("Skipping region file {:?}: {}", path, e);
+                    None
+                }
+            })
+            .collect();
+
+        Ok(counts)
+    }
+
+    /// Returns the size of all chunks ordered by their coordinates
+    /// without decompressing them. Files that can't be opened are skipped
+    pub fn chunk_sizes(&self) -> io::Result<Vec<ChunkSize>> {
+        let mut sizes = Vec::new();
 
-        for file in self.region_file_paths() {
-            let region_file = RegionFile::new(&file)?;
-            count += region_file.count_chunks() as u64;
+        for path in self.region_file_paths()? {
+            match RegionFile::open_read_only(&path) {
+                Ok(mut region_file) => sizes.append(&mut region_file.chunk_sizes()),
+                Err(e) => log::warn!("Skipping region file {:?}: {}", path, e),
+            }
         }
+        sizes.sort_by_key(|size| (size.x, size.z));
 
-        Ok(count)
+        Ok(sizes)
+    }
+
+    /// Returns the size in bytes of each region file taken from the file system
+    /// without opening the files. The files are ordered by their size starting with the largest
+    pub fn file_sizes(&self) -> io::Result<Vec<(PathBuf, u64)>> {
+        let mut sizes = Vec::new();
+
+        for path in self.region_file_paths()? {
+            let size = fs::metadata(&path)?.len();
+            sizes.push((path, size));
+        }
+        sizes.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+
+        Ok(sizes)
+    }
+
+    /// Returns the global coordinates, number of sectors and timestamp of all chunks
+    /// ordered by their coordinates. Files that can't be opened are skipped
+    pub fn list_chunks(&self) -> io::Result<Vec<(i32, i32, u8, u32)>> {
+        let mut chunks = Vec::new();
+
+        for path in self.region_file_paths()? {
+            match RegionFile::open_read_only(&path) {
+                Ok(region_file) => chunks.append(&mut region_file.chunk_entries()),
+                Err(e) => log::warn!("Skipping region file {:?}: {}", path, e),
+            }
+        }
+        chunks.sort_by_key(|(x, z, _, _)| (*x, *z));
+
+        Ok(chunks)
+    }
+
+    /// Compares the chunks of the world with the ones of the other world and returns
+    /// the coordinates of the differing chunks ordered by their coordinates.
+    /// Chunks are considered changed if their timestamp or length differs
+    /// or if `deep` is set and their decompressed nbt data differs
+    pub fn diff(
+        &self,
+        other: &WorldFolder,
+        deep: bool,
+    ) -> io::Result<Vec<(i32, i32, ChunkDifference)>> {
+        let paths = self.region_file_names()?;
+        let other_paths = other.region_file_names()?;
+        let names: BTreeSet<&PathBuf> = paths.keys().chain(other_paths.keys()).collect();
+        let mut differences = Vec::new();
+
+        for name in names {
+            let mut file = paths
+                .get(name)
+                .map(RegionFile::open_read_only)
+                .transpose()?;
+            let mut other_file = other_paths
+                .get(name)
+                .map(RegionFile::open_read_only)
+                .transpose()?;
+            let sizes = Self::chunk_size_map(&mut file);
+            let other_sizes = Self::chunk_size_map(&mut other_file);
+
+            for (&(x, z), size) in &sizes {
+                let changed = match (other_sizes.get(&(x, z)), &mut file, &mut other_file) {
+                    (None, _, _) => {
+                        differences.push((x, z, ChunkDifference::Removed));
+                        continue;
+                    }
+                    (Some(_), Some(file), Some(other_file)) if deep => {
+                        Self::chunk_nbt(file, x, z) != Self::chunk_nbt(other_file, x, z)
+                    }
+                    (Some(other_size), _, _) => {
+                        size.length != other_size.length || size.timestamp != other_size.timestamp
+                    }
+                };
+                if changed {
+                    differences.push((x, z, ChunkDifference::Changed));
+                }
+            }
+            for &(x, z) in other_sizes.keys().filter(|c| !sizes.contains_key(c)) {
+                differences.push((x, z, ChunkDifference::Added));
+            }
+        }
+        differences.sort_by_key(|(x, z, _)| (*x, *z));
+
+        Ok(differences)
+    }
+
+    /// Computes the checksum of the chunks of each region file and a checksum of the
+    /// world combining them with the paths of the files relative to the world
+    pub fn checksums(&self) -> io::Result<WorldChecksum> {
+        let mut names: Vec<(PathBuf, PathBuf)> = self.region_file_names()?.into_iter().collect();
+        names.sort();
+        let files: Vec<(PathBuf, io::Result<Checksum>)> = names
+            .into_par_iter()
+            .map(|(name, path)| {
+                let checksum = RegionFile::open_read_only(&path)
+                    .and_then(|mut region_file| region_file.checksum());
+                (name, checksum)
+            })
+            .collect();
+        let mut hasher = Sha256::new();
+        let mut complete = true;
+
+        for (name, checksum) in &files {
+            match checksum {
+                Ok(checksum) => {
+                    // paths are hashed with forward slashes to be the same on every platform
+                    let name = name
+                        .iter()
+                        .filter_map(|c| c.to_str())
+                        .collect::<Vec<&str>>();
+                    hasher.update(name.join("/").as_bytes());
+                    hasher.update([0]);
+                    hasher.update(checksum);
+                }
+                Err(_) => complete = false,
+            }
+        }
+
+        Ok(WorldChecksum {
+            files,
+            world: Some(hasher.finalize().into()).filter(|_| complete),
+        })
+    }
+
+    /// Returns the sizes of the chunks of the region file by their coordinates
+    fn chunk_size_map(region_file: &mut Option<RegionFile>) -> HashMap<(i32, i32), ChunkSize> {
+        region_file
+            .as_mut()
+            .map(|f| f.chunk_sizes())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|size| ((size.x, size.z), size))
+            .collect()
+    }
+
+    /// Reads the nbt data of a chunk for comparing it.
+    /// Chunks that can't be read are logged and compared as missing
+    fn chunk_nbt(region_file: &mut RegionFile, x: i32, z: i32) -> Option<TaggedNBTValue> {
+        match region_file.read_chunk_nbt(x as isize, z as isize) {
+            Ok(data) => data.map(|data| TaggedNBTValue::from(NBTValue::Compound(data))),
+            Err(e) => {
+                log::warn!("Failed to read chunk ({}, {}): {}", x, z, e);
+                None
+            }
+        }
+    }
+
+    /// Counts the chunks of the world by their generation status.
+    /// Files that can't be opened are skipped
+    pub fn count_statuses(&self) -> io::Result<HashMap<String, u64>> {
+        let paths = self.region_file_paths()?;
+        let statuses = paths
+            .into_par_iter()
+            .filter_map(|path| match RegionFile::open_read_only(&path) {
+                Ok(mut region_file) => Some(region_file.count_statuses()),
+                Err(e) => {
+                    log::warn!("Skipping region file {:?}: {}", path, e);
+                    None
+                }
+            })
+            .reduce(HashMap::new, |mut a, b| {
+                for (status, count) in b {
+                    *a.entry(status).or_insert(0) += count;
+                }
+                a
+            });
+
+        Ok(statuses)
+    }
+
+    /// Returns the global coordinates and inhabited time of all chunks.
+    /// Files that can't be opened are skipped
+    pub fn inhabited_times(&self) -> io::Result<Vec<(i32, i32, i64)>> {
+        let paths = self.region_file_paths()?;
+        let times = paths
+            .into_par_iter()
+            .filter_map(|path| match RegionFile::open_read_only(&path) {
+                Ok(mut region_file) => Some(region_file.inhabited_times()),
+                Err(e) => {
+                    log::warn!("Skipping region file {:?}: {}", path, e);
+                    None
+                }
+            })
+            .flatten()
+            .collect();
+
+        Ok(times)
+    }
+
+    /// Returns the smallest and largest chunk coordinates covered by the region files
+    /// by only looking at their names. Returns `None` if there are no region files
+    pub fn chunk_bounds(&self) -> io::Result<Option<ChunkBounds>> {
+        let bounds = self
+            .region_file_paths()?
+            .iter()
+            .filter_map(|path| parse_region_coords(path))
+            .fold(None, |bounds: Option<ChunkBounds>, (x, z)| {
+                let ((min_x, min_z), (max_x, max_z)) = bounds.unwrap_or(((x, z), (x, z)));
+
+                Some(((min_x.min(x), min_z.min(z)), (max_x.max(x), max_z.max(z))))
+            });
+
+        Ok(bounds.map(|((min_x, min_z), (max_x, max_z))| {
+            ((min_x * 32, min_z * 32), (max_x * 32 + 31, max_z * 32 + 31))
+        }))
     }
 
     /// Scans all region files for potential errors
     pub fn scan_files(&self, options: ScanOptions) -> io::Result<ScanStatistics> {
-        let paths = self.region_file_paths();
-        let bar = ProgressBar::new(paths.len() as u64);
+        let start = Instant::now();
+        let mut statistic = self
+            .scan_files_detailed(options)?
+            .into_iter()
+            .fold(ScanStatistics::new(), |a, (_, b)| a.add(b));
+        statistic.elapsed_ms = start.elapsed().as_millis() as u64;
+
+        Ok(statistic)
+    }
+
+    /// Scans a single region file that doesn't need to be part of a world folder.
+    /// The file is copied directly into the backup directory before it's modified
+    pub fn scan_single_file(path: &PathBuf, options: ScanOptions) -> io::Result<ScanStatistics> {
+        let start = Instant::now();
+        let folder = Self::new(path.parent().map(Path::to_path_buf).unwrap_or_default());
+        if let (Some(backup_path), true) = (&options.backup, options.writes_files()) {
+            Self::prepare_backup(backup_path)?;
+        }
+        if options.atomic && !options.dry_run {
+            remove_temp_file(path)?;
+        }
+        let target_path = folder.output_file(path, &options)?;
         let options = Arc::new(options);
+        let mut statistic = folder.scan_file(&target_path, &options)?;
+        statistic.elapsed_ms = start.elapsed().as_millis() as u64;
+        if let Some(callback) = &options.on_file_scanned {
+            callback.call(path, &statistic);
+        }
+
+        Ok(statistic)
+    }
+
+    /// Scans all region files for potential errors and returns the statistics of each file.
+    /// Files that can't be scanned are returned with statistics counting them as skipped
+    pub fn scan_files_detailed(
+        &self,
+        mut options: ScanOptions,
+    ) -> io::Result<Vec<(PathBuf, ScanStatistics)>> {
+        let start = Instant::now();
+        self.fill_world_info(&mut options);
+        let paths = self.region_file_paths()?;
+        if paths.is_empty() {
+            log::warn!("No region files found in {:?}", self.path);
+        }
+        if let (Some(backup_path), true) = (&options.backup, options.writes_files()) {
+            Self::prepare_backup(backup_path)?;
+        }
+        if options.atomic && !options.dry_run {
+            for path in &paths {
+                remove_temp_file(path)?;
+            }
+        }
+        // the size is taken before scanning since fixing can shrink the files
+        let total_size: u64 = paths
+            .iter()
+            .filter_map(|path| fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        let options = Arc::new(options);
+        // the bar would only produce noise in logs or files
+        let shows_bar =
+            options.progress && log::max_level() < LevelFilter::Debug && io::stderr().is_terminal();
+        // the bar counts chunks since the number of chunks per file varies a lot.
+        // Counting them only reads the headers of the files
+        let chunk_counts: HashMap<PathBuf, u64> = if shows_bar {
+            self.count_chunks_per_file()?
+                .into_iter()
+                .map(|(path, count)| (path, count as u64))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        let bar = ProgressBar::new(chunk_counts.values().sum());
         bar.set_style(
             ProgressStyle::default_bar().template("\r[{eta_precise}] {wide_bar} {pos}/{len} "),
         );
-        if log::max_level() == LevelFilter::Debug {
+        if !shows_bar {
             bar.set_draw_target(ProgressDrawTarget::hidden())
         }
         bar.enable_steady_tick(1000);
+        // a thread count of 0 lets rayon use all cores
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(options.threads)
+            .build()
+            .map_err(io::Error::other)?;
 
-        let statistic: ScanStatistics = paths
-            .par_iter()
-            .filter_map(|path| {
-                log::debug!("Opening and scanning region file {:?}", path);
-                let mut region_file = RegionFile::new(path)
-                    .map_err(|e| {
-                        log::error!("Failed to open region file {:?}: {}\n", path, e);
-                        if options.fix_delete {
-                            if let Err(e) = fs::remove_file(path) {
-                                return e;
-                            }
+        let files_done = AtomicU64::new(0);
+        let files_total = paths.len() as u64;
+
+        let results: io::Result<Vec<(PathBuf, ScanStatistics)>> = pool.install(|| {
+            paths
+                .par_iter()
+                .map(|path| {
+                    let result = self
+                        .output_file(path, &options)
+                        .and_then(|target_path| self.scan_file(&target_path, &options));
+                    bar.inc(chunk_counts.get(path).cloned().unwrap_or(0));
+                    if let Some(callback) = &options.on_progress {
+                        callback.call(ScanProgress {
+                            files_done: files_done.fetch_add(1, Ordering::Relaxed) + 1,
+                            files_total,
+                            path: path.clone(),
+                        });
+                    }
+
+                    let statistic = match result {
+                        Ok(statistic) => statistic,
+                        Err(e) if options.fail_fast => {
+                            return Err(io::Error::new(
+                                e.kind(),
+                                format!("Failed to scan region file {:?}: {}", path, e),
+                            ));
+                        }
+                        Err(e) => {
+                            log::warn!("Skipping region file {:?}: {}", path, e);
+                            let mut statistic = ScanStatistics::new();
+                            statistic.skipped_files = 1;
+                            statistic.failed_files.push(path.clone());
+
+                            statistic
                         }
+                    };
+                    if let Some(callback) = &options.on_file_scanned {
+                        callback.call(path, &statistic);
+                    }
+
+                    Ok((path.clone(), statistic))
+                })
+                .collect()
+        });
+
+        bar.finish_and_clear();
+        let results = results?;
+        let elapsed = start.elapsed();
+        let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+        let total_chunks: u64 = results.iter().map(|(_, s)| s.total_chunks).sum();
+        log::info!(
+            "Scanned {} chunks in {:.2}s ({:.0} chunks/s, {:.2} MiB/s)",
+            total_chunks,
+            elapsed.as_secs_f64(),
+            total_chunks as f64 / seconds,
+            total_size as f64 / 1024.0 / 1024.0 / seconds
+        );
+
+        Ok(results)
+    }
+
+    /// Watches the region folders and verifies each region file whose modification time
+    /// changed once it wasn't written to for the delay. The result of each scan is passed
+    /// to the callback. Only returns if watching the folders fails
+    pub fn watch<F: FnMut(&Path, io::Result<ScanStatistics>)>(
+        &self,
+        mut options: ScanOptions,
+        delay: Duration,
+        mut on_scan: F,
+    ) -> io::Result<()> {
+        // the game keeps writing to the files while they're watched
+        if options.modifies_files() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Watched region files can't be modified",
+            ));
+        }
+        self.fill_world_info(&mut options);
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::watcher(sender, delay).map_err(io::Error::other)?;
+        for (_, _, path) in self.region_folders() {
+            log::info!("Watching {:?}", path);
+            watcher
+                .watch(&path, RecursiveMode::NonRecursive)
+                .map_err(io::Error::other)?;
+        }
+        let mut modified_times: HashMap<PathBuf, SystemTime> = self
+            .region_file_paths()?
+            .into_iter()
+            .filter_map(|path| modified_time(&path).map(|time| (path, time)))
+            .collect();
+
+        for event in receiver {
+            let path = match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Rename(_, path) => path,
+                DebouncedEvent::Error(e, _) => return Err(io::Error::other(e)),
+                _ => continue,
+            };
+            let selected = parse_region_coords(&path)
+                .map(|(x, z)| self.matches_regions(x, z))
+                .unwrap_or(false);
+            let modified = match modified_time(&path) {
+                Some(modified) if selected => modified,
+                _ => continue,
+            };
+            // the game also touches files without changing them
+            if modified_times.insert(path.clone(), modified) == Some(modified) {
+                continue;
+            }
+            log::debug!("Verifying changed region file {:?}", path);
+            on_scan(&path, Self::scan_single_file(&path, options.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Recompresses all chunks of the world with the compression level of the options
+    /// and closes the gaps between the chunks afterwards. Returns the number of bytes
+    /// the chunks shrunk by and the statistics of defragmenting the files
+    pub fn recompress_files(&self, options: ScanOptions) -> io::Result<(i64, ScanStatistics)> {
+        let paths = self.region_file_paths()?;
+        if let Some(backup_path) = &options.backup {
+            Self::prepare_backup(backup_path)?;
+        }
+        let options = Arc::new(options.defrag(true).skip_nbt(true));
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(options.threads)
+            .build()
+            .map_err(io::Error::other)?;
+
+        let result = pool.install(|| {
+            paths
+                .par_iter()
+                .map(|path| {
+                    self.backup_file(path, &options)
+                        .and_then(|_| Self::recompress_file(path, &options))
+                        .unwrap_or_else(|e| {
+                            log::warn!("Skipping region file {:?}: {}", path, e);
+                            let mut statistic = ScanStatistics::new();
+                            statistic.skipped_files = 1;
+                            statistic.failed_files.push(path.clone());
+
+                            (0, statistic)
+                        })
+                })
+                .reduce(
+                    || (0, ScanStatistics::new()),
+                    |(saved_a, a), (saved_b, b)| (saved_a + saved_b, a.add(b)),
+                )
+        });
+
+        Ok(result)
+    }
+
+    /// Recompresses and defragments a single region file
+    fn recompress_file(
+        path: &PathBuf,
+        options: &Arc<ScanOptions>,
+    ) -> io::Result<(i64, ScanStatistics)> {
+        log::debug!("Recompressing region file {:?}", path);
+        let mut region_file = RegionFile::new(path)?;
+        let saved = region_file.recompress(Compression::new(options.compression_level))?;
+        let mut statistic = region_file.scan_chunks(options)?;
+        statistic.total_files = 1;
+
+        Ok((saved, statistic))
+    }
 
-                        e
-                    })
-                    .ok()?;
+    /// Scans a single region file and fixes it according to the options.
+    /// The file is backed up right before it's modified or removed
+    fn scan_file(&self, path: &PathBuf, options: &Arc<ScanOptions>) -> io::Result<ScanStatistics> {
+        if is_oversized(path, options.max_file_size) {
+            let mut statistic = ScanStatistics::new();
+            statistic.skipped_files = 1;
+            statistic.skipped_oversize = 1;
 
-                let result = region_file.scan_chunks(&options).ok()?;
-                if options.fix && result.shrunk_size > 0 {
-                    let f = OpenOptions::new().read(true).write(true).open(path).ok()?;
-                    f.set_len(result.shrunk_size).ok()?;
+            return Ok(statistic);
+        }
+        if let (Some(area), Some((region_x, region_z))) = (&options.trim, parse_region_coords(path))
+        {
+            if !area.intersects_region(region_x, region_z) {
+                return self.remove_region_file(path, options);
+            }
+        }
+        if let Some(size) = truncated_header_size(path) {
+            return self.remove_truncated_file(path, size, options);
+        }
+        log::debug!("Opening and scanning region file {:?}", path);
+        let modified = modified_time(path).filter(|_| options.preserve_mtime);
+        let backup = self.backup_path(path, options);
+        let (mut result, chunk_count) = if options.atomic && options.writes_files() {
+            Self::modify_atomically(path, |region_file| {
+                region_file.set_backup(backup.clone());
+                let result = region_file.scan_chunks(options)?;
+                let chunk_count = region_file.count_chunks();
+                if chunk_count == 0 && options.fix_delete {
+                    region_file.back_up()?;
+                }
+
+                Ok((result, chunk_count))
+            })?
+        } else {
+            let region_file = retry_io(path, options.io_retries, || {
+                if options.dry_run && options.modifies_files() {
+                    RegionFile::open_detached(path)
+                } else if options.modifies_files() {
+                    RegionFile::new(path)
+                } else if options.in_memory {
+                    RegionFile::open_in_memory(path)
+                } else {
+                    RegionFile::open_read_only(path)
                 }
-                bar.inc(1);
-                log::debug!("Statistics for {:?}:\n{}", path, result);
+            });
+            let mut region_file = match region_file {
+                Ok(region_file) => region_file,
+                Err(e) => {
+                    if options.fix_delete && is_corrupted_file(&e) {
+                        if options.dry_run {
+                            log::info!("Dry run: would delete {:?} that failed to open", path);
+                        } else {
+                            if let Some(backup) = &backup {
+                                back_up_file(path, backup)?;
+                            }
+                            log::debug!("Deleting region file {:?} that failed to open", path);
+                            fs::remove_file(path)?;
+                        }
+                    }
+                    return Err(e);
+                }
+            };
+            region_file.set_backup(backup);
+            let result = region_file.scan_chunks(options)?;
+            let chunk_count = region_file.count_chunks();
+            if chunk_count == 0 && options.fix_delete && !options.dry_run {
+                region_file.back_up()?;
+            }
 
-                Some(result)
+            // the file is closed at the end of the block before it can be removed
+            (result, chunk_count)
+        };
+        result.total_files = 1;
+        if chunk_count == 0 {
+            result.empty_regions = 1;
+            if options.fix_delete {
+                log::debug!("Deleting empty region file {:?}", path);
+                if !options.dry_run {
+                    fs::remove_file(path)?;
+                }
+                result.empty_regions_removed = 1;
+                result.final_size = 0;
+                result.add_repairs(path, vec![RepairAction::RemovedFile]);
+            }
+        }
+        if options.writes_files() {
+            // measured on disk since the file may have been truncated or removed
+            result.final_size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        }
+        if let (Some(modified), true) = (modified, options.writes_files() && path.exists()) {
+            log::debug!("Restoring the modification time of {:?}", path);
+            filetime::set_file_mtime(path, FileTime::from_system_time(modified))?;
+        }
+        if options.dry_run {
+            for (path, actions) in &result.repairs {
+                for action in actions {
+                    log::info!("Dry run: {:?}: {}", path, action);
+                }
+            }
+        }
+        log::debug!("Statistics for {:?}:\n{}", path, result);
+
+        Ok(result)
+    }
+
+    /// Modifies a temporary copy of the region file and replaces the original with it
+    /// once the modification succeeded. The original stays intact if it fails
+    fn modify_atomically<T, F: FnOnce(&mut RegionFile) -> io::Result<T>>(
+        path: &PathBuf,
+        modify: F,
+    ) -> io::Result<T> {
+        let temp_path = temp_file_path(path);
+        fs::copy(path, &temp_path)?;
+        let result = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&temp_path)
+            .and_then(|file| {
+                // the original path keeps the coordinates and external chunks of the region
+                let mut region_file = RegionFile::from_storage(path, file)?;
+                let result = modify(&mut region_file)?;
+                region_file.close()?;
+
+                Ok(result)
             })
-            .reduce(|| ScanStatistics::new(), |a, b| a.add(b));
+            .and_then(|result| {
+                fs::File::open(&temp_path)?.sync_all()?;
+                replace_file(&temp_path, path)?;
 
-        bar.finish_and_clear();
+                Ok(result)
+            });
+        if result.is_err() && temp_path.exists() {
+            log::debug!("Removing the temporary copy {:?}", temp_path);
+            fs::remove_file(&temp_path)?;
+        }
+
+        result
+    }
+
+    /// Reports a region file too small for its header and removes it if fix_delete is set
+    fn remove_truncated_file(
+        &self,
+        path: &Path,
+        size: u64,
+        options: &ScanOptions,
+    ) -> io::Result<ScanStatistics> {
+        log::warn!(
+            "Region file {:?} of {} bytes is too small to contain its header",
+            path,
+            size
+        );
+        let mut statistic = ScanStatistics::new();
+        statistic.total_files = 1;
+        statistic.truncated_header = 1;
+        statistic.total_size = size;
+        statistic.final_size = size;
+        if options.fix_delete {
+            if options.dry_run {
+                log::info!("Dry run: would delete {:?} that is too small", path);
+            } else {
+                if let Some(backup) = self.backup_path(path, options) {
+                    back_up_file(path, &backup)?;
+                }
+                log::debug!("Deleting region file {:?} that is too small", path);
+                fs::remove_file(path)?;
+            }
+            statistic.truncated_header_fixed = 1;
+            statistic.final_size = 0;
+            statistic.add_repairs(path, vec![RepairAction::RemovedFile]);
+        }
 
         Ok(statistic)
     }
 
+    /// Deletes the region file and its external chunk files
+    /// and returns the statistics of the removed chunks
+    fn remove_region_file(
+        &self,
+        path: &PathBuf,
+        options: &ScanOptions,
+    ) -> io::Result<ScanStatistics> {
+        log::debug!(
+            "Deleting region file {:?} outside of the trimmed area",
+            path
+        );
+        let region_file = RegionFile::open_read_only(path)?;
+        let mut statistic = ScanStatistics::new();
+        statistic.total_files = 1;
+        statistic.total_chunks = region_file.count_chunks() as u64;
+        statistic.pruned_chunks = statistic.total_chunks;
+        statistic.total_size = region_file.original_size();
+        statistic.pruned_space = fs::metadata(path)?.len();
+        drop(region_file);
+
+        for file in Self::external_chunk_files(path)? {
+            statistic.pruned_space += fs::metadata(&file)?.len();
+            if !options.dry_run {
+                if let Some(backup) = self.backup_path(&file, options) {
+                    back_up_file(&file, &backup)?;
+                }
+                fs::remove_file(file)?;
+            }
+        }
+        if !options.dry_run {
+            if let Some(backup) = self.backup_path(path, options) {
+                back_up_file(path, &backup)?;
+            }
+            fs::remove_file(path)?;
+        }
+        statistic.add_repairs(path, vec![RepairAction::RemovedFile]);
+
+        Ok(statistic)
+    }
+
+    /// Returns the external .mcc files storing chunks of the region file
+    fn external_chunk_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+
+        if let (Some((region_x, region_z)), Some(folder)) =
+            (parse_region_coords(path), path.parent())
+        {
+            for entry in fs::read_dir(folder)? {
+                let entry_path = entry?.path();
+                if let Some((x, z)) = external_chunk_coords(&entry_path) {
+                    if x.div_euclid(32) == region_x && z.div_euclid(32) == region_z {
+                        files.push(entry_path);
+                    }
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Creates the backup directory and makes sure that it's writable
+    /// before any region file is modified
+    fn prepare_backup(backup_path: &Path) -> io::Result<()> {
+        let map_err = |e: io::Error| {
+            io::Error::new(
+                e.kind(),
+                format!("Backup directory {:?} is not writable: {}", backup_path, e),
+            )
+        };
+        fs::create_dir_all(backup_path).map_err(map_err)?;
+        let probe_path = backup_path.join(".write-test");
+        fs::write(&probe_path, []).map_err(map_err)?;
+        fs::remove_file(&probe_path).map_err(map_err)?;
+
+        Ok(())
+    }
+
+    /// Copies the region file and its external chunk files to their backup paths
+    /// if the file is going to be modified
+    fn backup_file(&self, path: &Path, options: &ScanOptions) -> io::Result<()> {
+        let mut files = vec![path.to_path_buf()];
+        files.append(&mut Self::external_chunk_files(path)?);
+
+        for file in files {
+            if let Some(backup) = self.backup_path(&file, options) {
+                back_up_file(&file, &backup)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the path the file is copied to before it's modified. Files are either
+    /// backed up into the backup directory keeping their path relative to the world
+    /// or into a `.bak` file next to them. Returns `None` if nothing is backed up
+    fn backup_path(&self, file: &Path, options: &ScanOptions) -> Option<PathBuf> {
+        // the original files aren't modified if the fixed files are written elsewhere
+        if !options.writes_files() || options.output_dir.is_some() {
+            return None;
+        }
+        match &options.backup {
+            Some(backup_dir) => {
+                Some(backup_dir.join(file.strip_prefix(&self.path).unwrap_or(file)))
+            }
+            None if options.backup_in_place => {
+                let mut backup = file.as_os_str().to_owned();
+                backup.push(BACKUP_SUFFIX);
+
+                Some(PathBuf::from(backup))
+            }
+            None => None,
+        }
+    }
+
+    /// Copies the region file into the output directory if the files are fixed there
+    /// instead of in place. Returns the path of the file that is going to be modified
+    fn output_file(&self, path: &PathBuf, options: &ScanOptions) -> io::Result<PathBuf> {
+        match (&options.output_dir, options.writes_files()) {
+            (Some(output_dir), true) => {
+                log::debug!(
+                    "Copying {:?} to the output directory {:?}",
+                    path,
+                    output_dir
+                );
+                self.copy_region_file(path, output_dir)
+            }
+            _ => Ok(path.clone()),
+        }
+    }
+
+    /// Copies the region file and its external chunk files into the directory keeping their
+    /// path relative to the world folder. Returns the path of the copied region file
+    fn copy_region_file(&self, path: &Path, dir: &Path) -> io::Result<PathBuf> {
+        let mut files = vec![path.to_path_buf()];
+        files.append(&mut Self::external_chunk_files(path)?);
+        let mut target_paths = Vec::new();
+
+        for file in files {
+            let relative_path = file.strip_prefix(&self.path).unwrap_or(&file);
+            let target_path = dir.join(relative_path);
+            if let Some(parent) = target_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&file, &target_path).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Failed to copy {:?} to {:?}: {}", file, target_path, e),
+                )
+            })?;
+            target_paths.push(target_path);
+        }
+
+        // the region file is always the first of the copied files
+        Ok(target_paths.swap_remove(0))
+    }
+
+    /// Returns the dimensions of the world with the path of their region folder. Besides the
+    /// vanilla dimensions every folder below `dimensions` containing a region folder is
+    /// returned as a custom dimension. The overworld is always returned first
+    pub fn discover_region_dirs(&self) -> io::Result<Vec<(Dimension, PathBuf)>> {
+        let region_dir = RegionFolder::Region.dir_name();
+        let mut dimensions: Vec<(Dimension, PathBuf)> = Dimension::all()
+            .into_iter()
+            .map(|dimension| {
+                let path = dimension.path(&self.path).join(region_dir);
+                (dimension, path)
+            })
+            .filter(|(dimension, path)| *dimension == Dimension::Overworld || path.is_dir())
+            .collect();
+        let vanilla_count = dimensions.len();
+        let custom_path = self.path.join(CUSTOM_DIMENSIONS_DIR);
+        let mut pending = vec![custom_path.clone()];
+
+        while let Some(path) = pending.pop() {
+            if !path.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&path)? {
+                let entry_path = entry?.path();
+                if !entry_path.is_dir() {
+                    continue;
+                }
+                if entry_path.file_name().and_then(|n| n.to_str()) == Some(region_dir) {
+                    let relative = path.strip_prefix(&custom_path).unwrap_or(&path);
+                    let mut components = relative.iter().filter_map(|c| c.to_str());
+                    let namespace = components.next();
+                    let name = components.collect::<Vec<&str>>().join("/");
+                    match namespace {
+                        Some(namespace) if !name.is_empty() => dimensions.push((
+                            Dimension::Custom(format!("{}:{}", namespace, name)),
+                            entry_path,
+                        )),
+                        _ => log::debug!("Skipping {:?} outside of a dimension", entry_path),
+                    }
+                } else {
+                    pending.push(entry_path);
+                }
+            }
+        }
+        dimensions[vanilla_count..].sort_by(|(a, _), (b, _)| a.name().cmp(b.name()));
+
+        Ok(dimensions)
+    }
+
+    /// Returns the existing region folders of the selected dimensions and folder kinds
+    pub fn region_folders(&self) -> Vec<(Dimension, RegionFolder, PathBuf)> {
+        let mut region_folders = Vec::new();
+
+        for dimension in &self.dimensions {
+            let dimension_path = dimension.path(&self.path);
+            if *dimension != Dimension::Overworld && !dimension_path.exists() {
+                log::debug!("Skipping missing dimension {:?}", dimension_path);
+                continue;
+            }
+            for folder in &self.folders {
+                let region_file_path = dimension_path.join(PathBuf::from(folder.dir_name()));
+                // worlds created before 1.17 don't have entities and poi folders
+                // and other dimensions only have a region folder once they were visited
+                let required =
+                    *folder == RegionFolder::Region && *dimension == Dimension::Overworld;
+                if !required && !region_file_path.exists() {
+                    log::debug!("Skipping missing folder {:?}", region_file_path);
+                    continue;
+                }
+                region_folders.push((dimension.clone(), *folder, region_file_path));
+            }
+        }
+
+        region_folders
+    }
+
+    /// Returns the region file paths for the world folder by their path relative to it
+    fn region_file_names(&self) -> io::Result<HashMap<PathBuf, PathBuf>> {
+        let names = self
+            .region_file_paths()?
+            .into_iter()
+            .map(|path| {
+                let name = path.strip_prefix(&self.path).unwrap_or(&path).to_path_buf();
+                (name, path)
+            })
+            .collect();
+
+        Ok(names)
+    }
+
+    /// Returns if the region at the coordinates is selected by the region patterns
+    /// and inside the bounds
+    fn matches_regions(&self, x: i32, z: i32) -> bool {
+        (self.regions.is_empty() || self.regions.iter().any(|r| r.matches(x, z)))
+            && self.bounds.map(|b| b.contains(x, z)).unwrap_or(true)
+    }
+
     /// Returns a list of region file paths for the world folder
-    fn region_file_paths(&self) -> Vec<PathBuf> {
-        let region_file_path = self.path.join(PathBuf::from("region"));
+    fn region_file_paths(&self) -> io::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+
+        for (dimension, folder, path) in self.region_folders() {
+            let entries = fs::read_dir(&path).map_err(|e| {
+                let message = if e.kind() == io::ErrorKind::NotFound {
+                    format!(
+                        "No {}/ directory found at {:?}",
+                        folder.dir_name(),
+                        dimension.path(&self.path)
+                    )
+                } else {
+                    format!("Failed to read region folder {:?}: {}", path, e)
+                };
+
+                io::Error::new(e.kind(), message)
+            })?;
+            let mut folder_paths = Vec::new();
+            for entry in entries {
+                let entry_path = entry?.path();
+                let (x, z) = match parse_region_coords(&entry_path) {
+                    Some(coords) if entry_path.is_file() => coords,
+                    None if has_region_extension(&entry_path) => {
+                        log::warn!(
+                            "Skipping {:?} since its name doesn't match r.<x>.<z>",
+                            entry_path
+                        );
+                        continue;
+                    }
+                    _ => {
+                        log::debug!("Skipping {:?} since it isn't a region file", entry_path);
+                        continue;
+                    }
+                };
+                if self.matches_regions(x, z) {
+                    folder_paths.push(entry_path);
+                }
+            }
+            // the game keeps the McRegion files when it converts a world to anvil
+            if folder_paths
+                .iter()
+                .any(|p| RegionFormat::from_path(p) == RegionFormat::Anvil)
+            {
+                folder_paths.retain(|p| RegionFormat::from_path(p) == RegionFormat::Anvil);
+            }
+            paths.append(&mut folder_paths);
+        }
+
+        Ok(paths)
+    }
+}
+
+/// Returns if the path has the extension of an anvil or McRegion file
+fn has_region_extension(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some(ANVIL_EXTENSION) | Some(MCREGION_EXTENSION)
+    )
+}
+
+/// Returns the path of the temporary copy of a region file that is fixed atomically
+fn temp_file_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(TEMP_FILE_SUFFIX);
+
+    path.with_file_name(file_name)
+}
+
+/// Removes the temporary copy of the region file left behind by an interrupted fix
+fn remove_temp_file(path: &Path) -> io::Result<()> {
+    let temp_path = temp_file_path(path);
+    if temp_path.exists() {
+        log::info!("Removing stray temporary file {:?}", temp_path);
+        fs::remove_file(temp_path)?;
+    }
+
+    Ok(())
+}
+
+/// Moves the file to the target path replacing it. Files on different file systems
+/// can't be renamed and are copied instead which isn't atomic
+fn replace_file(path: &Path, target: &Path) -> io::Result<()> {
+    match fs::rename(path, target) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            log::warn!(
+                "Copying {:?} to {:?} since it can't be renamed across file systems",
+                path,
+                target
+            );
+            fs::copy(path, target)?;
+            fs::remove_file(path)
+        }
+        result => result,
+    }
+}
+
+/// Returns the modification time of the file if it can be read
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Returns if the error of opening a region file was caused by its content. Other errors
+/// like missing permissions or an unreachable network mount don't justify deleting the file
+fn is_corrupted_file(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+    )
+}
+
+/// Returns the size of the file if it's too small to contain the locations and timestamps.
+/// Files whose size can't be read are left to fail when they're opened
+fn truncated_header_size(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .ok()
+        .map(|metadata| metadata.len())
+        .filter(|size| *size < 2 * BLOCK_SIZE as u64)
+}
 
-        fs::read_dir(region_file_path)
+/// Returns if the file is larger than the maximum size and logs a warning if it is.
+/// Files whose size can't be read are left to fail when they're opened
+fn is_oversized(path: &Path, max_file_size: u64) -> bool {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.len() > max_file_size => {
+            log::warn!(
+                "Skipping region file {:?} of {} bytes that exceeds the maximum of {} bytes",
+                path,
+                metadata.len(),
+                max_file_size
+            );
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_too_small_for_the_header_are_only_removed_with_delete() {
+        let dir = std::env::temp_dir().join(format!(
+            "minecraft-regions-tool-{}-truncated-header",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("r.0.0.mca");
+        fs::write(&path, [0u8; 4097]).unwrap();
+        let world = WorldFolder::new(dir.clone());
+
+        let statistic = world
+            .scan_file(&path, &Arc::new(ScanOptions::new().fix(true)))
+            .unwrap();
+        assert_eq!(statistic.truncated_header, 1);
+        assert_eq!(statistic.truncated_header_fixed, 0);
+        assert!(path.exists());
+
+        let options = ScanOptions::new().fix(true).fix_delete(true);
+        let statistic = world.scan_file(&path, &Arc::new(options)).unwrap();
+        assert_eq!(statistic.truncated_header, 1);
+        assert_eq!(statistic.truncated_header_fixed, 1);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn only_files_smaller_than_the_header_are_truncated() {
+        let dir = std::env::temp_dir().join(format!(
+            "minecraft-regions-tool-{}-header-size",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("r.0.0.mca");
+
+        for size in [0, BLOCK_SIZE, 2 * BLOCK_SIZE - 1] {
+            fs::write(&path, vec![0u8; size]).unwrap();
+            assert_eq!(truncated_header_size(&path), Some(size as u64));
+        }
+        fs::write(&path, vec![0u8; 2 * BLOCK_SIZE]).unwrap();
+        assert_eq!(truncated_header_size(&path), None);
+    }
+
+    #[test]
+    fn chunks_are_counted_per_file_like_a_serial_count() {
+        let dir = std::env::temp_dir().join(format!(
+            "minecraft-regions-tool-{}-count-per-file",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("region")).unwrap();
+        for (name, chunks) in [("r.0.0.mca", 0), ("r.1.0.mca", 3), ("r.-2.5.mca", 40)] {
+            let mut data = vec![0u8; 3 * BLOCK_SIZE];
+            for index in 0..chunks {
+                // every chunk points at the same sector which is enough to be counted
+                data[index * 4..index * 4 + 4].copy_from_slice(&[0, 0, 2, 1]);
+            }
+            fs::write(dir.join("region").join(name), data).unwrap();
+        }
+        // too small to contain the header
+        fs::write(dir.join("region").join("r.3.3.mca"), [0u8; 100]).unwrap();
+        let world = WorldFolder::new(dir);
+
+        let mut serial: Vec<(PathBuf, usize)> = world
+            .region_file_paths()
             .unwrap()
-            .filter_map(|e| e.ok().map(|e| e.path()))
-            .collect()
+            .into_iter()
+            .filter_map(|path| {
+                let count = RegionFile::open_read_only(&path).ok()?.count_chunks();
+                Some((path, count))
+            })
+            .collect();
+        let mut parallel = world.count_chunks_per_file().unwrap();
+        serial.sort();
+        parallel.sort();
+
+        assert_eq!(parallel, serial);
+        assert_eq!(parallel.len(), 3);
+        assert_eq!(world.count_chunks().unwrap(), 43);
     }
 }
@@ -0,0 +1,119 @@
+use crate::constants::tags::{
+    TAG_DISABLED_DATAPACKS, TAG_ENABLED_DATAPACKS, TAG_LEVEL_NAME, TAG_SPAWN_X, TAG_SPAWN_Y,
+    TAG_SPAWN_Z, TAG_VERSION_NAME, TAG_WORLD_DATA_VERSION, TAG_WORLD_DIMENSIONS, TAG_WORLD_TIME,
+};
+use crate::nbt::{get_path, NBTReader, NBTValue};
+use flate2::read::GzDecoder;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io::{self, BufReader, ErrorKind};
+use std::path::Path;
+
+/// The name of the file storing the information about the world
+pub const LEVEL_DAT: &str = "level.dat";
+
+/// Information about the world read from its level.dat.
+/// Tags that don't exist in the version of the world are `None` or empty
+#[derive(Clone, Debug, Default)]
+pub struct WorldInfo {
+    pub name: Option<String>,
+    pub data_version: Option<i32>,
+    pub version_name: Option<String>,
+    pub spawn: Option<(i32, i32, i32)>,
+    /// The number of ticks the world has run
+    pub time: Option<i64>,
+    /// The dimensions generated by the world. Only stored since 1.16
+    pub dimensions: Vec<String>,
+    pub enabled_datapacks: Vec<String>,
+    pub disabled_datapacks: Vec<String>,
+}
+
+impl WorldInfo {
+    /// Reads the gzip compressed level.dat of the world folder
+    pub fn read(world_path: &Path) -> io::Result<Self> {
+        let path = world_path.join(LEVEL_DAT);
+        let file = fs::File::open(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to open {:?}: {}", path, e)))?;
+        let data = NBTReader::new(BufReader::new(GzDecoder::new(file)))
+            .parse()
+            .map_err(|e| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Failed to parse {:?}: {}", path, e),
+                )
+            })?;
+
+        Ok(Self::from_nbt(&data))
+    }
+
+    /// Takes the information from the parsed nbt data of a level.dat
+    pub fn from_nbt(data: &HashMap<String, NBTValue>) -> Self {
+        let int = |path| get_path(data, path).and_then(|v| v.as_int()).cloned();
+        let string = |path| get_path(data, path).and_then(|v| v.as_string()).cloned();
+        let strings = |path| -> Vec<String> {
+            get_path(data, path)
+                .and_then(|v| v.as_list())
+                .map(|list| list.iter().filter_map(|v| v.as_string()).cloned().collect())
+                .unwrap_or_default()
+        };
+        let mut dimensions: Vec<String> = get_path(data, TAG_WORLD_DIMENSIONS)
+            .and_then(|v| v.as_compound())
+            .map(|dimensions| dimensions.keys().cloned().collect())
+            .unwrap_or_default();
+        dimensions.sort();
+
+        Self {
+            name: string(TAG_LEVEL_NAME),
+            data_version: int(TAG_WORLD_DATA_VERSION),
+            version_name: string(TAG_VERSION_NAME),
+            spawn: match (int(TAG_SPAWN_X), int(TAG_SPAWN_Y), int(TAG_SPAWN_Z)) {
+                (Some(x), Some(y), Some(z)) => Some((x, y, z)),
+                _ => None,
+            },
+            time: get_path(data, TAG_WORLD_TIME)
+                .and_then(|v| v.as_long())
+                .cloned(),
+            dimensions,
+            enabled_datapacks: strings(TAG_ENABLED_DATAPACKS),
+            disabled_datapacks: strings(TAG_DISABLED_DATAPACKS),
+        }
+    }
+}
+
+impl Display for WorldInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let unknown = || String::from("unknown");
+
+        write!(
+            f,
+            "
+            Name: {}
+            Version: {} (data version {})
+            Spawn: {}
+            Dimensions: {}
+            Enabled datapacks: {}
+            Disabled datapacks: {}",
+            self.name.clone().unwrap_or_else(unknown),
+            self.version_name.clone().unwrap_or_else(unknown),
+            self.data_version
+                .map(|v| v.to_string())
+                .unwrap_or_else(unknown),
+            self.spawn
+                .map(|(x, y, z)| format!("{}, {}, {}", x, y, z))
+                .unwrap_or_else(unknown),
+            join_names(&self.dimensions, "unknown"),
+            join_names(&self.enabled_datapacks, "none"),
+            join_names(&self.disabled_datapacks, "none"),
+        )
+    }
+}
+
+/// Joins the names with commas or returns the fallback if there are none
+fn join_names(names: &[String], fallback: &str) -> String {
+    if names.is_empty() {
+        String::from(fallback)
+    } else {
+        names.join(", ")
+    }
+}